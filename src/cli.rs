@@ -1,15 +1,33 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 use crate::convert_0_1::convert_0_1;
+use crate::export::graph::{graph_cmd, GraphArgs};
+use crate::export::guide::{guide_cmd, GuideArgs};
+use crate::export::html::{html_cmd, HtmlArgs};
+use crate::export::ldtk::{ldtk_cmd, LdtkArgs};
+use crate::export::overview::{export_overview, ExportArgs};
+use crate::export::rooms::{rooms_cmd, RoomsArgs};
+use crate::export::tiled::{tiled_cmd, TiledArgs};
 use crate::gui::init::launch_gui;
+use crate::map::diff::{diff_cmd, DiffArgs};
+use crate::map::fsck::{check_cmd, CheckArgs};
+use crate::map::gc::{gc_cmd, GcArgs};
+use crate::map::merge::{merge_cmd, MergeArgs};
+use crate::map::resize::{resize_cmd, ResizeArgs};
+use crate::map::storage::{storage_cmd, StorageArgs};
 
 pub fn cli() {
-    let args = Args::parse();
+    let mut args = Args::parse();
 
     if args.convert_0_1 {
         convert_0_1(args);
+    } else if let Some(command) = args.command.take() {
+        if let Err(e) = command.run() {
+            eprintln!("Error: {e:?}");
+            std::process::exit(1);
+        }
     } else {
         launch_gui(args);
     }
@@ -17,7 +35,7 @@ pub fn cli() {
 
 /// mzd2
 #[derive(Parser, Debug)]
-#[command(version, about)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
 pub struct Args {
     /// Convert given mzd 0.1 map to current format. Does not launch GUI
     #[arg(long="convert-0.1")]
@@ -26,4 +44,58 @@ pub struct Args {
     /// Asset to open (map or tileset)
     #[arg()]
     pub load_paths: Vec<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Headless operations. These do not launch the GUI
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Render a Z level of a map into a single PNG
+    Export(ExportArgs),
+    /// Check maps and their resource files for corruption
+    Check(CheckArgs),
+    /// List, delete or quarantine room files no room of the map refers to anymore
+    Gc(GcArgs),
+    /// Convert how the room files of a map are named
+    Storage(StorageArgs),
+    /// Pad or crop every room of a map to another room size
+    Resize(ResizeArgs),
+    /// Three way merge of map files, usable as git merge driver
+    Merge(MergeArgs),
+    /// List the rooms added, removed, moved or changed between two versions of a map
+    Diff(DiffArgs),
+    /// Export the rooms, connections and warps of maps as graph (Graphviz DOT, GraphML or JSON)
+    Graph(GraphArgs),
+    /// Export a map as Tiled maps and worlds, or a tileset as Tiled tileset
+    Tiled(TiledArgs),
+    /// Export a map as LDtk project
+    Ldtk(LdtkArgs),
+    /// Export maps as static HTML viewer, which works opened from disk
+    Html(HtmlArgs),
+    /// Export a map as Markdown guide with images, describing every room
+    Guide(GuideArgs),
+    /// Export every room of a map, or of a Z level, as PNG files
+    Rooms(RoomsArgs),
+}
+
+impl Command {
+    pub fn run(self) -> anyhow::Result<()> {
+        match self {
+            Command::Export(args) => export_overview(args),
+            Command::Check(args) => check_cmd(args),
+            Command::Gc(args) => gc_cmd(args),
+            Command::Storage(args) => storage_cmd(args),
+            Command::Resize(args) => resize_cmd(args),
+            Command::Merge(args) => merge_cmd(args),
+            Command::Diff(args) => diff_cmd(args),
+            Command::Graph(args) => graph_cmd(args),
+            Command::Tiled(args) => tiled_cmd(args),
+            Command::Ldtk(args) => ldtk_cmd(args),
+            Command::Html(args) => html_cmd(args),
+            Command::Guide(args) => guide_cmd(args),
+            Command::Rooms(args) => rooms_cmd(args),
+        }
+    }
 }
@@ -9,9 +9,10 @@ use slotmap::{HopSlotMap, Key as _};
 use crate::cli::Args;
 use crate::gui::draw_state::DrawMode;
 use crate::gui::dsel_state::DSelMode;
-use crate::gui::map::{MapEditMode, MapState, RoomId, RoomMap};
+use crate::gui::map::conn_types::default_conn_types;
+use crate::gui::map::{MapEditMode, MapState, ResStorage, RoomId, RoomMap, SerLayout};
 use crate::gui::room::draw_image::DrawImage;
-use crate::gui::room::{Layer, Room};
+use crate::gui::room::{Layer, Room, CONN_NONE, CONN_OPEN};
 use crate::gui::sel_matrix::{SelEntry, SelMatrix, SelMatrixLayered};
 use crate::gui::tags::TagState;
 use crate::gui::util::ArrUtl;
@@ -73,7 +74,8 @@ pub fn convert_map(map_path: PathBuf, uuidmap: &mut UUIDMap) -> anyhow::Result<(
             locked: None,
             layers: old_room.visible_layers.into_iter().map(|v| Layer { vis: v as u8, label: Default::default() }).collect(),
             selected_layer: old_room.selected_layer,
-            dirconn: old_room.dirconn,
+            dirconn: old_room.dirconn.map(|a| a.map(|v| if v {CONN_OPEN} else {CONN_NONE} ) ),
+            title: String::new(),
             desc_text: old_room.desc_text,
             ctime: room_mtime,
             mtime: room_mtime,
@@ -140,6 +142,11 @@ pub fn convert_map(map_path: PathBuf, uuidmap: &mut UUIDMap) -> anyhow::Result<(
         quickroom_template: std::iter::repeat_with(|| None).take(4).collect(),
         set_dssel_merged: false,
         quick_shift_keep_gap: true,
+        res_storage: ResStorage::Uuid,
+        ser_layout: SerLayout::Compact,
+        conn_types: default_conn_types(),
+        import_profiles: vec![],
+        drop_profile: None,
     };
 
     uuidmap.insert(new_map_state.uuid, UUIDTarget::Map(new_map_id));
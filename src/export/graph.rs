@@ -0,0 +1,450 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::gui::map::conn_types::conn_type_name;
+use crate::gui::map::room_ops::{short_direction, try_side, OpAxis};
+use crate::gui::map::MapState;
+use crate::gui::room::{conn_exit, CONN_NONE};
+use crate::util::attached_to_path;
+
+use super::xml_escape;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum GraphFormat {
+    /// Graphviz
+    Dot,
+    Graphml,
+    Json,
+}
+
+impl GraphFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Dot => "dot",
+            Self::Graphml => "graphml",
+            Self::Json => "json",
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "dot" | "gv" => Some(Self::Dot),
+            "graphml" | "xml" => Some(Self::Graphml),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// The connection graph of maps, in the form written as JSON
+#[derive(Serialize)]
+pub struct GraphDoc {
+    pub maps: Vec<GraphMap>,
+    pub rooms: Vec<GraphRoom>,
+    pub edges: Vec<GraphEdgeOut>,
+    /// Rooms grouped by map and Z level, if clustered
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clusters: Option<Vec<GraphCluster>>,
+}
+
+#[derive(Serialize)]
+pub struct GraphMap {
+    pub uuid: Uuid,
+    pub title: String,
+}
+
+#[derive(Serialize)]
+pub struct GraphRoom {
+    pub uuid: Uuid,
+    pub map: Uuid,
+    pub coord: [u8;3],
+    pub title: String,
+    pub desc: String,
+    pub tags: Vec<GraphTag>,
+}
+
+#[derive(Serialize)]
+pub struct GraphTag {
+    pub uuid: Uuid,
+    pub text: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub item: String,
+}
+
+#[derive(Serialize)]
+pub struct GraphEdgeOut {
+    pub from: Uuid,
+    pub to: Uuid,
+    pub to_map: Uuid,
+    /// The destination isn't one of the exported rooms
+    pub external: bool,
+    #[serde(flatten)]
+    pub via: GraphVia,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GraphVia {
+    /// Between neighboring rooms, exported once per pair of sides
+    Conn {
+        /// Like "X+", from the from room
+        direction: String,
+        conn_type: String,
+        /// Only passable from the from room
+        oneway: bool,
+    },
+    Warp {
+        tag: Uuid,
+        text: String,
+    },
+}
+
+#[derive(Serialize)]
+pub struct GraphCluster {
+    pub map: Uuid,
+    pub z: u8,
+    pub rooms: Vec<Uuid>,
+}
+
+impl GraphDoc {
+    pub fn build<'a>(maps: impl IntoIterator<Item = &'a MapState>, cluster_z: bool) -> Self {
+        let maps = maps.into_iter().collect::<Vec<_>>();
+        let mut doc = GraphDoc {
+            maps: maps.iter().map(|m| GraphMap { uuid: m.uuid, title: m.title.clone() }).collect(),
+            rooms: vec![],
+            edges: vec![],
+            clusters: None,
+        };
+
+        for state in &maps {
+            let mut rooms = state.rooms.values().filter(|r| !r.transient ).collect::<Vec<_>>();
+            rooms.sort_by_key(|r| [r.coord[2],r.coord[1],r.coord[0]] );
+
+            let matrix = rooms.iter()
+                .map(|r| (r.coord,*r) )
+                .collect::<HashMap<_,_>>();
+
+            for room in rooms {
+                doc.rooms.push(GraphRoom {
+                    uuid: room.uuid,
+                    map: state.uuid,
+                    coord: room.coord,
+                    title: room.title.clone(),
+                    desc: room.desc_text.clone(),
+                    tags: room.tags.iter()
+                        .map(|(&uuid,t)| GraphTag { uuid, text: t.text.clone(), item: t.item.trim().to_owned() })
+                        .collect(),
+                });
+
+                for axis in [OpAxis::X, OpAxis::Y, OpAxis::Z] {
+                    let here = room.dirconn[axis.axis_idx()][1];
+                    if here == CONN_NONE {continue;}
+                    let Some(&side) = try_side(room.coord, axis, true, |c| matrix.get(&c) ).flatten() else {continue};
+                    let there = side.dirconn[axis.axis_idx()][0];
+                    if there == CONN_NONE {continue;}
+
+                    let (from,to,dir,v) = match (conn_exit(here),conn_exit(there)) {
+                        (true,_) => (room,side,true,here),
+                        (false,true) => (side,room,false,there),
+                        // blocked both ways
+                        (false,false) => continue,
+                    };
+                    doc.edges.push(GraphEdgeOut {
+                        from: from.uuid,
+                        to: to.uuid,
+                        to_map: state.uuid,
+                        external: false,
+                        via: GraphVia::Conn {
+                            direction: short_direction(axis, dir).to_owned(),
+                            conn_type: conn_type_name(&state.conn_types, v),
+                            oneway: !(conn_exit(here) && conn_exit(there)),
+                        },
+                    });
+                }
+            }
+        }
+
+        for state in &maps {
+            for room in state.rooms.values() {
+                if room.transient {continue;}
+                for (&tag_id,tag) in &room.tags {
+                    let Some(warp) = &tag.warp else {continue};
+                    doc.edges.push(GraphEdgeOut {
+                        from: room.uuid,
+                        to: warp.dest_room,
+                        to_map: warp.dest_map,
+                        external: false,
+                        via: GraphVia::Warp {
+                            tag: tag_id,
+                            text: tag.text.clone(),
+                        },
+                    });
+                }
+            }
+        }
+
+        let known = doc.rooms.iter().map(|r| (r.uuid,r.map) ).collect::<HashMap<_,_>>();
+        for edge in &mut doc.edges {
+            edge.external = known.get(&edge.to) != Some(&edge.to_map);
+        }
+
+        if cluster_z {
+            let mut clusters = BTreeMap::<(usize,u8),Vec<Uuid>>::new();
+            for room in &doc.rooms {
+                let map = doc.maps.iter().position(|m| m.uuid == room.map ).unwrap_or_default();
+                clusters.entry((map,room.coord[2])).or_default().push(room.uuid);
+            }
+            doc.clusters = Some(
+                clusters.into_iter()
+                    .map(|((map,z),rooms)| GraphCluster { map: doc.maps[map].uuid, z, rooms })
+                    .collect()
+            );
+        }
+
+        doc
+    }
+
+    fn map_title(&self, map: Uuid) -> Option<&str> {
+        self.maps.iter().find(|m| m.uuid == map ).map(|m| m.title.as_str() )
+    }
+
+    fn room_label(room: &GraphRoom) -> String {
+        let [x,y,z] = room.coord;
+        if room.title.is_empty() {
+            format!("X{x}Y{y}Z{z}")
+        } else {
+            format!("{}\nX{x}Y{y}Z{z}", room.title)
+        }
+    }
+
+    /// Warp destinations outside of the exported rooms, once each
+    fn external_rooms(&self) -> Vec<(Uuid,Uuid)> {
+        let mut seen = HashSet::new();
+        self.edges.iter()
+            .filter(|e| e.external && seen.insert(e.to) )
+            .map(|e| (e.to,e.to_map) )
+            .collect()
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "digraph mzd2 {{");
+        let _ = writeln!(out, "    node [shape=box];");
+
+        let room_line = |out: &mut String, room: &GraphRoom, indent: &str| {
+            let tags = room.tags.iter().map(|t| t.text.as_str() ).collect::<Vec<_>>();
+            let _ = writeln!(
+                out, "{indent}{} [label={}, tooltip={}, map={}, coord=\"{},{},{}\", tags={}];",
+                dot_str(&room.uuid.to_string()), dot_str(&Self::room_label(room)), dot_str(&room.desc),
+                dot_str(&room.map.to_string()), room.coord[0], room.coord[1], room.coord[2],
+                dot_str(&tags.join("\n")),
+            );
+        };
+
+        match &self.clusters {
+            Some(clusters) => {
+                let rooms = self.rooms.iter().map(|r| (r.uuid,r) ).collect::<HashMap<_,_>>();
+                for (i,cluster) in clusters.iter().enumerate() {
+                    let title = self.map_title(cluster.map).unwrap_or_default();
+                    let _ = writeln!(out, "    subgraph cluster_{i} {{");
+                    let _ = writeln!(out, "        label={};", dot_str(&format!("{title} Z{}", cluster.z)));
+                    for uuid in &cluster.rooms {
+                        room_line(&mut out, rooms[uuid], "        ");
+                    }
+                    let _ = writeln!(out, "    }}");
+                }
+            },
+            None => {
+                for room in &self.rooms {
+                    room_line(&mut out, room, "    ");
+                }
+            },
+        }
+
+        for (room,map) in self.external_rooms() {
+            let label = format!("{}\nroom {room}", self.map_title(map).map_or_else(|| format!("map {map}"), str::to_owned ));
+            let _ = writeln!(out, "    {} [label={}, map={}, style=dashed];", dot_str(&room.to_string()), dot_str(&label), dot_str(&map.to_string()));
+        }
+
+        for edge in &self.edges {
+            let from = dot_str(&edge.from.to_string());
+            let to = dot_str(&edge.to.to_string());
+            match &edge.via {
+                GraphVia::Conn { direction, conn_type, oneway } => {
+                    let dir = if *oneway {"forward"} else {"both"};
+                    let _ = writeln!(out, "    {from} -> {to} [label={}, dir={dir}, direction={}, conn_type={}];", dot_str(conn_type), dot_str(direction), dot_str(conn_type));
+                },
+                GraphVia::Warp { tag, text } => {
+                    let _ = writeln!(out, "    {from} -> {to} [label={}, style=dashed, tag={}];", dot_str(text), dot_str(&tag.to_string()));
+                },
+            }
+        }
+
+        let _ = writeln!(out, "}}");
+        out
+    }
+
+    pub fn to_graphml(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        let _ = writeln!(out, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#);
+        for (id,target,name,ty) in [
+            ("map","node","map","string"),
+            ("title","node","title","string"),
+            ("desc","node","desc","string"),
+            ("x","node","x","int"),
+            ("y","node","y","int"),
+            ("z","node","z","int"),
+            ("tags","node","tags","string"),
+            ("external","node","external","boolean"),
+            ("kind","edge","kind","string"),
+            ("direction","edge","direction","string"),
+            ("conn_type","edge","conn_type","string"),
+            ("oneway","edge","oneway","boolean"),
+            ("tag","edge","tag","string"),
+            ("text","edge","text","string"),
+        ] {
+            let _ = writeln!(out, r#"  <key id="{id}" for="{target}" attr.name="{name}" attr.type="{ty}"/>"#);
+        }
+        let _ = writeln!(out, r#"  <graph id="mzd2" edgedefault="directed">"#);
+
+        let room_node = |out: &mut String, room: &GraphRoom, indent: &str| {
+            let tags = room.tags.iter().map(|t| t.text.as_str() ).collect::<Vec<_>>();
+            let _ = writeln!(out, r#"{indent}<node id="{}">"#, room.uuid);
+            for (key,value) in [
+                ("map",room.map.to_string()),
+                ("title",room.title.clone()),
+                ("desc",room.desc.clone()),
+                ("x",room.coord[0].to_string()),
+                ("y",room.coord[1].to_string()),
+                ("z",room.coord[2].to_string()),
+                ("tags",tags.join("\n")),
+            ] {
+                let _ = writeln!(out, r#"{indent}  <data key="{key}">{}</data>"#, xml_escape(&value));
+            }
+            let _ = writeln!(out, "{indent}</node>");
+        };
+
+        match &self.clusters {
+            Some(clusters) => {
+                let rooms = self.rooms.iter().map(|r| (r.uuid,r) ).collect::<HashMap<_,_>>();
+                for (i,cluster) in clusters.iter().enumerate() {
+                    let title = self.map_title(cluster.map).unwrap_or_default();
+                    let _ = writeln!(out, r#"    <node id="cluster_{i}">"#);
+                    let _ = writeln!(out, r#"      <data key="map">{}</data>"#, cluster.map);
+                    let _ = writeln!(out, r#"      <data key="title">{}</data>"#, xml_escape(&format!("{title} Z{}", cluster.z)));
+                    let _ = writeln!(out, r#"      <data key="z">{}</data>"#, cluster.z);
+                    let _ = writeln!(out, r#"      <graph id="cluster_{i}:" edgedefault="directed">"#);
+                    for uuid in &cluster.rooms {
+                        room_node(&mut out, rooms[uuid], "        ");
+                    }
+                    let _ = writeln!(out, "      </graph>");
+                    let _ = writeln!(out, "    </node>");
+                }
+            },
+            None => {
+                for room in &self.rooms {
+                    room_node(&mut out, room, "    ");
+                }
+            },
+        }
+
+        for (room,map) in self.external_rooms() {
+            let _ = writeln!(out, r#"    <node id="{room}">"#);
+            let _ = writeln!(out, r#"      <data key="map">{map}</data>"#);
+            let _ = writeln!(out, r#"      <data key="external">true</data>"#);
+            let _ = writeln!(out, "    </node>");
+        }
+
+        for (i,edge) in self.edges.iter().enumerate() {
+            let _ = writeln!(out, r#"    <edge id="e{i}" source="{}" target="{}">"#, edge.from, edge.to);
+            let data = match &edge.via {
+                GraphVia::Conn { direction, conn_type, oneway } => vec![
+                    ("kind","conn".to_owned()),
+                    ("direction",direction.clone()),
+                    ("conn_type",conn_type.clone()),
+                    ("oneway",oneway.to_string()),
+                ],
+                GraphVia::Warp { tag, text } => vec![
+                    ("kind","warp".to_owned()),
+                    ("tag",tag.to_string()),
+                    ("text",text.clone()),
+                ],
+            };
+            for (key,value) in data {
+                let _ = writeln!(out, r#"      <data key="{key}">{}</data>"#, xml_escape(&value));
+            }
+            let _ = writeln!(out, "    </edge>");
+        }
+
+        let _ = writeln!(out, "  </graph>");
+        let _ = writeln!(out, "</graphml>");
+        out
+    }
+
+    pub fn render(&self, format: GraphFormat) -> anyhow::Result<String> {
+        Ok(match format {
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Graphml => self.to_graphml(),
+            GraphFormat::Json => serde_json::to_string_pretty(self)?,
+        })
+    }
+}
+
+fn dot_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => {},
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[derive(clap::Args, Debug)]
+pub struct GraphArgs {
+    /// Maps to export. Warps between them become edges between their rooms
+    #[arg(required = true)]
+    pub maps: Vec<PathBuf>,
+    /// Output file. Defaults to the first map path with the extension of the format attached
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// Output format. Defaults to the extension of the output, else dot
+    #[arg(short, long)]
+    pub format: Option<GraphFormat>,
+    /// Group the rooms of every map and Z level into a cluster
+    #[arg(long)]
+    pub cluster_z: bool,
+}
+
+pub fn graph_cmd(args: GraphArgs) -> anyhow::Result<()> {
+    let maps = args.maps.iter()
+        .map(|path|
+            MapState::read_file(path)
+                .with_context(|| format!("Reading {}", path.to_string_lossy()) )
+        )
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let format = args.format
+        .or_else(|| args.output.as_deref().and_then(GraphFormat::from_path) )
+        .unwrap_or(GraphFormat::Dot);
+    let output = args.output.unwrap_or_else(|| attached_to_path(&args.maps[0], format!(".{}", format.extension())) );
+
+    let doc = GraphDoc::build(&maps, args.cluster_z);
+    std::fs::write(&output, doc.render(format)?).context("Writing output file")?;
+
+    eprintln!("Exported {} rooms and {} edges to {}", doc.rooms.len(), doc.edges.len(), output.to_string_lossy());
+
+    Ok(())
+}
@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::gui::map::conn_types::conn_type_name;
+use crate::gui::map::room_ops::{describe_direction, try_side, OpAxis};
+use crate::gui::map::{Map, RoomId};
+use crate::gui::room::Room;
+use crate::map::graph::RoomGraph;
+use crate::util::attached_to_path;
+use crate::util::img::write_png;
+
+use super::overview::render_overview;
+use super::{flatten_layers, load_room_image, LayerSel};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum GuideOrder {
+    /// By Z level, then row, then column
+    Coords,
+    /// Breadth first over connections and warps from the start room, then the unreachable rooms by coords
+    Bfs,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum GuideSections {
+    /// A section with image per room
+    Room,
+    /// A section with overview image per Z level, with the rooms as subsections
+    Level,
+}
+
+fn room_name(room: &Room) -> String {
+    let [x,y,z] = room.coord;
+    if room.title.is_empty() {
+        format!("X{x} Y{y} Z{z}")
+    } else {
+        format!("X{x} Y{y} Z{z}: {}", room.title)
+    }
+}
+
+fn room_link(room: &Room) -> String {
+    format!("[{}](#room-{})", room_name(room), room.uuid)
+}
+
+/// The rooms in the order of the guide
+fn traversal(map: &Map, order: GuideOrder, start: Option<RoomId>) -> Vec<RoomId> {
+    let mut rooms = map.state.rooms.iter()
+        .filter(|(_,r)| !r.transient )
+        .map(|(id,r)| (id,r.coord) )
+        .collect::<Vec<_>>();
+    rooms.sort_by_key(|&(_,[x,y,z])| [z,y,x] );
+    let mut rooms = rooms.into_iter().map(|(id,_)| id ).collect::<Vec<_>>();
+
+    if order == GuideOrder::Bfs && let Some(start) = start {
+        let graph = RoomGraph::build([&map.state]);
+        let Some(start) = graph.node(&map.state.rooms[start].uuid) else {return rooms};
+        let ids = map.state.rooms.iter().map(|(id,r)| (r.uuid,id) ).collect::<HashMap<_,_>>();
+        let mut visited = graph.bfs_order(start).into_iter()
+            .map(|n| ids[&graph.nodes[n].room] )
+            .collect::<Vec<_>>();
+        rooms.retain(|id| !visited.contains(id) );
+        visited.extend(rooms);
+        rooms = visited;
+    }
+
+    rooms
+}
+
+fn write_room_text(out: &mut String, map: &Map, room_id: RoomId) {
+    let room = &map.state.rooms[room_id];
+
+    if !room.desc_text.trim().is_empty() {
+        let _ = writeln!(out, "{}\n", room.desc_text.trim());
+    }
+
+    if !room.tags.is_empty() {
+        let _ = writeln!(out, "**Tags**\n");
+        for tag in room.tags.values() {
+            let text = if tag.text.trim().is_empty() {"(no text)"} else {tag.text.trim()};
+            let mut line = text.replace('\n', " ");
+            if !tag.item.trim().is_empty() {
+                let _ = write!(line, " (item: {})", tag.item.trim());
+            }
+            if let Some(warp) = &tag.warp {
+                let dest = map.state.rooms.values()
+                    .find(|r| r.uuid == warp.dest_room && !r.transient )
+                    .filter(|_| warp.dest_map == map.state.uuid );
+                match dest {
+                    Some(dest) => {let _ = write!(line, " → warp to {}", room_link(dest));},
+                    None => {let _ = write!(line, " → warp to room {} of map {}", warp.dest_room, warp.dest_map);},
+                }
+            }
+            let _ = writeln!(out, "- {line}");
+        }
+        let _ = writeln!(out);
+    }
+
+    let mut conns = vec![];
+    for axis in [OpAxis::X, OpAxis::Y, OpAxis::Z] {
+        for dir in [false,true] {
+            let Some([exit,enter]) = map.get_room_conn_ways(room_id, axis, dir) else {continue};
+            let v = room.dirconn[axis.axis_idx()][dir as usize];
+            let neighbor = try_side(room.coord, axis, dir, |c| map.room_matrix.get(c).cloned() )
+                .flatten()
+                .and_then(|id| map.state.rooms.get(id) );
+            let Some(neighbor) = neighbor else {continue};
+            let ways = match (exit,enter) {
+                (true,true) => "",
+                (true,false) => ", one-way out",
+                (false,true) => ", one-way in",
+                (false,false) => ", blocked",
+            };
+            conns.push(format!(
+                "- {}: {}{ways} → {}",
+                describe_direction(axis, dir), conn_type_name(&map.state.conn_types, v), room_link(neighbor),
+            ));
+        }
+    }
+    if !conns.is_empty() {
+        let _ = writeln!(out, "**Connections**\n");
+        for line in conns {
+            let _ = writeln!(out, "{line}");
+        }
+        let _ = writeln!(out);
+    }
+}
+
+/// Write a Markdown document describing every room of the map, with the images into a folder next to it.
+///
+/// Returns the rooms and levels whose image failed.
+pub fn export_guide(map: &Map, dest: &Path, sections: GuideSections, order: GuideOrder, start: Option<RoomId>) -> anyhow::Result<Vec<String>> {
+    let image_dir_name = format!("{}_images", dest.file_stem().unwrap_or_default().to_string_lossy());
+    let image_dir = dest.with_file_name(&image_dir_name);
+    std::fs::create_dir_all(&image_dir).context("Creating image folder")?;
+
+    let rooms = traversal(map, order, start);
+    let mut failed = vec![];
+
+    let mut out = String::new();
+    let _ = writeln!(out, "# {}\n", map.state.title);
+    if order == GuideOrder::Bfs && let Some(start) = start {
+        let _ = writeln!(out, "Starting at {}.\n", room_link(&map.state.rooms[start]));
+    }
+
+    match sections {
+        GuideSections::Room => {
+            for &room_id in &rooms {
+                let room = &map.state.rooms[room_id];
+                let [x,y,z] = room.coord;
+                let _ = writeln!(out, "<a id=\"room-{}\"></a>\n", room.uuid);
+                let _ = writeln!(out, "## {}\n", room_name(room));
+
+                let file_name = format!("X{x}_Y{y}_Z{z}.png");
+                let image = load_room_image(room, &map.path, map.state.rooms_size)
+                    .and_then(|image| {
+                        let layers = LayerSel::Visible.layers_of(room, image.layers);
+                        let flat = flatten_layers(&image, &layers, map.state.rooms_size);
+                        let file = File::create(image_dir.join(&file_name))?;
+                        write_png(BufWriter::new(file), &flat)?;
+                        Ok(())
+                    });
+                match image {
+                    Ok(()) => {let _ = writeln!(out, "![{}]({image_dir_name}/{file_name})\n", room_name(room));},
+                    Err(e) => failed.push(format!("X{x}Y{y}Z{z}: {e}")),
+                }
+
+                write_room_text(&mut out, map, room_id);
+            }
+        },
+        GuideSections::Level => {
+            // the levels in the order their first room is visited
+            let mut levels = vec![];
+            for &room_id in &rooms {
+                let z = map.state.rooms[room_id].coord[2];
+                if !levels.contains(&z) {
+                    levels.push(z);
+                }
+            }
+
+            for z in levels {
+                let _ = writeln!(out, "## Z{z}\n");
+
+                let file_name = format!("Z{z}.png");
+                let image = render_overview(map, z, &LayerSel::Visible, 1, true, true)
+                    .and_then(|image| {
+                        let file = File::create(image_dir.join(&file_name))?;
+                        write_png(BufWriter::new(file), &image)?;
+                        Ok(())
+                    });
+                match image {
+                    Ok(()) => {let _ = writeln!(out, "![Z{z}]({image_dir_name}/{file_name})\n");},
+                    Err(e) => failed.push(format!("Z{z}: {e}")),
+                }
+
+                for &room_id in rooms.iter().filter(|&&r| map.state.rooms[r].coord[2] == z ) {
+                    let room = &map.state.rooms[room_id];
+                    let _ = writeln!(out, "<a id=\"room-{}\"></a>\n", room.uuid);
+                    let _ = writeln!(out, "### {}\n", room_name(room));
+                    write_room_text(&mut out, map, room_id);
+                }
+            }
+        },
+    }
+
+    std::fs::write(dest, out).context("Writing document")?;
+
+    Ok(failed)
+}
+
+#[derive(clap::Args, Debug)]
+pub struct GuideArgs {
+    /// Map to export
+    pub map: PathBuf,
+    /// Output Markdown file. Defaults to the map path with .md attached. The images are written next to it
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// A section per room or per Z level
+    #[arg(long, default_value = "room")]
+    pub sections: GuideSections,
+    /// Order of the rooms
+    #[arg(long, default_value = "coords")]
+    pub order: GuideOrder,
+    /// Coord of the start room for bfs order, like 10,10,128
+    #[arg(long, value_delimiter = ',')]
+    pub start: Option<Vec<u8>>,
+}
+
+pub fn guide_cmd(args: GuideArgs) -> anyhow::Result<()> {
+    let mut uuidmap = Default::default();
+    let map = Map::load_map(args.map, &mut uuidmap).context("Loading map")?;
+    let output = args.output.unwrap_or_else(|| attached_to_path(&map.path, ".md") );
+
+    let start = match args.start.as_deref() {
+        Some(&[x,y,z]) => Some(*map.room_matrix.get([x,y,z]).with_context(|| format!("No room at X{x}Y{y}Z{z}") )?),
+        Some(_) => anyhow::bail!("The start must be a coord like 10,10,128"),
+        None => None,
+    };
+    anyhow::ensure!(args.order != GuideOrder::Bfs || start.is_some(), "bfs order needs a start room");
+
+    for e in export_guide(&map, &output, args.sections, args.order, start)? {
+        eprintln!("Failed to export image {e}");
+    }
+    eprintln!("Exported to {}", output.to_string_lossy());
+
+    Ok(())
+}
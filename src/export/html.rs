@@ -0,0 +1,192 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::gui::map::conn_types::conn_style;
+use crate::gui::map::room_ops::{short_direction, OpAxis};
+use crate::gui::map::Map;
+use crate::gui::room::{conn_exit, CONN_NONE};
+use crate::util::attached_to_path;
+use crate::util::img::write_png;
+
+use super::{flatten_layers, load_room_image, LayerSel};
+
+const VIEWER_HTML: &str = include_str!("viewer.html");
+
+#[derive(Serialize)]
+struct ViewerData {
+    maps: Vec<ViewerMap>,
+}
+
+#[derive(Serialize)]
+struct ViewerMap {
+    uuid: Uuid,
+    title: String,
+    rooms_size: [u32;2],
+    levels: Vec<u8>,
+    current_level: u8,
+    rooms: Vec<ViewerRoom>,
+}
+
+#[derive(Serialize)]
+struct ViewerRoom {
+    uuid: Uuid,
+    coord: [u8;3],
+    title: String,
+    desc: String,
+    /// Relative to the viewer, None if the image failed to load
+    img: Option<String>,
+    conns: Vec<ViewerConn>,
+    tags: Vec<ViewerTag>,
+}
+
+#[derive(Serialize)]
+struct ViewerConn {
+    dir: &'static str,
+    color: String,
+}
+
+#[derive(Serialize)]
+struct ViewerTag {
+    pos: [u32;2],
+    text: String,
+    color: String,
+    warp: Option<ViewerWarp>,
+}
+
+#[derive(Serialize)]
+struct ViewerWarp {
+    map: Uuid,
+    room: Uuid,
+    pos: [u32;2],
+}
+
+fn color_hex([r,g,b]: [u8;3]) -> String {
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+/// Write a static viewer of the maps into the folder, which works opened from disk.
+///
+/// Warps between the exported maps can be followed in the viewer.
+/// Returns the rooms that failed to load, which are shown without image.
+pub fn export_html(maps: &[&Map], dest: &Path) -> anyhow::Result<Vec<String>> {
+    let mut failed = vec![];
+    let mut data = ViewerData { maps: vec![] };
+
+    for map in maps {
+        let rooms_size = map.state.rooms_size;
+        let image_dir = dest.join("rooms").join(map.state.uuid.to_string());
+        std::fs::create_dir_all(&image_dir).context("Creating output folder")?;
+
+        let mut rooms = map.state.rooms.iter().filter(|(_,r)| !r.transient ).collect::<Vec<_>>();
+        rooms.sort_by_key(|(_,r)| [r.coord[2],r.coord[1],r.coord[0]] );
+
+        let mut out_rooms = vec![];
+        for (room_id,room) in rooms {
+            let [x,y,z] = room.coord;
+
+            let img = match load_room_image(room, &map.path, rooms_size) {
+                Ok(image) => {
+                    let layers = LayerSel::Visible.layers_of(room, image.layers);
+                    let flat = flatten_layers(&image, &layers, rooms_size);
+                    let file = File::create(image_dir.join(format!("{}.png", room.uuid))).context("Creating room image")?;
+                    write_png(BufWriter::new(file), &flat).context("Writing room image")?;
+                    Some(format!("rooms/{}/{}.png", map.state.uuid, room.uuid))
+                },
+                Err(e) => {
+                    failed.push(format!("{} X{x}Y{y}Z{z}: {e}", map.state.title));
+                    None
+                },
+            };
+
+            let mut conns = vec![];
+            for axis in [OpAxis::X, OpAxis::Y, OpAxis::Z] {
+                for dir in [false,true] {
+                    if !map.get_room_connected(room_id, axis, dir) {continue;}
+                    let v = room.dirconn[axis.axis_idx()][dir as usize];
+                    if v == CONN_NONE {continue;}
+                    let [r,g,b,_] = conn_style(&map.state.conn_types, v).0.to_array();
+                    // entry only sides are drawn dimmed
+                    let color = if conn_exit(v) {color_hex([r,g,b])} else {color_hex([r/2,g/2,b/2])};
+                    conns.push(ViewerConn { dir: short_direction(axis, dir), color });
+                }
+            }
+
+            let tags = room.tags.values()
+                .map(|t| ViewerTag {
+                    pos: t.pos,
+                    text: t.text.clone(),
+                    color: color_hex(t.color),
+                    warp: t.warp.as_ref().map(|w| ViewerWarp { map: w.dest_map, room: w.dest_room, pos: w.dest_pos }),
+                })
+                .collect();
+
+            out_rooms.push(ViewerRoom {
+                uuid: room.uuid,
+                coord: room.coord,
+                title: room.title.clone(),
+                desc: room.desc_text.clone(),
+                img,
+                conns,
+                tags,
+            });
+        }
+
+        let mut levels = out_rooms.iter().map(|r| r.coord[2] ).collect::<Vec<_>>();
+        levels.dedup();
+        let current_level = if levels.contains(&map.state.current_level) {
+            map.state.current_level
+        } else {
+            levels.first().copied().unwrap_or(map.state.current_level)
+        };
+
+        data.maps.push(ViewerMap {
+            uuid: map.state.uuid,
+            title: map.state.title.clone(),
+            rooms_size,
+            levels,
+            current_level,
+            rooms: out_rooms,
+        });
+    }
+
+    std::fs::write(dest.join("index.html"), VIEWER_HTML).context("Writing viewer")?;
+    // a script instead of JSON, as browsers don't allow loading files from disk
+    std::fs::write(dest.join("data.js"), format!("const MZD = {};\n", serde_json::to_string(&data)?))
+        .context("Writing viewer data")?;
+
+    Ok(failed)
+}
+
+#[derive(clap::Args, Debug)]
+pub struct HtmlArgs {
+    /// Maps to export. Warps between them can be followed in the viewer
+    #[arg(required = true)]
+    pub maps: Vec<PathBuf>,
+    /// Output folder. Defaults to the first map path with _html attached
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+pub fn html_cmd(args: HtmlArgs) -> anyhow::Result<()> {
+    let mut uuidmap = Default::default();
+    let maps = args.maps.into_iter()
+        .map(|path| {
+            let context = format!("Loading {}", path.to_string_lossy());
+            Map::load_map(path, &mut uuidmap).context(context)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let output = args.output.unwrap_or_else(|| attached_to_path(&maps[0].path, "_html") );
+
+    for e in export_html(&maps.iter().collect::<Vec<_>>(), &output)? {
+        eprintln!("Failed to load room {e}");
+    }
+    eprintln!("Exported to {}", output.join("index.html").to_string_lossy());
+
+    Ok(())
+}
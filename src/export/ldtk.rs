@@ -0,0 +1,386 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use image::{GenericImageView, RgbaImage};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::gui::map::Map;
+use crate::gui::room::Room;
+use crate::gui::tags::TagState;
+use crate::util::attached_to_path;
+use crate::util::img::write_png;
+
+use super::load_room_image;
+
+const JSON_VERSION: &str = "1.5.3";
+/// The grid of mzd2, in pixels
+const GRID: u32 = 8;
+
+/// Identifiers must start with a letter and only contain letters, digits and underscores
+fn identifier(s: &str, fallback: &str) -> String {
+    let s = s.trim().chars()
+        .map(|c| if c.is_ascii_alphanumeric() {c} else {'_'} )
+        .collect::<String>();
+    match s.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => s,
+        _ => fallback.to_owned(),
+    }
+}
+
+/// Identifiers must be unique, else a number is attached
+fn unique(name: String, used: &mut HashSet<String>) -> String {
+    let mut candidate = name.clone();
+    let mut n = 2;
+    while !used.insert(candidate.clone()) {
+        candidate = format!("{name}_{n}");
+        n += 1;
+    }
+    candidate
+}
+
+fn color_hex([r,g,b]: [u8;3]) -> String {
+    format!("#{r:02X}{g:02X}{b:02X}")
+}
+
+fn room_identifier(room: &Room) -> String {
+    let [x,y,z] = room.coord;
+    format!("X{x}_Y{y}_Z{z}")
+}
+
+struct Uids(i64);
+
+impl Uids {
+    fn next(&mut self) -> i64 {
+        self.0 += 1;
+        self.0
+    }
+}
+
+fn field_def(uids: &mut Uids, name: &str, ty: &str, ldtk_type: &str) -> (i64,Value) {
+    let uid = uids.next();
+    let def = json!({
+        "identifier": name, "doc": null, "__type": ty, "uid": uid, "type": ldtk_type,
+        "isArray": false, "canBeNull": true, "arrayMinLength": null, "arrayMaxLength": null,
+        "editorDisplayMode": if ldtk_type == "F_EntityRef" {"RefLinkBetweenCenters"} else {"Hidden"},
+        "editorDisplayScale": 1, "editorDisplayPos": "Above", "editorLinkStyle": "CurvedArrow",
+        "editorDisplayColor": null, "editorAlwaysShow": false, "editorShowInWorld": true, "editorCutLongValues": true,
+        "editorTextSuffix": null, "editorTextPrefix": null, "useForSmartColor": ldtk_type == "F_Color",
+        "exportToToc": false, "searchable": false, "min": null, "max": null, "regex": null,
+        "acceptFileTypes": null, "defaultOverride": null, "textLanguageMode": null,
+        "symmetricalRef": false, "autoChainRef": true, "allowOutOfLevelRef": true, "allowedRefs": "Any",
+        "allowedRefsEntityUid": null, "allowedRefTags": [], "tilesetUid": null,
+    });
+    (uid,def)
+}
+
+fn field_instance(name: &str, ty: &str, def_uid: i64, value: Value) -> Value {
+    let editor = match &value {
+        Value::Null => json!([]),
+        Value::Object(o) => json!([{"id": "V_String", "params": [o["entityIid"]]}]),
+        v => json!([{"id": "V_String", "params": [v]}]),
+    };
+    json!({
+        "__identifier": name, "__type": ty, "__value": value, "__tile": null,
+        "defUid": def_uid, "realEditorValues": editor,
+    })
+}
+
+fn entity_def(uid: i64, name: &str, color: &str, field_defs: Vec<Value>) -> Value {
+    json!({
+        "identifier": name, "uid": uid, "tags": [], "exportToToc": false, "allowOutOfBounds": false, "doc": null,
+        "width": GRID, "height": GRID, "resizableX": false, "resizableY": false,
+        "minWidth": null, "maxWidth": null, "minHeight": null, "maxHeight": null, "keepAspectRatio": false,
+        "tileOpacity": 1, "fillOpacity": 0.08, "lineOpacity": 0, "hollow": false, "color": color,
+        "renderMode": "Ellipse", "showName": true, "tilesetId": null, "tileRenderMode": "FitInside",
+        "tileRect": null, "uiTileRect": null, "nineSliceBorders": [],
+        "maxCount": 0, "limitScope": "PerLevel", "limitBehavior": "MoveLastOne",
+        "pivotX": 0.5, "pivotY": 0.5, "fieldDefs": field_defs,
+    })
+}
+
+fn layer_def(uid: i64, name: &str, ty: &str, tileset_uid: Option<i64>) -> Value {
+    json!({
+        "__type": ty, "identifier": name, "type": ty, "uid": uid, "doc": null, "uiColor": null,
+        "gridSize": GRID, "guideGridWid": 0, "guideGridHei": 0, "displayOpacity": 1, "inactiveOpacity": 1,
+        "hideInList": false, "hideFieldsWhenInactive": true, "canSelectWhenInactive": true, "renderInWorldView": true,
+        "pxOffsetX": 0, "pxOffsetY": 0, "parallaxFactorX": 0, "parallaxFactorY": 0, "parallaxScaling": true,
+        "requiredTags": [], "excludedTags": [], "autoTilesKilledByOtherLayerUid": null, "uiFilterTags": [],
+        "useAsyncRender": false, "intGridValues": [], "intGridValuesGroups": [], "autoRuleGroups": [],
+        "autoSourceLayerDefUid": null, "tilesetDefUid": tileset_uid, "tilePivotX": 0, "tilePivotY": 0,
+        "biomeFieldUid": null,
+    })
+}
+
+/// The image of one room layer of all rooms, every room in its own cell
+struct Atlas {
+    uid: i64,
+    def_uid: i64,
+    name: String,
+    rel_path: String,
+    image: RgbaImage,
+}
+
+/// Write the map as LDtk project, with a level per room and the Z levels as world depth.
+///
+/// The room layers become tile layers, of a tileset per layer combining the images of all rooms.
+/// Returns the rooms that failed to load, which are exported without their image.
+pub fn export_map_ldtk(map: &Map, dest: &Path) -> anyhow::Result<Vec<String>> {
+    let rooms_size = map.state.rooms_size;
+    let grid_size = [rooms_size[0] / GRID, rooms_size[1] / GRID];
+
+    let mut rooms = map.state.rooms.values().filter(|r| !r.transient ).collect::<Vec<_>>();
+    anyhow::ensure!(!rooms.is_empty(), "No rooms to export");
+    rooms.sort_by_key(|r| [r.coord[2],r.coord[1],r.coord[0]] );
+
+    let min_z = rooms.iter().map(|r| r.coord[2] ).min().unwrap_or_default();
+    let n_layers = rooms.iter().map(|r| r.layers.len() ).max().unwrap_or_default();
+
+    let mut failed = vec![];
+    let images = rooms.iter()
+        .map(|room| load_room_image(room, &map.path, rooms_size)
+            .map_err(|e| failed.push(format!("{}: {e}", room_identifier(room))) )
+            .ok()
+        )
+        .collect::<Vec<_>>();
+
+    let image_dir_name = format!("{}_images", dest.file_stem().unwrap_or_default().to_string_lossy());
+    let image_dir = dest.with_file_name(&image_dir_name);
+    std::fs::create_dir_all(&image_dir).context("Creating image folder")?;
+
+    let mut uids = Uids(0);
+
+    // the rooms are placed in the atlases in rows
+    let columns = (rooms.len() as f64).sqrt().ceil() as u32;
+    let rows = (rooms.len() as u32).div_ceil(columns);
+    let atlas_cell = |i: usize| [(i as u32 % columns) * rooms_size[0], (i as u32 / columns) * rooms_size[1]];
+
+    let mut layer_names = HashSet::from(["Tags".to_owned()]);
+    let mut atlases = (0 .. n_layers)
+        .map(|layer| {
+            // the label of the layer, if a room has one
+            let label = rooms.iter()
+                .filter_map(|r| r.layers.get(layer) )
+                .map(|l| l.label.as_str() )
+                .find(|l| !l.is_empty() )
+                .unwrap_or_default();
+            Atlas {
+                uid: uids.next(),
+                def_uid: uids.next(),
+                name: unique(identifier(label, &format!("Layer_{layer}")), &mut layer_names),
+                rel_path: format!("{image_dir_name}/layer_{layer}.png"),
+                image: RgbaImage::new(columns * rooms_size[0], rows * rooms_size[1]),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    for (i,image) in images.iter().enumerate() {
+        let Some(image) = image else {continue};
+        let [x,y] = atlas_cell(i);
+        for (layer,atlas) in atlases.iter_mut().enumerate().take(image.layers) {
+            let view = image.img.view(0, layer as u32 * rooms_size[1], rooms_size[0], rooms_size[1]);
+            image::imageops::replace(&mut atlas.image, &*view, x as i64, y as i64);
+        }
+    }
+
+    for atlas in &atlases {
+        let file = File::create(image_dir.join(Path::new(&atlas.rel_path).file_name().unwrap())).context("Creating layer image")?;
+        write_png(BufWriter::new(file), &atlas.image).context("Writing layer image")?;
+    }
+
+    // tags and warp targets are entities
+    let (text_uid,text_def) = field_def(&mut uids, "text", "String", "F_String");
+    let (color_uid,color_def) = field_def(&mut uids, "color", "Color", "F_Color");
+    let (item_uid,item_def) = field_def(&mut uids, "item", "String", "F_String");
+    let (warp_uid,warp_def) = field_def(&mut uids, "warp", "EntityRef", "F_EntityRef");
+    let (external_uid,external_def) = field_def(&mut uids, "warp_external", "String", "F_String");
+    let tag_uid = uids.next();
+    let target_uid = uids.next();
+    let entity_layer_uid = uids.next();
+
+    let level_uids = rooms.iter().map(|_| uids.next() ).collect::<Vec<_>>();
+    let entity_layer_iids = rooms.iter().map(|_| Uuid::now_v7() ).collect::<Vec<_>>();
+    let room_index = rooms.iter().enumerate().map(|(i,r)| (r.uuid,i) ).collect::<HashMap<_,_>>();
+
+    // the warps point to the tag at the destination, or else to an added warp target entity
+    let mut targets: Vec<Vec<(Uuid,[u32;2])>> = vec![vec![]; rooms.len()];
+    let mut warp_refs = HashMap::new();
+    for room in &rooms {
+        for (&tag_id,tag) in &room.tags {
+            let Some(warp) = &tag.warp else {continue};
+            if warp.dest_map != map.state.uuid {continue;}
+            let Some(&dest) = room_index.get(&warp.dest_room) else {continue};
+            let hit = rooms[dest].tags.iter()
+                .find(|(_,t)| t.touch_in_range(warp.dest_pos) )
+                .map(|(&id,_)| id )
+                .or_else(|| targets[dest].iter().find(|(_,p)| *p == warp.dest_pos ).map(|&(id,_)| id ) );
+            let target = hit.unwrap_or_else(|| {
+                let id = Uuid::now_v7();
+                targets[dest].push((id,warp.dest_pos));
+                id
+            });
+            warp_refs.insert(tag_id, json!({
+                "entityIid": target.to_string(),
+                "layerIid": entity_layer_iids[dest].to_string(),
+                "levelIid": rooms[dest].uuid.to_string(),
+                "worldIid": map.state.uuid.to_string(),
+            }));
+        }
+    }
+
+    let entity = |identifier: &str, def_uid: i64, iid: Uuid, [x,y]: [u32;2], [wx,wy]: [u32;2], color: String, fields: Vec<Value>| json!({
+        "__identifier": identifier, "__grid": [x / GRID, y / GRID], "__pivot": [0.5,0.5], "__tags": [],
+        "__tile": null, "__smartColor": color, "__worldX": wx + x, "__worldY": wy + y,
+        "iid": iid.to_string(), "width": GRID, "height": GRID, "defUid": def_uid, "px": [x,y],
+        "fieldInstances": fields,
+    });
+    let tag_fields = |tag_id: &Uuid, tag: &TagState| {
+        let external = tag.warp.as_ref()
+            .filter(|_| !warp_refs.contains_key(tag_id) )
+            .map_or(Value::Null, |w| json!(format!("{} {}", w.dest_map, w.dest_room)) );
+        let item = tag.item.trim();
+        vec![
+            field_instance("text", "String", text_uid, json!(tag.text)),
+            field_instance("color", "Color", color_uid, json!(color_hex(tag.color))),
+            field_instance("item", "String", item_uid, if item.is_empty() {Value::Null} else {json!(item)}),
+            field_instance("warp", "EntityRef", warp_uid, warp_refs.get(tag_id).cloned().unwrap_or(Value::Null)),
+            field_instance("warp_external", "String", external_uid, external),
+        ]
+    };
+
+    let mut levels = vec![];
+    let mut level_names = HashSet::new();
+    for (i,room) in rooms.iter().enumerate() {
+        let [x,y,z] = room.coord;
+        let world = [x as u32 * rooms_size[0], y as u32 * rooms_size[1]];
+
+        let mut entities = room.tags.iter()
+            .map(|(tag_id,tag)| entity("Tag", tag_uid, *tag_id, tag.pos, world, color_hex(tag.color), tag_fields(tag_id, tag)) )
+            .collect::<Vec<_>>();
+        entities.extend(
+            targets[i].iter().map(|&(id,pos)| entity("WarpTarget", target_uid, id, pos, world, "#FFFFFF".into(), vec![]) )
+        );
+
+        let mut layer_instances = vec![json!({
+            "__identifier": "Tags", "__type": "Entities", "__cWid": grid_size[0], "__cHei": grid_size[1],
+            "__gridSize": GRID, "__opacity": 1, "__pxTotalOffsetX": 0, "__pxTotalOffsetY": 0,
+            "__tilesetDefUid": null, "__tilesetRelPath": null, "iid": entity_layer_iids[i].to_string(),
+            "levelId": level_uids[i], "layerDefUid": entity_layer_uid, "pxOffsetX": 0, "pxOffsetY": 0,
+            "visible": true, "optionalRules": [], "intGridCsv": [], "autoLayerTiles": [], "seed": 0,
+            "overrideTilesetUid": null, "gridTiles": [], "entityInstances": entities,
+        })];
+
+        // top most first
+        for (layer,atlas) in atlases.iter().enumerate().rev() {
+            let mut tiles = vec![];
+            if let Some(image) = &images[i] && layer < image.layers {
+                let [ax,ay] = atlas_cell(i);
+                let atlas_columns = atlas.image.width() / GRID;
+                for ty in 0 .. grid_size[1] {
+                    for tx in 0 .. grid_size[0] {
+                        let src = [ax + tx * GRID, ay + ty * GRID];
+                        let view = atlas.image.view(src[0], src[1], GRID, GRID);
+                        if view.pixels().all(|(_,_,p)| p[3] == 0 ) {continue;}
+                        tiles.push(json!({
+                            "px": [tx * GRID, ty * GRID], "src": src, "f": 0,
+                            "t": (src[1] / GRID) * atlas_columns + src[0] / GRID,
+                            "d": [ty * grid_size[0] + tx], "a": 1,
+                        }));
+                    }
+                }
+            }
+            let visible = room.layers.get(layer).is_none_or(|l| l.vis != 0 );
+            layer_instances.push(json!({
+                "__identifier": atlas.name, "__type": "Tiles", "__cWid": grid_size[0], "__cHei": grid_size[1],
+                "__gridSize": GRID, "__opacity": 1, "__pxTotalOffsetX": 0, "__pxTotalOffsetY": 0,
+                "__tilesetDefUid": atlas.uid, "__tilesetRelPath": atlas.rel_path, "iid": Uuid::now_v7().to_string(),
+                "levelId": level_uids[i], "layerDefUid": atlas.def_uid, "pxOffsetX": 0, "pxOffsetY": 0,
+                "visible": visible, "optionalRules": [], "intGridCsv": [], "autoLayerTiles": [], "seed": 0,
+                "overrideTilesetUid": null, "gridTiles": tiles, "entityInstances": [],
+            }));
+        }
+
+        let title = if room.title.is_empty() {room_identifier(room)} else {room.title.clone()};
+        levels.push(json!({
+            "identifier": unique(identifier(&title, &room_identifier(room)), &mut level_names), "iid": room.uuid.to_string(), "uid": level_uids[i],
+            "worldX": world[0], "worldY": world[1], "worldDepth": z as i32 - min_z as i32,
+            "pxWid": rooms_size[0], "pxHei": rooms_size[1], "__bgColor": "#696A79", "bgColor": null,
+            "useAutoIdentifier": false, "bgRelPath": null, "bgPos": null, "bgPivotX": 0.5, "bgPivotY": 0.5,
+            "__smartColor": "#ADADB5", "__bgPos": null, "externalRelPath": null,
+            "fieldInstances": [], "layerInstances": layer_instances, "__neighbours": [],
+        }));
+    }
+
+    let mut layer_defs = vec![layer_def(entity_layer_uid, "Tags", "Entities", None)];
+    layer_defs.extend(atlases.iter().rev().map(|a| layer_def(a.def_uid, &a.name, "Tiles", Some(a.uid)) ));
+
+    let tilesets = atlases.iter()
+        .map(|a| json!({
+            "__cWid": a.image.width() / GRID, "__cHei": a.image.height() / GRID,
+            "identifier": a.name, "uid": a.uid, "relPath": a.rel_path, "embedAtlas": null,
+            "pxWid": a.image.width(), "pxHei": a.image.height(), "tileGridSize": GRID,
+            "spacing": 0, "padding": 0, "tags": [], "tagsSourceEnumUid": null, "enumTags": [],
+            "customData": [], "savedSelections": [], "cachedPixelData": null,
+        }))
+        .collect::<Vec<_>>();
+
+    let project = json!({
+        "__header__": {
+            "fileType": "LDtk Project JSON", "app": "LDtk", "doc": "https://ldtk.io/json",
+            "schema": "https://ldtk.io/files/JSON_SCHEMA.json", "appAuthor": "Sebastien 'deepnight' Benard",
+            "appVersion": JSON_VERSION, "url": "https://ldtk.io",
+        },
+        "iid": map.state.uuid.to_string(), "jsonVersion": JSON_VERSION, "appBuildId": 0, "nextUid": uids.next(),
+        "identifierStyle": "Free", "toc": [], "worldLayout": "Free",
+        "worldGridWidth": rooms_size[0], "worldGridHeight": rooms_size[1],
+        "defaultLevelWidth": rooms_size[0], "defaultLevelHeight": rooms_size[1],
+        "defaultPivotX": 0, "defaultPivotY": 0, "defaultGridSize": GRID,
+        "defaultEntityWidth": GRID, "defaultEntityHeight": GRID,
+        "bgColor": "#40465B", "defaultLevelBgColor": "#696A79",
+        "minifyJson": false, "externalLevels": false, "exportTiled": false, "simplifiedExport": false,
+        "imageExportMode": "None", "exportLevelBg": true, "pngFilePattern": null,
+        "backupOnSave": false, "backupLimit": 10, "backupRelPath": null, "levelNamePattern": "Level_%idx",
+        "tutorialDesc": null, "customCommands": [], "flags": [],
+        "defs": {
+            "layers": layer_defs,
+            "entities": [
+                entity_def(tag_uid, "Tag", "#FF00FF", vec![text_def, color_def, item_def, warp_def, external_def]),
+                entity_def(target_uid, "WarpTarget", "#FFFFFF", vec![]),
+            ],
+            "tilesets": tilesets,
+            "enums": [], "externalEnums": [], "levelFields": [],
+        },
+        "levels": levels,
+        "worlds": [],
+        "dummyWorldIid": map.state.uuid.to_string(),
+    });
+
+    std::fs::write(dest, serde_json::to_string_pretty(&project)?).context("Writing project")?;
+
+    Ok(failed)
+}
+
+#[derive(clap::Args, Debug)]
+pub struct LdtkArgs {
+    /// Map to export
+    pub map: PathBuf,
+    /// Output project. Defaults to the map path with .ldtk attached. The layer images are written next to it
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+pub fn ldtk_cmd(args: LdtkArgs) -> anyhow::Result<()> {
+    let mut uuidmap = Default::default();
+    let map = Map::load_map(args.map, &mut uuidmap).context("Loading map")?;
+    let output = args.output.unwrap_or_else(|| attached_to_path(&map.path, ".ldtk") );
+
+    for e in export_map_ldtk(&map, &output)? {
+        eprintln!("Failed to load room {e}");
+    }
+    eprintln!("Exported to {}", output.to_string_lossy());
+
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use std::path::Path;
+
+use image::{GenericImageView, RgbaImage};
+
+use crate::gui::room::Room;
+use crate::gui::room::draw_image::DrawImage;
+
+pub mod graph;
+pub mod guide;
+pub mod html;
+pub mod ldtk;
+pub mod overview;
+pub mod rooms;
+pub mod tiled;
+
+/// Which layers of a room should be exported
+#[derive(Clone, Debug)]
+pub enum LayerSel {
+    /// The layers marked visible in the room
+    Visible,
+    All,
+    Only(Vec<usize>),
+}
+
+impl LayerSel {
+    pub fn from_args(layers: Option<Vec<usize>>, all_layers: bool) -> Self {
+        match (layers, all_layers) {
+            (Some(v), _) => Self::Only(v),
+            (None, true) => Self::All,
+            (None, false) => Self::Visible,
+        }
+    }
+
+    pub fn layers_of(&self, room: &Room, n_layers: usize) -> Vec<usize> {
+        match self {
+            Self::Visible => room.layers.iter().enumerate()
+                .filter(|&(i,l)| l.vis != 0 && i < n_layers )
+                .map(|(i,_)| i )
+                .collect(),
+            Self::All => (0 .. n_layers).collect(),
+            Self::Only(v) => v.iter().cloned().filter(|&i| i < n_layers ).collect(),
+        }
+    }
+}
+
+/// Load the image of a room without going through the GUI error handling
+pub fn load_room_image(room: &Room, map_path: &Path, rooms_size: [u32;2]) -> anyhow::Result<DrawImage> {
+    if let Some(loaded) = &room.loaded {
+        return Ok(DrawImage {
+            img: loaded.image.img.clone(),
+            tex: None,
+            layers: loaded.image.layers,
+        });
+    }
+    let loaded = room.load_room_res(map_path, rooms_size)?;
+    Ok(loaded.image)
+}
+
+/// Blend the given layers of the room image, bottom to top, into one rooms_size image
+pub fn flatten_layers(image: &DrawImage, layers: &[usize], rooms_size: [u32;2]) -> RgbaImage {
+    let mut dest = RgbaImage::new(rooms_size[0], rooms_size[1]);
+    for &layer in layers {
+        if layer >= image.layers {continue;}
+        let view = image.img.view(0, layer as u32 * rooms_size[1], rooms_size[0], rooms_size[1]);
+        image::imageops::overlay(&mut dest, &*view, 0, 0);
+    }
+    dest
+}
+
+/// For text and attribute values
+pub fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\n' => out.push_str("&#10;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+pub fn upscale(image: RgbaImage, scale: u32) -> anyhow::Result<RgbaImage> {
+    if scale <= 1 {return Ok(image);}
+    let (Some(width),Some(height)) = (image.width().checked_mul(scale),image.height().checked_mul(scale)) else {
+        anyhow::bail!("Exported image would be too large");
+    };
+    Ok(image::imageops::resize(
+        &image,
+        width,
+        height,
+        image::imageops::FilterType::Nearest,
+    ))
+}
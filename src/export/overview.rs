@@ -0,0 +1,165 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use image::{Rgba, RgbaImage};
+
+use crate::gui::map::room_ops::OpAxis;
+use crate::gui::map::conn_types::conn_style;
+use crate::gui::map::{Map, RoomId};
+use crate::gui::room::{conn_kind, Room, CONN_OPEN};
+use crate::gui::tags::RADIUS;
+use crate::gui::util::ArrUtl;
+use crate::util::attached_to_path;
+use crate::util::img::{fill_circle, fill_rect, write_png};
+
+use super::{flatten_layers, load_room_image, upscale, LayerSel};
+
+const CONN_COLOR: Rgba<u8> = Rgba([0,255,0,255]);
+const CONN_UP_COLOR: Rgba<u8> = Rgba([0,160,255,255]);
+const CONN_DOWN_COLOR: Rgba<u8> = Rgba([255,160,0,255]);
+const ERROR_COLOR: Rgba<u8> = Rgba([255,0,0,128]);
+
+#[derive(clap::Args, Debug)]
+pub struct ExportArgs {
+    /// Map to export
+    pub map: PathBuf,
+    /// Output PNG. Defaults to the map path with _z<level>.png attached
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// Z level to export. Defaults to the level last viewed in the map
+    #[arg(short = 'z', long)]
+    pub level: Option<u8>,
+    /// Integer upscale factor
+    #[arg(short, long, default_value_t = 1)]
+    pub scale: u32,
+    /// Comma separated room layers to export. Defaults to the visible layers of each room
+    #[arg(long, value_delimiter = ',')]
+    pub layers: Option<Vec<usize>>,
+    /// Export all room layers instead of only the visible ones
+    #[arg(long)]
+    pub all_layers: bool,
+    /// Draw room connections on top (green: XY door, blue: Z+, orange: Z-)
+    #[arg(long)]
+    pub conns: bool,
+    /// Draw tags on top
+    #[arg(long)]
+    pub tags: bool,
+}
+
+pub fn export_overview(args: ExportArgs) -> anyhow::Result<()> {
+    anyhow::ensure!(args.scale != 0, "Scale must not be 0");
+
+    let mut uuidmap = Default::default();
+
+    let map = Map::load_map(args.map, &mut uuidmap).context("Loading map")?;
+
+    let level = args.level.unwrap_or(map.state.current_level);
+    let layer_sel = LayerSel::from_args(args.layers, args.all_layers);
+    let output = args.output.unwrap_or_else(|| attached_to_path(&map.path, format!("_z{level}.png")) );
+
+    let image = render_overview(&map, level, &layer_sel, args.scale, args.conns, args.tags)?;
+
+    let file = File::create(&output).context("Creating output file")?;
+    write_png(BufWriter::new(file), &image).context("Writing PNG")?;
+
+    eprintln!("Exported Z{level} to {}", output.to_string_lossy());
+
+    Ok(())
+}
+
+/// Composite all rooms of the level into one image, cropped to the rooms' bounding box
+pub fn render_overview(map: &Map, level: u8, layer_sel: &LayerSel, scale: u32, draw_conns: bool, draw_tags: bool) -> anyhow::Result<RgbaImage> {
+    let rooms_size = map.state.rooms_size;
+
+    let rooms = map.state.rooms.iter()
+        .filter(|(_,r)| r.coord[2] == level && !r.transient )
+        .collect::<Vec<_>>();
+
+    anyhow::ensure!(!rooms.is_empty(), "No rooms on Z{level}");
+
+    let (mut min, mut max) = ([255u8;2],[0u8;2]);
+    for (_,room) in &rooms {
+        min = min.vmin([room.coord[0],room.coord[1]]);
+        max = max.vmax([room.coord[0],room.coord[1]]);
+    }
+
+    let (Some(room_w),Some(room_h)) = (rooms_size[0].checked_mul(scale),rooms_size[1].checked_mul(scale)) else {
+        anyhow::bail!("Exported image would be too large");
+    };
+    let room_px = [room_w,room_h];
+    let n_rooms = max.sub(min).as_u32().add([1,1]);
+    let (Some(width),Some(height)) = (n_rooms[0].checked_mul(room_px[0]),n_rooms[1].checked_mul(room_px[1])) else {
+        anyhow::bail!("Exported image would be too large");
+    };
+
+    let mut dest = RgbaImage::new(width, height);
+
+    let room_off = |room: &Room| [room.coord[0],room.coord[1]].sub(min).as_u32().mul(room_px);
+
+    for &(_,room) in &rooms {
+        let off = room_off(room);
+        match load_room_image(room, &map.path, rooms_size) {
+            Ok(image) => {
+                let layers = layer_sel.layers_of(room, image.layers);
+                let flat = upscale(flatten_layers(&image, &layers, rooms_size), scale)?;
+                image::imageops::replace(&mut dest, &flat, off[0] as i64, off[1] as i64);
+            },
+            Err(e) => {
+                eprintln!("Failed to load room @ X{}Y{}Z{}: {e}", room.coord[0], room.coord[1], room.coord[2]);
+                fill_rect(&mut dest, off.as_i64(), room_px, ERROR_COLOR);
+            },
+        }
+    }
+
+    if draw_conns {
+        for &(id,room) in &rooms {
+            draw_room_conns(map, id, room_off(room), room_px, scale, &mut dest);
+        }
+    }
+
+    if draw_tags {
+        for &(_,room) in &rooms {
+            let off = room_off(room);
+            for tag in room.tags.values() {
+                let [r,g,b] = tag.color;
+                fill_circle(&mut dest, off.add(tag.pos.mul([scale,scale])).as_i64(), RADIUS * scale, Rgba([r,g,b,255]));
+            }
+        }
+    }
+
+    Ok(dest)
+}
+
+fn draw_room_conns(map: &Map, room_id: RoomId, off: [u32;2], room_px: [u32;2], scale: u32, dest: &mut RgbaImage) {
+    let thick = 2 * scale;
+    let [x,y] = off.as_i64();
+    let [w,h] = room_px.as_i64();
+    let [door_w,door_h] = room_px.div([4,4]);
+    let t = thick as i64;
+
+    let sides = [
+        (OpAxis::X, false, [x - t/2, y + (h - door_h as i64)/2], [thick,door_h]),
+        (OpAxis::X, true , [x + w - t/2, y + (h - door_h as i64)/2], [thick,door_h]),
+        (OpAxis::Y, false, [x + (w - door_w as i64)/2, y - t/2], [door_w,thick]),
+        (OpAxis::Y, true , [x + (w - door_w as i64)/2, y + h - t/2], [door_w,thick]),
+        (OpAxis::Z, true , [x + w - 4*t, y + t], [thick*3,thick*3]),
+        (OpAxis::Z, false, [x + w - 4*t, y + 5*t], [thick*3,thick*3]),
+    ];
+
+    for (axis,dir,pos,size) in sides {
+        if !map.get_room_connected(room_id, axis, dir) {continue;}
+        let conn = map.state.rooms[room_id].dirconn[axis.axis_idx()][dir as usize];
+        let color = match (axis,dir) {
+            _ if conn_kind(conn) != CONN_OPEN => {
+                let [r,g,b,_] = conn_style(&map.state.conn_types, conn).0.to_array();
+                Rgba([r,g,b,255])
+            },
+            (OpAxis::Z, true) => CONN_UP_COLOR,
+            (OpAxis::Z, false) => CONN_DOWN_COLOR,
+            _ => CONN_COLOR,
+        };
+        fill_rect(dest, pos, size, color);
+    }
+}
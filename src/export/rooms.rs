@@ -0,0 +1,225 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::gui::map::{Map, RoomId};
+use crate::gui::room::Room;
+use crate::util::attached_to_path;
+use crate::util::img::write_png;
+
+use super::{flatten_layers, load_room_image, upscale, LayerSel};
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug, clap::ValueEnum)]
+pub enum RoomsNaming {
+    /// Like X10_Y10_Z128
+    #[default]
+    Coords,
+    /// The room title, or the coords if it has none
+    Title,
+}
+
+pub struct RoomsExportOpts {
+    pub layer_sel: LayerSel,
+    /// A file per layer instead of the layers flattened into one
+    pub separate_layers: bool,
+    pub scale: u32,
+    pub naming: RoomsNaming,
+    pub manifest: bool,
+}
+
+impl Default for RoomsExportOpts {
+    fn default() -> Self {
+        Self {
+            layer_sel: LayerSel::Visible,
+            separate_layers: false,
+            scale: 1,
+            naming: RoomsNaming::Coords,
+            manifest: false,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ManifestEntry {
+    file: String,
+    /// None if flattened
+    layer: Option<usize>,
+    room: Uuid,
+    coord: [u8;3],
+    title: String,
+    tags: Vec<ManifestTag>,
+}
+
+#[derive(Serialize)]
+struct ManifestTag {
+    uuid: Uuid,
+    /// Relative to the room, in unscaled pixels
+    pos: [u32;2],
+    text: String,
+    item: String,
+    warp_map: Option<Uuid>,
+    warp_room: Option<Uuid>,
+}
+
+fn coord_stem(room: &Room) -> String {
+    let [x,y,z] = room.coord;
+    format!("X{x}_Y{y}_Z{z}")
+}
+
+fn title_stem(room: &Room) -> String {
+    let stem = room.title.trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' {c} else {'_'} )
+        .collect::<String>();
+    if stem.trim_matches('_').is_empty() {
+        coord_stem(room)
+    } else {
+        stem
+    }
+}
+
+/// Write every given room as PNG into the folder, with an optional manifest.json describing the files.
+///
+/// Returns the rooms that failed to load, which are skipped.
+pub fn export_rooms(map: &Map, rooms: &[RoomId], dest: &Path, opts: &RoomsExportOpts) -> anyhow::Result<Vec<String>> {
+    anyhow::ensure!(opts.scale != 0, "Scale must not be 0");
+    std::fs::create_dir_all(dest).context("Creating output folder")?;
+
+    let rooms_size = map.state.rooms_size;
+
+    let mut rooms = rooms.iter()
+        .filter_map(|&id| map.state.rooms.get(id) )
+        .filter(|r| !r.transient )
+        .collect::<Vec<_>>();
+    rooms.sort_by_key(|r| [r.coord[2],r.coord[1],r.coord[0]] );
+
+    let mut failed = vec![];
+    let mut used_stems = HashSet::new();
+    let mut manifest = vec![];
+
+    for room in rooms {
+        let image = match load_room_image(room, &map.path, rooms_size) {
+            Ok(v) => v,
+            Err(e) => {
+                failed.push(format!("{}: {e}", coord_stem(room)));
+                continue;
+            },
+        };
+
+        let mut stem = match opts.naming {
+            RoomsNaming::Coords => coord_stem(room),
+            RoomsNaming::Title => title_stem(room),
+        };
+        // rooms can share a title
+        if !used_stems.insert(stem.to_lowercase()) {
+            let mut i = 2;
+            while !used_stems.insert(format!("{stem}_{i}").to_lowercase()) {
+                i += 1;
+            }
+            stem = format!("{stem}_{i}");
+        }
+
+        let layers = opts.layer_sel.layers_of(room, image.layers);
+        let files = if opts.separate_layers {
+            layers.iter()
+                .map(|&layer| (format!("{stem}_L{layer}.png"), Some(layer), image.layer_image(layer, rooms_size)) )
+                .collect::<Vec<_>>()
+        } else {
+            vec![(format!("{stem}.png"), None, flatten_layers(&image, &layers, rooms_size))]
+        };
+
+        for (file_name,layer,img) in files {
+            let img = upscale(img, opts.scale)?;
+            let file = File::create(dest.join(&file_name)).context("Creating room image")?;
+            write_png(BufWriter::new(file), &img).context("Writing room image")?;
+
+            if opts.manifest {
+                manifest.push(ManifestEntry {
+                    file: file_name,
+                    layer,
+                    room: room.uuid,
+                    coord: room.coord,
+                    title: room.title.clone(),
+                    tags: room.tags.iter()
+                        .map(|(&uuid,t)| ManifestTag {
+                            uuid,
+                            pos: t.pos,
+                            text: t.text.clone(),
+                            item: t.item.clone(),
+                            warp_map: t.warp.as_ref().map(|w| w.dest_map ),
+                            warp_room: t.warp.as_ref().map(|w| w.dest_room ),
+                        })
+                        .collect(),
+                });
+            }
+        }
+    }
+
+    if opts.manifest {
+        std::fs::write(dest.join("manifest.json"), serde_json::to_string_pretty(&manifest)?)
+            .context("Writing manifest")?;
+    }
+
+    Ok(failed)
+}
+
+#[derive(clap::Args, Debug)]
+pub struct RoomsArgs {
+    /// Map to export
+    pub map: PathBuf,
+    /// Output folder. Defaults to the map path with _rooms attached
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// Only export the rooms of this Z level
+    #[arg(short = 'z', long)]
+    pub level: Option<u8>,
+    /// Integer upscale factor
+    #[arg(short, long, default_value_t = 1)]
+    pub scale: u32,
+    /// Comma separated room layers to export. Defaults to the visible layers of each room
+    #[arg(long, value_delimiter = ',')]
+    pub layers: Option<Vec<usize>>,
+    /// Export all room layers instead of only the visible ones
+    #[arg(long)]
+    pub all_layers: bool,
+    /// Write every layer into its own file instead of flattening them
+    #[arg(long)]
+    pub separate_layers: bool,
+    /// How the files are named
+    #[arg(long, default_value = "coords")]
+    pub names: RoomsNaming,
+    /// Also write a manifest.json mapping the files to room uuid, coord and tags
+    #[arg(long)]
+    pub manifest: bool,
+}
+
+pub fn rooms_cmd(args: RoomsArgs) -> anyhow::Result<()> {
+    let mut uuidmap = Default::default();
+    let map = Map::load_map(args.map, &mut uuidmap).context("Loading map")?;
+    let output = args.output.unwrap_or_else(|| attached_to_path(&map.path, "_rooms") );
+
+    let rooms = map.state.rooms.iter()
+        .filter(|(_,r)| args.level.is_none_or(|z| r.coord[2] == z ) )
+        .map(|(id,_)| id )
+        .collect::<Vec<_>>();
+
+    let opts = RoomsExportOpts {
+        layer_sel: LayerSel::from_args(args.layers, args.all_layers),
+        separate_layers: args.separate_layers,
+        scale: args.scale,
+        naming: args.names,
+        manifest: args.manifest,
+    };
+
+    for e in export_rooms(&map, &rooms, &output, &opts)? {
+        eprintln!("Failed to load room {e}");
+    }
+    eprintln!("Exported to {}", output.to_string_lossy());
+
+    Ok(())
+}
@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use image::GenericImageView;
+
+use crate::gui::map::conn_types::conn_type_name;
+use crate::gui::map::room_ops::{short_direction, OpAxis};
+use crate::gui::map::Map;
+use crate::gui::room::{Room, CONN_NONE, CONN_ONEWAY_IN};
+use crate::gui::sel_matrix::{SelEntryRead, SelMatrix};
+use crate::gui::tileset::Tileset;
+use crate::util::attached_to_path;
+use crate::util::img::write_png;
+
+use super::{load_room_image, xml_escape};
+
+/// The grid of mzd2, in pixels
+const TILE: u32 = 8;
+
+const TMX_HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>"#;
+
+fn room_stem(room: &Room) -> String {
+    let [x,y,z] = room.coord;
+    format!("X{x}_Y{y}_Z{z}")
+}
+
+fn write_property(out: &mut String, indent: &str, name: &str, ty: Option<&str>, value: &str) {
+    let ty = ty.map_or(String::new(), |t| format!(r#" type="{t}""#) );
+    let _ = writeln!(out, r#"{indent}<property name="{}"{ty} value="{}"/>"#, xml_escape(name), xml_escape(value));
+}
+
+/// Write every room of the map as Tiled map, with a Tiled world per Z level placing the rooms.
+///
+/// Returns the rooms that failed to load, which are exported without their image layers.
+pub fn export_map_tiled(map: &Map, dest: &Path) -> anyhow::Result<Vec<String>> {
+    let rooms_size = map.state.rooms_size;
+    let image_dir = dest.join("rooms");
+    std::fs::create_dir_all(&image_dir).context("Creating output folder")?;
+
+    let mut rooms = map.state.rooms.values().filter(|r| !r.transient ).collect::<Vec<_>>();
+    rooms.sort_by_key(|r| [r.coord[2],r.coord[1],r.coord[0]] );
+
+    let mut failed = vec![];
+    let mut worlds = BTreeMap::<u8,Vec<serde_json::Value>>::new();
+
+    for room in rooms {
+        let stem = room_stem(room);
+
+        let image = match load_room_image(room, &map.path, rooms_size) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                failed.push(format!("{stem}: {e}"));
+                None
+            },
+        };
+
+        let mut out = String::new();
+        let _ = writeln!(out, "{TMX_HEADER}");
+        let _ = writeln!(
+            out, r#"<map version="1.10" tiledversion="1.10.2" orientation="orthogonal" renderorder="right-down" width="{}" height="{}" tilewidth="{TILE}" tileheight="{TILE}" infinite="0" nextlayerid="{}" nextobjectid="{}">"#,
+            rooms_size[0] / TILE, rooms_size[1] / TILE, room.layers.len() + 2, room.tags.len() + 1,
+        );
+
+        let _ = writeln!(out, " <properties>");
+        write_property(&mut out, "  ", "uuid", None, &room.uuid.to_string());
+        write_property(&mut out, "  ", "title", None, &room.title);
+        write_property(&mut out, "  ", "desc", None, &room.desc_text);
+        for axis in [OpAxis::X, OpAxis::Y, OpAxis::Z] {
+            for dir in [false,true] {
+                let v = room.dirconn[axis.axis_idx()][dir as usize];
+                if v == CONN_NONE {continue;}
+                let mut value = conn_type_name(&map.state.conn_types, v);
+                if v & CONN_ONEWAY_IN != 0 {
+                    value.push_str(" (enter only)");
+                }
+                write_property(&mut out, "  ", &format!("conn {}", short_direction(axis, dir)), None, &value);
+            }
+        }
+        let _ = writeln!(out, " </properties>");
+
+        if let Some(image) = &image {
+            for i in 0 .. image.layers.min(room.layers.len()) {
+                let file_name = format!("{stem}_{i}.png");
+                let view = image.img.view(0, i as u32 * rooms_size[1], rooms_size[0], rooms_size[1]).to_image();
+                let file = File::create(image_dir.join(&file_name)).context("Creating layer image")?;
+                write_png(BufWriter::new(file), &view).context("Writing layer image")?;
+
+                let layer = &room.layers[i];
+                let name = if layer.label.is_empty() {format!("Layer {i}")} else {layer.label.clone()};
+                let _ = writeln!(out, r#" <imagelayer id="{}" name="{}" visible="{}">"#, i + 1, xml_escape(&name), (layer.vis != 0) as u8);
+                let _ = writeln!(out, r#"  <image source="rooms/{file_name}" width="{}" height="{}"/>"#, rooms_size[0], rooms_size[1]);
+                let _ = writeln!(out, " </imagelayer>");
+            }
+        }
+
+        let _ = writeln!(out, r#" <objectgroup id="{}" name="Tags">"#, room.layers.len() + 1);
+        for (i,(tag_id,tag)) in room.tags.iter().enumerate() {
+            let [r,g,b] = tag.color;
+            let _ = writeln!(out, r#"  <object id="{}" name="{}" x="{}" y="{}">"#, i + 1, xml_escape(&tag.text), tag.pos[0], tag.pos[1]);
+            let _ = writeln!(out, "   <properties>");
+            write_property(&mut out, "    ", "uuid", None, &tag_id.to_string());
+            write_property(&mut out, "    ", "text", None, &tag.text);
+            write_property(&mut out, "    ", "color", Some("color"), &format!("#ff{r:02x}{g:02x}{b:02x}"));
+            if !tag.item.trim().is_empty() {
+                write_property(&mut out, "    ", "item", None, tag.item.trim());
+            }
+            if let Some(warp) = &tag.warp {
+                write_property(&mut out, "    ", "warp_map", None, &warp.dest_map.to_string());
+                write_property(&mut out, "    ", "warp_room", None, &warp.dest_room.to_string());
+                write_property(&mut out, "    ", "warp_x", Some("int"), &warp.dest_pos[0].to_string());
+                write_property(&mut out, "    ", "warp_y", Some("int"), &warp.dest_pos[1].to_string());
+            }
+            let _ = writeln!(out, "   </properties>");
+            let _ = writeln!(out, "   <point/>");
+            let _ = writeln!(out, "  </object>");
+        }
+        let _ = writeln!(out, " </objectgroup>");
+        let _ = writeln!(out, "</map>");
+
+        std::fs::write(dest.join(format!("{stem}.tmx")), out).context("Writing room map")?;
+
+        worlds.entry(room.coord[2]).or_default().push(serde_json::json!({
+            "fileName": format!("{stem}.tmx"),
+            "x": room.coord[0] as u32 * rooms_size[0],
+            "y": room.coord[1] as u32 * rooms_size[1],
+            "width": rooms_size[0],
+            "height": rooms_size[1],
+        }));
+    }
+
+    let name = map.path.file_stem().unwrap_or_default().to_string_lossy();
+    for (z,maps) in worlds {
+        let world = serde_json::json!({
+            "maps": maps,
+            "onlyShowAdjacentMaps": false,
+            "type": "world",
+        });
+        std::fs::write(dest.join(format!("{name}_z{z}.world")), serde_json::to_string_pretty(&world)?)
+            .context("Writing world")?;
+    }
+
+    Ok(failed)
+}
+
+/// Write the tileset as Tiled tileset, with a tile for every selection group of the sel matrix
+pub fn export_tileset_tsx(tileset: &Tileset, dest: &Path) -> anyhow::Result<()> {
+    let [w,h] = tileset.state.validate_size;
+    let image_path = tileset.path.canonicalize().unwrap_or_else(|_| tileset.path.clone() );
+    let dest_dir = dest.parent()
+        .filter(|p| !p.as_os_str().is_empty() )
+        .unwrap_or(Path::new("."))
+        .canonicalize().ok();
+    // relative if next to each other
+    let source = match image_path.file_name() {
+        Some(name) if image_path.parent() == dest_dir.as_deref() => PathBuf::from(name),
+        _ => image_path.clone(),
+    };
+    let source = xml_escape(&source.to_string_lossy());
+
+    let groups = sel_groups(&tileset.sel_matrix);
+    let max = groups.iter().fold([TILE;2], |[mw,mh],&(_,[gw,gh])| [mw.max(gw),mh.max(gh)] );
+
+    let mut out = String::new();
+    let _ = writeln!(out, "{TMX_HEADER}");
+    let _ = writeln!(
+        out, r#"<tileset version="1.10" tiledversion="1.10.2" name="{}" tilewidth="{}" tileheight="{}" tilecount="{}" columns="0">"#,
+        xml_escape(&tileset.state.title), max[0], max[1], groups.len(),
+    );
+    let _ = writeln!(out, r#" <grid orientation="orthogonal" width="{TILE}" height="{TILE}"/>"#);
+    for (i,([x,y],[gw,gh])) in groups.into_iter().enumerate() {
+        let _ = writeln!(out, r#" <tile id="{i}" x="{x}" y="{y}" width="{gw}" height="{gh}">"#);
+        let _ = writeln!(out, r#"  <image source="{source}" width="{w}" height="{h}"/>"#);
+        let _ = writeln!(out, " </tile>");
+    }
+    let _ = writeln!(out, "</tileset>");
+
+    std::fs::write(dest, out).context("Writing tileset")?;
+    Ok(())
+}
+
+/// Position and size of the selection groups, in pixels
+fn sel_groups(sel_matrix: &SelMatrix) -> Vec<([u32;2],[u32;2])> {
+    let [w,h] = sel_matrix.dims;
+    let mut groups = vec![];
+    for y in 0 .. h {
+        for x in 0 .. w {
+            let Some(entry) = sel_matrix.get([x,y]) else {continue};
+            if entry.start != [0,0] || entry.size[0] == 0 || entry.size[1] == 0 {continue;}
+            groups.push((
+                [x * TILE, y * TILE],
+                [entry.size[0] as u32 * TILE, entry.size[1] as u32 * TILE],
+            ));
+        }
+    }
+    groups
+}
+
+#[derive(clap::Args, Debug)]
+pub struct TiledArgs {
+    /// Map (.mzdmap) or tileset image to export
+    pub input: PathBuf,
+    /// Output folder for a map, or TSX file for a tileset. Defaults to the input path with _tiled or .tsx attached
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+pub fn tiled_cmd(args: TiledArgs) -> anyhow::Result<()> {
+    if args.input.extension().is_some_and(|e| e == "mzdmap" ) {
+        let mut uuidmap = Default::default();
+        let map = Map::load_map(args.input, &mut uuidmap).context("Loading map")?;
+        let output = args.output.unwrap_or_else(|| attached_to_path(&map.path, "_tiled") );
+
+        for e in export_map_tiled(&map, &output)? {
+            eprintln!("Failed to load room {e}");
+        }
+        eprintln!("Exported to {}", output.to_string_lossy());
+    } else {
+        let tileset = Tileset::load(args.input).context("Loading tileset")?;
+        let output = args.output.unwrap_or_else(|| attached_to_path(&tileset.path, ".tsx") );
+
+        export_tileset_tsx(&tileset, &output)?;
+        eprintln!("Exported to {}", output.to_string_lossy());
+    }
+
+    Ok(())
+}
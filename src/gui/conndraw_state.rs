@@ -2,14 +2,18 @@ use crate::map::coord_store::CoordStore;
 
 use super::map::room_ops::{OpAxis, try_side};
 use super::map::{MapEditMode, RoomMap, RoomId};
-use super::room::Room;
+use super::room::{ConnType, Room, CONN_NONE, CONN_ONEWAY_IN, CONN_OPEN};
 use super::util::ArrUtl;
 
 pub struct ConnDrawState {
     active: Option<[f32;2]>,
     mode: MapEditMode,
     z: u8,
-    connect: bool,
+    conn: ConnType,
+    /// The type painted by connecting
+    pub draw_type: ConnType,
+    /// Connect only in the direction the cursor moves, or from the clicked room in the Z modes
+    pub oneway: bool,
 }
 
 impl ConnDrawState {
@@ -18,7 +22,9 @@ impl ConnDrawState {
             active: None,
             mode: MapEditMode::DrawSel,
             z: 128,
-            connect: false,
+            conn: CONN_NONE,
+            draw_type: CONN_OPEN,
+            oneway: false,
         }
     }
 
@@ -30,48 +36,61 @@ impl ConnDrawState {
             self.active = Some(pos);
             self.mode = mode;
             self.z = z;
-            self.connect = connect;
+            self.conn = if connect {self.draw_type} else {CONN_NONE};
         }
 
-        let mut set_dir = |room: &mut Room,ax: OpAxis,dir: bool| {
-            room.dirconn[ax.axis_idx()][dir as usize] = self.connect;
+        let conn = self.conn;
+        let oneway = self.oneway && conn != CONN_NONE;
+
+        let mut set_dir = |room: &mut Room,ax: OpAxis,dir: bool,v: ConnType| {
+            room.dirconn[ax.axis_idx()][dir as usize] = v;
             inval();
         };
 
-        let mut set_cd = |coord: [u8;3],ax: OpAxis,dir: bool| {
+        // out: the one-way connection leads from the room at coord to the side
+        let mut set_cd = |coord: [u8;3],ax: OpAxis,dir: bool,out: bool| {
+            let (here,there) = match (oneway,out) {
+                (false,_) => (conn,conn),
+                (true,true) => (conn,conn | CONN_ONEWAY_IN),
+                (true,false) => (conn | CONN_ONEWAY_IN,conn),
+            };
             if let Some(&id) = matrix.get(coord) {
                 if let Some(room) = rooms.get_mut(id) {
-                    set_dir(room,ax,dir);
+                    set_dir(room,ax,dir,here);
                 }
             }
             try_side(coord, ax, dir, |c2| {
                 if let Some(&id) = matrix.get(c2) {
                     if let Some(room) = rooms.get_mut(id) {
-                        set_dir(room,ax,!dir);
+                        set_dir(room,ax,!dir,there);
                     }
                 }
             });
         };
 
-        let (prev_coord,prev_cl) = quantize_detect(self.active.unwrap(), rooms_size);
+        let prev_pos = self.active.unwrap();
+        let (prev_coord,prev_cl) = quantize_detect(prev_pos, rooms_size);
         let (coord,cl) = quantize_detect(pos, rooms_size);
 
         if self.mode == MapEditMode::ConnUp {
-            set_cd([coord[0],coord[1],self.z],OpAxis::Z,true);
+            set_cd([coord[0],coord[1],self.z],OpAxis::Z,true,true);
         }
         if self.mode == MapEditMode::ConnDown {
-            set_cd([coord[0],coord[1],self.z],OpAxis::Z,false);
+            set_cd([coord[0],coord[1],self.z],OpAxis::Z,false,true);
         }
 
         if self.mode == MapEditMode::ConnXY {
             if prev_cl == Outor::Center && prev_coord != coord {
                 let cl2 = detect_super_fast_move(prev_coord, coord);
                 if let Outor::Side(ax,dir) = cl2 {
-                    set_cd([coord[0],coord[1],self.z],ax,dir);
+                    // moved from the side into this room
+                    set_cd([coord[0],coord[1],self.z],ax,dir,false);
                 }
             }
             if let Outor::Side(ax,dir) = cl {
-                set_cd([coord[0],coord[1],self.z],ax,dir);
+                let delta = pos[ax.axis_idx()] - prev_pos[ax.axis_idx()];
+                let out = if dir {delta >= 0.} else {delta <= 0.};
+                set_cd([coord[0],coord[1],self.z],ax,dir,out);
             }
         }
         
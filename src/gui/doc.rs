@@ -14,5 +14,7 @@ pub const DOC_MAP_SINGLEMOVE: &str = "Move a single room";
 pub const DOC_MAP_SHIFTAWAY: &str = "Move all rooms in the direction (including current row) into the direction, leaving a gap across the entire map.";
 pub const DOC_MAP_COLLAPSE: &str = "Move all rooms from the direction towards selected coord. Reqires gap on the current row across the entire map.";
 pub const DOC_MAP_SMARTMOVE: &str = "Move a connected group of rooms. Affected rooms are highlighted on hover.";
+pub const DOC_MAP_GROUPMOVE: &str = "Move the group of rooms by one. Refused if any room would collide.";
+pub const DOC_MAP_GROUPDUP: &str = "Duplicate the group of rooms next to itself in the direction.";
 pub const DOC_MAP_SHIFTSIZE: &str = "By how much rooms should be moved with the move ops below.";
 pub const DOC_MAP_AWAYLOCK: &str = "If enabled, refuse moving group of rooms if rooms TODO";
@@ -4,6 +4,7 @@ use crate::util::{MapId, TilesetId};
 
 use super::init::SharedApp;
 use super::palette::{lru_ui, palette_ui};
+use super::search::search_ui;
 
 pub struct Docky {
     state: Option<DockState<DockTab>>,
@@ -32,7 +33,7 @@ impl Docky {
 fn create_initial() -> DockState<DockTab> {
     let mut state = DockState::new(vec![DockTab::Draw]);
     let surf = state.main_surface_mut();
-    let [orig,_] = surf.split_left(NodeIndex::root(), 0.5, vec![DockTab::Palette]);
+    let [orig,_] = surf.split_left(NodeIndex::root(), 0.5, vec![DockTab::Palette, DockTab::Search]);
     surf.split_right(orig, 0.9, vec![DockTab::Lru]);
     //surf.split_above(left, 0.01, vec![]);
     state
@@ -45,6 +46,7 @@ pub enum DockTab {
     Palette,
     Lru,
     Draw,
+    Search,
 }
 
 impl DockTab {
@@ -55,6 +57,7 @@ impl DockTab {
             DockTab::Palette => matches!(other,Self::Palette),
             DockTab::Lru => matches!(other,Self::Lru),
             DockTab::Draw => matches!(other,Self::Draw),
+            DockTab::Search => matches!(other,Self::Search),
         }
     }
 }
@@ -225,6 +228,7 @@ impl TabViewer for TabV<'_> {
             DockTab::Palette => "Palette".into(),
             DockTab::Lru => "LastUsed".into(),
             DockTab::Draw => "Draw".into(),
+            DockTab::Search => "Search".into(),
         }
     }
 
@@ -250,6 +254,7 @@ impl TabViewer for TabV<'_> {
             },
             DockTab::Palette => palette_ui(self.0, ui),
             DockTab::Lru => lru_ui(self.0, ui),
+            DockTab::Search => search_ui(self.0, ui),
             DockTab::Draw => if let Some(map) = self.0.dock.last_focused_map.and_then(|id| self.0.maps.open_maps.get_mut(&id)) {
                 let mut map = map.borrow_mut();
                 map.ui_draw(
@@ -268,6 +273,7 @@ impl TabViewer for TabV<'_> {
             DockTab::Palette => egui::Id::new("Palette"),
             DockTab::Lru => egui::Id::new("LruPalette"),
             DockTab::Draw => egui::Id::new("Draw"),
+            DockTab::Search => egui::Id::new("Search"),
         }
     }
 
@@ -30,7 +30,15 @@ impl SharedApp {
             self.top_panel.last_map_path.get_or_insert(path);
             ctx.request_repaint();
         } else if let Ok(img) = load_image_off_thread(&path).inspect_err(|e| eprintln!("Failed to load dropped image {}: {e}", path.to_string_lossy()) ) {
-            self.try_load_tileset(path, img.to_rgba8());
+            let drop_map = self.dock.last_focused_map
+                .and_then(|id| self.maps.open_maps.get(&id) )
+                .filter(|m| m.borrow().state.drop_profile.is_some() );
+            if let Some(map) = drop_map {
+                map.borrow_mut().import_dropped_image(&img.to_rgba8(), &mut self.sam.uuidmap)
+                    .unwrap_gui("Failed to import dropped image");
+            } else {
+                self.try_load_tileset(path, img.to_rgba8());
+            }
             ctx.request_repaint();
         }
     }
@@ -14,6 +14,7 @@ use super::tags::WarpUR;
 use super::{MutQueue, dpi_hack};
 use super::map::RoomId;
 use super::palette::Palette;
+use super::search::Search;
 use super::top_panel::{TopPanel, top_panel_ui};
 use super::window_states::map::Maps;
 use super::window_states::tileset::Tilesets;
@@ -44,6 +45,7 @@ pub struct SharedApp {
     pub maps: Maps,
     pub tilesets: Tilesets,
     pub palette: Palette,
+    pub search: Search,
     pub init_load_paths: Vec<PathBuf>,
     pub sam: SAM,
     pub dock: Docky,
@@ -68,6 +70,7 @@ impl SharedApp {
             maps: Maps::new(),
             tilesets: Tilesets::new(),
             palette: Palette::new(),
+            search: Search::new(),
             sam: SAM {
                 dpi_scale: 0.,
                 mut_queue: vec![],
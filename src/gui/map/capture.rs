@@ -0,0 +1,299 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use image::RgbaImage;
+
+use crate::gui::room::{CONN_NONE, CONN_OPEN};
+use crate::gui::util::RfdUtil;
+use crate::util::img::load_image;
+use crate::util::gui_error;
+
+use super::import_profile::{ui_import_profile_select, write_room_layer};
+use super::room_ops::{describe_direction, try_side, OpAxis, RoomOp};
+use super::uuid::UUIDMap;
+use super::{next_ur_op_id, Map};
+
+const POLL_INTERVAL: f64 = 0.5;
+
+const IMAGE_EXTENSIONS: &[&str] = &["png","jpg","jpeg","bmp","gif","webp","qoi"];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CaptureFit {
+    /// Cut out the center, or center smaller screenshots
+    Crop,
+    /// Stretch to the rooms size
+    Scale,
+}
+
+pub struct CaptureState {
+    pub dir_path: PathBuf,
+    pub axis: OpAxis,
+    pub dir: bool,
+    pub fit: CaptureFit,
+    pub profile: Option<usize>,
+    pub placed: usize,
+    /// Files present at the start or already placed
+    seen: HashSet<PathBuf>,
+    /// Size at the last poll, new files are placed once it stops changing
+    pending: HashMap<PathBuf,u64>,
+    last_poll: f64,
+    /// The undo entry of the session, which is extended as long as it is the latest one
+    undo_id: Option<u64>,
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| IMAGE_EXTENSIONS.iter().any(|i| e.eq_ignore_ascii_case(i) ) )
+}
+
+fn list_images(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && is_image(&path) {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+pub(super) fn fit_image(image: RgbaImage, rooms_size: [u32;2], fit: CaptureFit) -> RgbaImage {
+    let [w,h] = rooms_size;
+    match fit {
+        CaptureFit::Scale => image::imageops::resize(&image, w, h, image::imageops::FilterType::Nearest),
+        CaptureFit::Crop => {
+            let mut dest = RgbaImage::new(w, h);
+            let x = (w as i64 - image.width() as i64) / 2;
+            let y = (h as i64 - image.height() as i64) / 2;
+            image::imageops::replace(&mut dest, &image, x, y);
+            dest
+        },
+    }
+}
+
+impl CaptureState {
+    /// New image files whose size didn't change since the last poll, oldest first
+    fn poll(&mut self) -> anyhow::Result<Vec<PathBuf>> {
+        let mut ready = vec![];
+        for path in list_images(&self.dir_path)? {
+            if self.seen.contains(&path) {continue;}
+            let Ok(meta) = std::fs::metadata(&path) else {continue};
+            let size = meta.len();
+            if size != 0 && self.pending.get(&path) == Some(&size) {
+                self.pending.remove(&path);
+                self.seen.insert(path.clone());
+                ready.push((meta.modified().ok(),path));
+            } else {
+                self.pending.insert(path, size);
+            }
+        }
+        ready.sort();
+        Ok(ready.into_iter().map(|(_,p)| p ).collect())
+    }
+}
+
+impl Map {
+    pub(super) fn toggle_capture(&mut self) {
+        if self.capture.take().is_some() {return;}
+
+        if self.state.ssel_coord.is_none() {
+            gui_error("Capture", "Select the room to start from");
+            return;
+        }
+
+        let mut dialog = rfd::FileDialog::new()
+            .set_title("Capture screenshots from folder");
+        if let Some(dir) = self.path.parent() {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(dir_path) = dialog.try_set_parent().pick_folder() else {return};
+
+        let seen = match list_images(&dir_path) {
+            Ok(v) => v.into_iter().collect(),
+            Err(e) => {
+                gui_error("Failed to read capture folder", e);
+                return;
+            },
+        };
+
+        self.capture = Some(CaptureState {
+            dir_path,
+            axis: OpAxis::X,
+            dir: true,
+            fit: CaptureFit::Crop,
+            profile: None,
+            placed: 0,
+            seen,
+            pending: Default::default(),
+            last_poll: 0.,
+            undo_id: None,
+        });
+    }
+
+    pub(super) fn ui_capture_window(&mut self, ctx: &egui::Context, uuidmap: &mut UUIDMap) {
+        let profiles = &self.state.import_profiles;
+        let Some(cs) = &mut self.capture else {return};
+
+        let mut open = true;
+
+        let resp = egui::Window::new(format!("Capture - {}", self.state.title))
+            .id(self.id.egui_id_map().with("capture"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label(format!("Watching {}", cs.dir_path.to_string_lossy()));
+                ui.label(format!("{} screens placed", cs.placed));
+                ui.horizontal(|ui| {
+                    ui.label("Next room");
+                    for (axis,dir) in [(OpAxis::X,false),(OpAxis::X,true),(OpAxis::Y,false),(OpAxis::Y,true),(OpAxis::Z,true),(OpAxis::Z,false)] {
+                        if ui.selectable_label(cs.axis == axis && cs.dir == dir, describe_direction(axis, dir)).clicked() {
+                            cs.axis = axis;
+                            cs.dir = dir;
+                        }
+                    }
+                }).response.on_hover_text("Side of the selected room where the next screenshot is placed. With the mouse over this window, also set with the arrow keys and PageUp/PageDown");
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut cs.fit, CaptureFit::Crop, "Crop");
+                    ui.radio_value(&mut cs.fit, CaptureFit::Scale, "Scale");
+                }).response.on_hover_text("Cut out the center of screenshots of another size, or stretch them to the rooms size");
+                ui.horizontal(|ui| {
+                    ui.label("Profile");
+                    ui_import_profile_select(ui, self.id.egui_id_map().with("capture_profile"), profiles, &mut cs.profile);
+                });
+            });
+
+        if resp.is_some_and(|r| r.response.contains_pointer() ) && !ctx.wants_keyboard_input() {
+            let keys = [
+                (egui::Key::ArrowLeft, OpAxis::X, false),
+                (egui::Key::ArrowRight, OpAxis::X, true),
+                (egui::Key::ArrowUp, OpAxis::Y, false),
+                (egui::Key::ArrowDown, OpAxis::Y, true),
+                (egui::Key::PageUp, OpAxis::Z, true),
+                (egui::Key::PageDown, OpAxis::Z, false),
+            ];
+            for (key,axis,dir) in keys {
+                if ctx.input(|i| i.key_pressed(key) ) {
+                    cs.axis = axis;
+                    cs.dir = dir;
+                }
+            }
+        }
+
+        if !open {
+            self.capture = None;
+            return;
+        }
+
+        ctx.request_repaint_after(Duration::from_secs_f64(POLL_INTERVAL));
+        let now = ctx.input(|i| i.time );
+        if now - cs.last_poll < POLL_INTERVAL {return;}
+        cs.last_poll = now;
+
+        let ready = match cs.poll() {
+            Ok(v) => v,
+            Err(e) => {
+                gui_error("Failed to read capture folder", e);
+                self.capture = None;
+                return;
+            },
+        };
+
+        for path in ready {
+            if let Err(e) = self.capture_place(&path, uuidmap) {
+                gui_error(&format!("Failed to place {}", path.to_string_lossy()), e);
+            }
+        }
+    }
+
+    /// Place the screenshot next to the selected room, or at the selected coord if there is no room yet, and select it
+    fn capture_place(&mut self, path: &Path, uuidmap: &mut UUIDMap) -> anyhow::Result<()> {
+        let image = load_image(path).context("Loading image")?.to_rgba8();
+
+        let mut undo = vec![];
+        let result = self.capture_place_ops(image, &mut undo, uuidmap);
+
+        // one undo entry for the whole session, also for the ops applied before a failure
+        if !undo.is_empty() {
+            undo.reverse();
+            let mut ur = RoomOp::Multi(undo);
+            let cs = self.capture.as_mut().unwrap();
+            if let Some(id) = cs.undo_id && self.undo_buf.back().is_some_and(|&(_,i)| i == id ) {
+                let (prev,_) = self.undo_buf.pop_back().unwrap();
+                ur = RoomOp::Multi(vec![ur,prev]);
+            }
+            let id = next_ur_op_id();
+            cs.undo_id = Some(id);
+            self.undo_buf.push_back((ur,id));
+            self.after_room_op_apply_invalidation(false);
+        }
+
+        let dest = result?;
+
+        self.capture.as_mut().unwrap().placed += 1;
+        self.ssel_room = self.room_matrix.get(dest).cloned();
+        self.state.ssel_coord = Some(dest);
+        self.ssel_updated();
+        self.update_level(dest[2]);
+        self.move_viewpos_centred([dest[0],dest[1]]);
+
+        Ok(())
+    }
+
+    fn capture_place_ops(&mut self, image: RgbaImage, undo: &mut Vec<RoomOp>, uuidmap: &mut UUIDMap) -> anyhow::Result<[u8;3]> {
+        let cs = self.capture.as_ref().context("Capture not running")?;
+        let (axis,dir) = (cs.axis,cs.dir);
+        let (image,layer) = self.prepare_import_image(&image, cs.profile, cs.fit);
+
+        let from = self.ssel_room
+            .and_then(|id| Some((id,self.state.rooms.get(id)?)) )
+            .filter(|(_,r)| !r.transient )
+            .map(|(id,r)| (id,r.coord) );
+        let dest = match from {
+            Some((_,c)) => try_side(c, axis, dir, |c2| c2 ).context("The selected room is at the border of the map")?,
+            None => self.state.ssel_coord.context("No room selected")?,
+        };
+
+        if let Some((from_room,from_coord)) = from && self.room_matrix.get(dest).is_some() {
+            // push the rooms in the way away from the selected room
+            let shift = self.shift_smart_new_collect(dest, Some(from_coord), false, axis, dir, true)
+                .filter(|v| !v.rooms.contains(&from_room) )
+                .context("The rooms in the way can't be pushed away")?;
+            self.capture_apply(RoomOp::SiftSmart(shift, true), undo, uuidmap)?;
+        }
+
+        let op = self.create_image_room(dest, image, layer, uuidmap)
+            .with_context(|| format!("X{} Y{} Z{} is occupied", dest[0], dest[1], dest[2]) )?;
+        self.capture_apply(op, undo, uuidmap)?;
+
+        // the pushed away rooms were connected to the selected room
+        let dangling = try_side(dest, axis, dir, |c2| self.room_matrix.get(c2).cloned() )
+            .flatten()
+            .is_some_and(|id| self.state.rooms[id].dirconn[axis.axis_idx()][!dir as usize] != CONN_NONE );
+        if dangling {
+            self.capture_apply(RoomOp::Conn(dest, axis, dir, [CONN_NONE,CONN_NONE]), undo, uuidmap)?;
+        }
+
+        if let Some((_,from_coord)) = from {
+            self.capture_apply(RoomOp::Conn(from_coord, axis, dir, [CONN_OPEN,CONN_OPEN]), undo, uuidmap)?;
+        }
+
+        Ok(dest)
+    }
+
+    /// Room with the image in the layer, with empty layers below
+    pub(super) fn create_image_room(&mut self, coord: [u8;3], image: RgbaImage, layer: usize, uuidmap: &mut UUIDMap) -> Option<RoomOp> {
+        let Some(RoomOp::Ins(mut room)) = self.create_create_room(coord, uuidmap) else {return None};
+        write_room_layer(&mut room, layer, &image, self.state.rooms_size);
+        Some(RoomOp::Ins(room))
+    }
+
+    fn capture_apply(&mut self, op: RoomOp, undo: &mut Vec<RoomOp>, uuidmap: &mut UUIDMap) -> anyhow::Result<()> {
+        let mut msg = String::new();
+        if !self.validate_apply(&op, &mut msg) {
+            bail!("{msg}");
+        }
+        undo.push(self.apply_room_op(op, uuidmap));
+        Ok(())
+    }
+}
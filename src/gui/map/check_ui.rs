@@ -0,0 +1,162 @@
+use crate::gui::init::SAM;
+use crate::map::coord_store::CoordStore;
+use crate::map::fsck::{check_map, repair_map, CheckReport, IssueAt};
+use crate::map::gc::{delete_orphans, find_orphans, format_size, quarantine_orphans, referenced_resources, OrphanReport};
+use crate::util::uuid::UUIDTarget;
+use crate::util::ResultExt;
+
+use super::Map;
+
+pub struct CheckWindow {
+    report: CheckReport,
+    repair_log: Vec<String>,
+    orphans: Option<OrphanReport>,
+}
+
+impl Map {
+    pub(super) fn run_check(&self, sam: &SAM) -> CheckReport {
+        check_map(&self.state, &self.path, |warp| {
+            match sam.uuidmap.get(&warp.dest_map) {
+                Some(&UUIDTarget::Map(dest_map)) => Some(matches!(
+                    sam.uuidmap.get(&warp.dest_room),
+                    Some(&UUIDTarget::Room(map,_)) if map == dest_map
+                )),
+                _ => None,
+            }
+        })
+    }
+
+    pub(super) fn open_check_window(&mut self, sam: &SAM) {
+        let report = self.run_check(sam);
+        self.check_window = Some(CheckWindow {
+            report,
+            repair_log: vec![],
+            orphans: self.find_orphans(sam),
+        });
+    }
+
+    fn find_orphans(&self, sam: &SAM) -> Option<OrphanReport> {
+        // deleted rooms in the undo buffer still hold their resources in the uuidmap
+        let mut referenced = referenced_resources(&self.state);
+        referenced.extend(
+            sam.uuidmap.iter()
+                .filter(|(_,t)| matches!(t, &&(UUIDTarget::Resource(map,_) | UUIDTarget::SharedResource(map,_)) if map == self.id) )
+                .map(|(&uuid,_)| uuid )
+        );
+
+        find_orphans(&self.path, &referenced).unwrap_gui("Failed to search unreferenced files")
+    }
+
+    pub(super) fn ui_check_window(&mut self, ctx: &egui::Context, sam: &SAM) {
+        let Some(window) = &self.check_window else {return};
+
+        let mut open = true;
+        let mut recheck = false;
+        let mut repair = false;
+        let mut jump_to = None;
+        let mut orphans_delete = false;
+        let mut orphans_quarantine = false;
+
+        egui::Window::new(format!("Check - {}", self.state.title))
+            .id(self.id.egui_id_map().with("check"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    recheck = ui.button("Recheck").clicked();
+                    repair = ui.add_enabled(!window.report.is_empty(), egui::Button::new("Repair"))
+                        .on_hover_text("Move colliding rooms, disconnect one-sided connections, remove dangling warps and recreate broken room files. Clears undo.")
+                        .clicked();
+                });
+                ui.separator();
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    if window.report.is_empty() {
+                        ui.label("No issues found");
+                    }
+                    for issue in &window.report.issues {
+                        let resp = ui.selectable_label(false, issue.to_string());
+                        if let IssueAt::Room(_, coord) = issue.at && resp.clicked() {
+                            jump_to = Some(coord);
+                        }
+                    }
+                    if !window.repair_log.is_empty() {
+                        ui.separator();
+                        for line in &window.repair_log {
+                            ui.label(line);
+                        }
+                    }
+                    if let Some(orphans) = &window.orphans && !orphans.is_empty() {
+                        ui.separator();
+                        ui.horizontal(|ui| {
+                            ui.label(format!("{} unreferenced files, {}", orphans.files.len(), format_size(orphans.total_size())));
+                            orphans_delete = ui.button("Delete").on_hover_text("Must be double clicked").double_clicked();
+                            orphans_quarantine = ui.button("Quarantine").on_hover_text("Move into the quarantine folder in the _data folder").clicked();
+                        });
+                        egui::CollapsingHeader::new("Files").show(ui, |ui| {
+                            for (path,size) in &orphans.files {
+                                ui.label(format!("{} ({})", path.to_string_lossy(), format_size(*size)));
+                            }
+                        });
+                    }
+                });
+            });
+
+        if (orphans_delete || orphans_quarantine) && let Some(orphans) = &window.orphans {
+            if orphans_delete {
+                delete_orphans(orphans).unwrap_gui("Failed to delete unreferenced files");
+            } else {
+                quarantine_orphans(orphans, &self.path).unwrap_gui("Failed to quarantine unreferenced files");
+            }
+            recheck = true;
+        }
+
+        if let Some([x,y,z]) = jump_to {
+            self.move_viewpos_centred([x,y]);
+            self.update_level(z);
+        }
+
+        if repair {
+            self.repair_check_issues(sam);
+        } else if recheck {
+            self.open_check_window(sam);
+        }
+
+        if !open {
+            self.check_window = None;
+        }
+    }
+
+    fn repair_check_issues(&mut self, sam: &SAM) {
+        let Some(window) = self.check_window.take() else {return};
+
+        let repair_log = repair_map(&mut self.state, &self.path, &window.report);
+
+        // rooms with repaired files may be loaded again
+        for issue in &window.report.issues {
+            if !issue.kind.is_resource() {continue;}
+            let room = match issue.at {
+                IssueAt::Room(id, _) => self.state.rooms.get_mut(id),
+                IssueAt::Template(i) => self.state.quickroom_template.get_mut(i).and_then(Option::as_mut),
+            };
+            if let Some(room) = room {
+                room.locked = None;
+            }
+        }
+
+        self.room_matrix = CoordStore::new();
+        for (id,room) in &self.state.rooms {
+            if room.transient {continue;}
+            self.room_matrix.insert(room.coord, id);
+        }
+
+        self.undo_buf.clear();
+        self.redo_buf.clear();
+        self.after_room_op_apply_invalidation(false);
+
+        let report = self.run_check(sam);
+        self.check_window = Some(CheckWindow {
+            report,
+            repair_log,
+            orphans: self.find_orphans(sam),
+        });
+    }
+}
@@ -0,0 +1,169 @@
+use egui::{Color32, RichText};
+use serde::{Deserialize, Serialize};
+
+use crate::gui::room::{conn_kind, ConnType, CONN_ONEWAY_IN, CONN_OPEN};
+
+use super::Map;
+
+/// A user definable kind of connection, like a locked door or a bombable wall
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct ConnTypeDef {
+    pub name: String,
+    pub color: [u8;3],
+    /// Short text shown at the connection
+    #[serde(default)]
+    pub icon: String,
+    /// The item needed to pass, empty if none
+    #[serde(default)]
+    pub requires: String,
+    /// Passing the first time uses up one of the required item, like a small key
+    #[serde(default)]
+    pub consumes: bool,
+}
+
+impl ConnTypeDef {
+    fn new(name: &str, color: [u8;3], icon: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            color,
+            icon: icon.to_owned(),
+            requires: String::new(),
+            consumes: false,
+        }
+    }
+
+    fn with_lock(mut self, requires: &str, consumes: bool) -> Self {
+        self.requires = requires.to_owned();
+        self.consumes = consumes;
+        self
+    }
+
+    pub fn color32(&self) -> Color32 {
+        let [r,g,b] = self.color;
+        Color32::from_rgb(r, g, b)
+    }
+}
+
+/// The first is the open type, which connections of older maps are
+pub fn default_conn_types() -> Vec<ConnTypeDef> {
+    vec![
+        ConnTypeDef::new("Open", [0,255,0], ""),
+        ConnTypeDef::new("Locked", [255,200,0], "L").with_lock("Small Key", true),
+        ConnTypeDef::new("Bombable", [255,110,0], "B"),
+        ConnTypeDef::new("Shutter", [150,150,255], "S"),
+        ConnTypeDef::new("Stairs", [0,200,255], "H"),
+        ConnTypeDef::new("Boss", [200,0,255], "K").with_lock("Big Key", false),
+    ]
+}
+
+/// Color and icon of a connection, unknown types are magenta
+pub fn conn_style(conn_types: &[ConnTypeDef], v: ConnType) -> (Color32,&str) {
+    match (conn_kind(v) as usize).checked_sub(1).and_then(|i| conn_types.get(i) ) {
+        Some(t) => (t.color32(), &t.icon),
+        None => (Color32::from_rgb(255, 0, 255), "?"),
+    }
+}
+
+/// Name of the type of a connection, for exports
+pub fn conn_type_name(conn_types: &[ConnTypeDef], v: ConnType) -> String {
+    match (conn_kind(v) as usize).checked_sub(1).and_then(|i| conn_types.get(i) ) {
+        Some(t) => t.name.clone(),
+        None => format!("Type {}", conn_kind(v)),
+    }
+}
+
+impl Map {
+    /// Select the type painted in the connect modes
+    pub(super) fn ui_conn_type_select(&mut self, ui: &mut egui::Ui) {
+        if self.state.conn_types.is_empty() {
+            self.state.conn_types = default_conn_types();
+        }
+        if self.cd_state.draw_type as usize > self.state.conn_types.len() {
+            self.cd_state.draw_type = CONN_OPEN;
+        }
+
+        let selected = &self.state.conn_types[self.cd_state.draw_type as usize - 1];
+        egui::ComboBox::from_id_salt(self.id.egui_id_map().with("conn_type"))
+            .selected_text(RichText::new(&selected.name).color(selected.color32()))
+            .show_ui(ui, |ui| {
+                for (i,t) in self.state.conn_types.iter().enumerate() {
+                    let text = RichText::new(format!("{} {}", t.name, t.icon)).color(t.color32());
+                    ui.selectable_value(&mut self.cd_state.draw_type, i as ConnType + 1, text);
+                }
+            })
+            .response
+            .on_hover_text("Type of the connections made with left click");
+        ui.checkbox(&mut self.cd_state.oneway, "One-way")
+            .on_hover_text("Connect only in the direction the cursor moves from room to room. In ConnZ modes, from the clicked room up or down");
+
+        if ui.button("Conn Types").on_hover_text("Edit the connection types of the map").clicked() {
+            self.conn_types_window = !self.conn_types_window;
+        }
+    }
+
+    pub(super) fn ui_conn_types_window(&mut self, ctx: &egui::Context) {
+        if !self.conn_types_window {return;}
+        if self.state.conn_types.is_empty() {
+            self.state.conn_types = default_conn_types();
+        }
+
+        let used = self.conn_type_usage();
+        let mut open = true;
+
+        egui::Window::new(format!("Connection Types - {}", self.state.title))
+            .id(self.id.egui_id_map().with("conn_types"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let last = self.state.conn_types.len() - 1;
+                let mut remove = false;
+
+                egui::Grid::new(self.id.egui_id_map().with("conn_types_grid")).show(ui, |ui| {
+                    for (i,t) in self.state.conn_types.iter_mut().enumerate() {
+                        ui.color_edit_button_srgb(&mut t.color);
+                        ui.add(egui::TextEdit::singleline(&mut t.name).desired_width(120.));
+                        ui.add(egui::TextEdit::singleline(&mut t.icon).hint_text("Icon").desired_width(32.));
+                        ui.add(egui::TextEdit::singleline(&mut t.requires).hint_text("Requires item").desired_width(100.))
+                            .on_hover_text("The item a tag must give, to pass the connection");
+                        ui.checkbox(&mut t.consumes, "Consume")
+                            .on_hover_text("Opening uses up one of the item, like a small key");
+                        ui.label(format!("{} sides", used[i]));
+                        // only the last, as the connections refer to the types by index
+                        if i == last && i != 0 {
+                            remove = ui.add_enabled(used[i] == 0, egui::Button::new("Delete"))
+                                .on_disabled_hover_text("The type is still used")
+                                .clicked();
+                        }
+                        ui.end_row();
+                    }
+                });
+
+                if remove {
+                    self.state.conn_types.pop();
+                }
+
+                if self.state.conn_types.len() < CONN_ONEWAY_IN as usize - 1 && ui.button("Add").clicked() {
+                    let name = format!("Type {}", self.state.conn_types.len() + 1);
+                    self.state.conn_types.push(ConnTypeDef::new(&name, [255,255,255], ""));
+                }
+            });
+
+        if !open {
+            self.conn_types_window = false;
+        }
+    }
+
+    /// Number of connected room sides of every type
+    fn conn_type_usage(&self) -> Vec<usize> {
+        let mut used = vec![0; self.state.conn_types.len()];
+        let rooms = self.state.rooms.values()
+            .chain(self.state.quickroom_template.iter().flatten());
+        for room in rooms {
+            for &v in room.dirconn.iter().flatten() {
+                if let Some(n) = (conn_kind(v) as usize).checked_sub(1).and_then(|i| used.get_mut(i) ) {
+                    *n += 1;
+                }
+            }
+        }
+        used
+    }
+}
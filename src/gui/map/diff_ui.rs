@@ -0,0 +1,255 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use egui::{Color32, CornerRadius, Sense, StrokeKind, TextureHandle, TextureOptions};
+use uuid::Uuid;
+
+use crate::export::load_room_image;
+use crate::gui::rector;
+use crate::gui::room::draw_image::DrawImage;
+use crate::gui::texture::{basic_tex_shape, color_image_of_image_area};
+use crate::gui::util::{alloc_painter_rel, ArrUtl, RfdUtil};
+use crate::map::diff::{diff_maps, load_old_room_image, DiffKind, MapDiff, RoomDiff};
+use crate::util::ResultExt;
+
+use super::{Map, MapState};
+
+pub struct DiffView {
+    old_path: PathBuf,
+    old: MapState,
+    diff: MapDiff,
+    selected: Option<Uuid>,
+    compare: Option<RoomCompare>,
+    blink: bool,
+}
+
+/// Old and new texture of every layer of the selected room
+struct RoomCompare {
+    uuid: Uuid,
+    layers: Vec<[Option<TextureHandle>;2]>,
+}
+
+pub fn diff_color(kind: DiffKind) -> Color32 {
+    match kind {
+        DiffKind::Added => Color32::from_rgb(0, 200, 0),
+        DiffKind::Removed => Color32::from_rgb(220, 0, 0),
+        DiffKind::Moved => Color32::from_rgb(255, 140, 0),
+        DiffKind::Modified => Color32::from_rgb(230, 210, 0),
+    }
+}
+
+impl Map {
+    pub(super) fn open_diff_dialog(&mut self) {
+        let mut dialog = rfd::FileDialog::new()
+            .add_filter("mzdmap", &["mzdmap","bak"])
+            .set_title("Compare with map file");
+        if let Some(dir) = self.path.parent() {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(path) = dialog.try_set_parent().pick_file() else {return};
+
+        let Some(old) = MapState::read_file(&path).unwrap_gui("Failed to read map to compare") else {return};
+
+        self.diff_view = Some(DiffView {
+            diff: diff_maps(&old, &path, &self.state, &self.path),
+            old_path: path,
+            old,
+            selected: None,
+            compare: None,
+            blink: false,
+        });
+    }
+
+    fn refresh_diff(&mut self) {
+        let Some(view) = &mut self.diff_view else {return};
+        view.diff = diff_maps(&view.old, &view.old_path, &self.state, &self.path);
+        view.compare = None;
+    }
+
+    /// Select the changed room at coord on the current level, if any
+    pub(super) fn diff_select_at(&mut self, coord: [u8;3]) {
+        let Some(view) = &mut self.diff_view else {return};
+        let hit = view.diff.rooms.iter()
+            .find(|r| r.new_coord == Some(coord) )
+            .or_else(|| view.diff.rooms.iter().find(|r| r.new_coord.is_none() && r.old_coord == Some(coord) ) );
+        if let Some(room) = hit {
+            view.selected = Some(room.uuid);
+        }
+    }
+
+    pub(super) fn diff_overlay_shapes(&self, mut dest: impl FnMut(egui::Shape)) {
+        let Some(view) = &self.diff_view else {return};
+        let level = self.state.current_level;
+        let rs = self.state.rooms_size;
+
+        let room_rect = |[x,y,_]: [u8;3], inset: u32| rector(
+            x as u32 * rs[0] + inset, y as u32 * rs[1] + inset,
+            (x as u32 + 1) * rs[0] - inset, (y as u32 + 1) * rs[1] - inset,
+        );
+        let center = |[x,y,_]: [u8;3]| egui::pos2(
+            (x as f32 + 0.5) * rs[0] as f32,
+            (y as f32 + 0.5) * rs[1] as f32,
+        );
+
+        for room in &view.diff.rooms {
+            let color = diff_color(room.kind);
+            let width = if view.selected == Some(room.uuid) {4.} else {2.};
+            let stroke = egui::Stroke::new(width, color);
+
+            if let Some(coord) = room.new_coord && coord[2] == level {
+                dest(egui::Shape::rect_filled(room_rect(coord, 0), CornerRadius::ZERO, color.gamma_multiply(0.15)));
+                dest(egui::Shape::rect_stroke(room_rect(coord, 2), CornerRadius::ZERO, stroke, StrokeKind::Inside));
+            }
+
+            if let Some(old) = room.old_coord && old[2] == level {
+                if room.kind == DiffKind::Removed {
+                    let rect = room_rect(old, 2);
+                    dest(egui::Shape::rect_stroke(rect, CornerRadius::ZERO, stroke, StrokeKind::Inside));
+                    dest(egui::Shape::line_segment([rect.left_top(), rect.right_bottom()], stroke));
+                    dest(egui::Shape::line_segment([rect.right_top(), rect.left_bottom()], stroke));
+                } else if room.is_moved() {
+                    let thin = egui::Stroke::new(1., color);
+                    dest(egui::Shape::rect_stroke(room_rect(old, 4), CornerRadius::ZERO, thin, StrokeKind::Inside));
+                }
+            }
+
+            // only if both ends are on this level
+            if room.is_moved() && let (Some(old),Some(new)) = (room.old_coord,room.new_coord) && old[2] == level && new[2] == level {
+                dest(egui::Shape::line_segment([center(old), center(new)], stroke));
+                dest(egui::Shape::circle_filled(center(new), 4., color));
+            }
+        }
+    }
+
+    pub(super) fn ui_diff_window(&mut self, ctx: &egui::Context) {
+        let Some(view) = &mut self.diff_view else {return};
+
+        let mut open = true;
+        let mut refresh = false;
+        let mut jump_to = None;
+
+        egui::Window::new(format!("Diff - {}", self.state.title))
+            .id(self.id.egui_id_map().with("diff"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label(format!("Compared with {}", view.old_path.to_string_lossy()));
+                ui.horizontal(|ui| {
+                    refresh = ui.button("Refresh").on_hover_text("Compare again with the current state of the map").clicked();
+                    ui.checkbox(&mut view.blink, "Blink")
+                        .on_hover_text("Alternate between old and new instead of showing them side by side");
+                });
+                ui.horizontal(|ui| {
+                    for (kind,name) in [(DiffKind::Added,"added"),(DiffKind::Removed,"removed"),(DiffKind::Moved,"moved"),(DiffKind::Modified,"modified")] {
+                        ui.colored_label(diff_color(kind), format!("{} {name}", view.diff.count(kind)));
+                    }
+                });
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .max_height(200.)
+                    .show(ui, |ui| {
+                        if view.diff.rooms.is_empty() {
+                            ui.label("No differences");
+                        }
+                        for room in &view.diff.rooms {
+                            let text = egui::RichText::new(room.to_string()).color(diff_color(room.kind));
+                            if ui.selectable_label(view.selected == Some(room.uuid), text).clicked() {
+                                view.selected = Some(room.uuid);
+                                jump_to = Some(room.coord());
+                            }
+                        }
+                    });
+
+                let Some(room) = view.selected.and_then(|uuid| view.diff.rooms.iter().find(|r| r.uuid == uuid ) ) else {return};
+                if room.kind == DiffKind::Moved {return;}
+
+                if view.compare.as_ref().is_none_or(|c| c.uuid != room.uuid) {
+                    view.compare = Some(room_compare(room, &view.old, &view.old_path, &self.state, &self.path, ctx));
+                }
+                let Some(compare) = &view.compare else {return};
+
+                ui.separator();
+
+                let rs = self.state.rooms_size;
+                let height = 128.min(rs[1]);
+                let size = [height * rs[0] / rs[1], height];
+                let show_new = (ui.input(|i| i.time ) * 2.) as u64 % 2 == 1;
+                if view.blink {
+                    ui.ctx().request_repaint_after(Duration::from_millis(100));
+                    ui.label(if show_new {"New"} else {"Old"});
+                } else {
+                    ui.horizontal(|ui| {
+                        ui.add_sized([size[0] as f32,16.], egui::Label::new("Old"));
+                        ui.add_sized([size[0] as f32,16.], egui::Label::new("New"));
+                    });
+                }
+
+                egui::ScrollArea::vertical()
+                    .id_salt("layers")
+                    .show(ui, |ui| {
+                        for (i,[old,new]) in compare.layers.iter().enumerate() {
+                            let changed = room.changed_layers.as_ref().is_some_and(|l| l.contains(&i) );
+                            ui.label(format!("Layer {i}{}", if changed {" (changed)"} else {""}));
+                            ui.horizontal(|ui| {
+                                if view.blink {
+                                    layer_preview(if show_new {new} else {old}, size, ui);
+                                } else {
+                                    layer_preview(old, size, ui);
+                                    layer_preview(new, size, ui);
+                                }
+                            });
+                        }
+                    });
+            });
+
+        if refresh {
+            self.refresh_diff();
+        }
+
+        if let Some([x,y,z]) = jump_to {
+            self.move_viewpos_centred([x,y]);
+            self.update_level(z);
+        }
+
+        if !open {
+            self.diff_view = None;
+        }
+    }
+}
+
+fn room_compare(room: &RoomDiff, old: &MapState, old_path: &std::path::Path, new: &MapState, new_path: &std::path::Path, ctx: &egui::Context) -> RoomCompare {
+    let old_image = old.rooms.values()
+        .find(|r| r.uuid == room.uuid )
+        .and_then(|r| load_old_room_image(r, old_path, new_path, old.rooms_size).ok() );
+    let new_image = new.rooms.values()
+        .find(|r| r.uuid == room.uuid )
+        .and_then(|r| load_room_image(r, new_path, new.rooms_size).ok() );
+
+    let layer_tex = |image: &Option<DrawImage>, rooms_size: [u32;2], layer: usize, name: &str| {
+        let image = image.as_ref().filter(|i| layer < i.layers )?;
+        let color_image = color_image_of_image_area(&image.img, [0, layer as u32 * rooms_size[1]], rooms_size);
+        Some(ctx.load_texture(format!("diff_{name}_{}_{layer}", room.uuid), color_image, TextureOptions::NEAREST))
+    };
+
+    let layers = old_image.as_ref().map_or(0, |i| i.layers )
+        .max(new_image.as_ref().map_or(0, |i| i.layers ));
+
+    RoomCompare {
+        uuid: room.uuid,
+        layers: (0 .. layers)
+            .map(|i| [
+                layer_tex(&old_image, old.rooms_size, i, "old"),
+                layer_tex(&new_image, new.rooms_size, i, "new"),
+            ])
+            .collect(),
+    }
+}
+
+fn layer_preview(tex: &Option<TextureHandle>, size: [u32;2], ui: &mut egui::Ui) {
+    let p = alloc_painter_rel(ui, size.as_f32().into(), Sense::hover(), 1.);
+    let dest_rect = rector(0, 0, size[0], size[1]);
+    let mut shapes = vec![egui::Shape::rect_filled(dest_rect, CornerRadius::ZERO, Color32::BLACK)];
+    if let Some(tex) = tex {
+        shapes.push(basic_tex_shape(tex.id(), dest_rect).into());
+    }
+    p.extend_rel_fixtex(shapes);
+}
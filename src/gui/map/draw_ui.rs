@@ -8,13 +8,13 @@ use crate::gui::init::SAM;
 use crate::gui::key_manager::KMKey;
 use crate::gui::palette::{Palette, PaletteItem};
 use crate::gui::room::draw_image::DrawImageGroup;
-use crate::gui::room::Room;
+use crate::gui::room::{Room, CONN_NONE};
 use crate::gui::util::{alloc_painter_rel, dpad, dpad_icons, dpadc, dragslider_up, draw_grid, ArrUtl, DragOp, ResponseUtil};
 use crate::SRc;
 
 use super::room_ops::{try_side, OpAxis, RoomOp};
 use super::room_template_icon::templicon;
-use super::uuid::UUIDMap;
+use super::uuid::{release_res, UUIDMap};
 use super::{next_ur_op_id, HackRenderMode, Map, RoomId};
 
 impl Map {
@@ -64,7 +64,7 @@ impl Map {
             if self.state.rooms.get(v).is_none_or(|v| v.transient) {
                 if let Some(room) = self.state.rooms.remove(v) {
                     uuidmap.remove(&room.uuid);
-                    uuidmap.remove(&room.resuuid);
+                    release_res(uuidmap, room.resuuid);
                 }
             }
         }
@@ -458,7 +458,13 @@ impl Map {
                         let id = self.editsel.single_room().unwrap();
 
                         let icons = dpad_icons(|axis,dir|
-                            if self.get_room_connected(id, axis, dir) {"C"} else {""}
+                            match self.get_room_conn_ways(id, axis, dir) {
+                                Some([true,true]) => "C",
+                                Some([true,false]) => "C>",
+                                Some([false,true]) => "C<",
+                                Some([false,false]) => "C!",
+                                None => "",
+                            }
                         );
 
                         ui.horizontal(|ui| {
@@ -650,8 +656,10 @@ impl Map {
                         let grid_stroke = egui::Stroke::new(1., Color32::BLACK);
                         draw_grid([8,8], ([0.,0.], self.state.rooms_size.as_f32()), grid_stroke, 0., |s| shapes.push(s) );
 
+                        // the 16 pixel grid would leave a half cell at the room edge, if the room isn't a multiple of 16
+                        let coarse = self.state.rooms_size.map(|v| if v.is_multiple_of(16) {16} else {8} );
                         let grid_stroke = egui::Stroke::new(1., Color32::WHITE);
-                        draw_grid([16,16], ([0.,0.], self.state.rooms_size.as_f32()), grid_stroke, 0., |s| shapes.push(s) );
+                        draw_grid(coarse, ([0.,0.], self.state.rooms_size.as_f32()), grid_stroke, 0., |s| shapes.push(s) );
                     };
 
                     if !mods.shift {draw_grid(&mut shapes);}
@@ -790,7 +798,7 @@ impl Map {
         if quickmove.is_none() && let Some((axis,dir)) = makeconn {
             if let Some(id) = self.editsel.single_room() && self.state.rooms.contains_key(id) {
                 let conn = self.get_room_connected(id, axis, dir);
-                self.set_room_connect(id, axis, dir, !conn);
+                self.set_room_connect(id, axis, dir, if conn {CONN_NONE} else {self.cd_state.draw_type}, self.cd_state.oneway);
                 ui.ctx().request_repaint();
             }
         }
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use egui::{Color32, CornerRadius, StrokeKind};
+use image::RgbaImage;
+use lab::Lab;
+
+use crate::gui::rector;
+use crate::gui::room::CONN_OPEN;
+use crate::gui::room::draw_image::DrawImage;
+use crate::gui::util::RfdUtil;
+use crate::util::img::load_image;
+use crate::util::ResultExt;
+
+use super::capture::CaptureFit;
+use super::import_profile::ui_import_profile_select;
+use super::room_ops::{short_direction, try_side, OpAxis, RoomOp};
+use super::uuid::UUIDMap;
+use super::Map;
+
+/// Length of the compared segments of an edge
+const SEGMENT: u32 = 8;
+/// Width of the border strip averaged per segment
+const DEPTH: u32 = 2;
+/// Difference of a segment with content on only one side of the edge
+const MISMATCH_PENALTY: f32 = 50.;
+const MAX_SUGGESTIONS: usize = 10;
+
+/// Axis and direction of a side of a room
+type Side = (OpAxis,bool);
+
+const SIDES: [Side;4] = [(OpAxis::X,false),(OpAxis::X,true),(OpAxis::Y,false),(OpAxis::Y,true)];
+
+pub struct PlacementSuggestion {
+    pub coord: [u8;3],
+    /// Mean Lab difference over the matched edges, lower is better
+    pub score: f32,
+    /// Sides of the placed image facing existing rooms
+    pub edges: Vec<Side>,
+}
+
+pub struct EdgeMatchView {
+    pub path: PathBuf,
+    source: RgbaImage,
+    pub fit: CaptureFit,
+    pub profile: Option<usize>,
+    /// The candidate fitted to the rooms size
+    image: DrawImage,
+    layer: usize,
+    /// Connect the placed room to the rooms at its matched edges
+    pub connect: bool,
+    level: u8,
+    pub suggestions: Vec<PlacementSuggestion>,
+    hovered: Option<usize>,
+}
+
+/// Lab averages of the segments along a side of the image, None where it has no content
+fn edge_signature(image: &DrawImage, layers: impl Iterator<Item=usize> + Clone, axis: OpAxis, dir: bool, rooms_size: [u32;2]) -> Vec<Option<Lab>> {
+    if axis == OpAxis::Z {return vec![];}
+    // the axis across the edge, the segments go along the other
+    let a = axis.axis_idx();
+    let across = if dir {rooms_size[a] - DEPTH} else {0};
+    let mut size = [SEGMENT;2];
+    size[a] = DEPTH;
+
+    (0 .. rooms_size[1 - a] / SEGMENT)
+        .map(|i| {
+            let mut off = [i * SEGMENT;2];
+            off[a] = across;
+            image.lab_avg(off, size, layers.clone(), rooms_size)
+        })
+        .collect()
+}
+
+fn edge_difference(a: &[Option<Lab>], b: &[Option<Lab>]) -> Option<f32> {
+    let mut sum = 0.;
+    let mut n = 0;
+    for (a,b) in a.iter().zip(b) {
+        match (a,b) {
+            (Some(a),Some(b)) => sum += a.squared_distance(b).sqrt(),
+            (None,None) => continue,
+            _ => sum += MISMATCH_PENALTY,
+        }
+        n += 1;
+    }
+    (n != 0).then(|| sum / n as f32 )
+}
+
+impl Map {
+    pub(super) fn open_edge_match_dialog(&mut self) {
+        let mut dialog = rfd::FileDialog::new()
+            .add_filter("Image", &["png","jpg","jpeg","bmp","gif","webp","qoi"])
+            .set_title("Find placement of screen image");
+        if let Some(dir) = self.path.parent() {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(path) = dialog.try_set_parent().pick_file() else {return};
+
+        let Some(image) = load_image(&path).unwrap_gui("Failed to load image") else {return};
+
+        let source = image.to_rgba8();
+        let fit = CaptureFit::Crop;
+        let (img,layer) = self.prepare_import_image(&source, None, fit);
+        let mut view = EdgeMatchView {
+            path,
+            source,
+            fit,
+            profile: None,
+            image: DrawImage {
+                img,
+                tex: None,
+                layers: 1,
+            },
+            layer,
+            connect: true,
+            level: self.state.current_level,
+            suggestions: vec![],
+            hovered: None,
+        };
+        view.suggestions = self.edge_match_suggestions(&view.image, view.level);
+        self.edge_match = Some(view);
+    }
+
+    /// Rank the free coords next to the rooms of the level by how well the edges of the image continue the open edges of the rooms
+    pub fn edge_match_suggestions(&mut self, image: &DrawImage, level: u8) -> Vec<PlacementSuggestion> {
+        let rooms_size = self.state.rooms_size;
+
+        let candidate = SIDES.map(|(axis,dir)| edge_signature(image, 0..1, axis, dir, rooms_size) );
+
+        // summed difference and matched sides per free coord
+        let mut matches: HashMap<[u8;3],(f32,Vec<Side>)> = HashMap::new();
+
+        for (_,room) in &mut self.state.rooms {
+            if room.coord[2] != level || room.transient {continue;}
+
+            let open_sides = SIDES.iter()
+                .filter_map(|&(axis,dir)| try_side(room.coord, axis, dir, |c| (axis,dir,c) ) )
+                .filter(|&(_,_,c)| self.room_matrix.get(c).is_none() )
+                .collect::<Vec<_>>();
+            if open_sides.is_empty() || !room.ensure_loaded(&self.path, rooms_size) {continue;}
+
+            let Some(loaded) = &room.loaded else {continue};
+            let visible = room.layers.iter().enumerate()
+                .filter(|(_,l)| l.vis != 0 )
+                .map(|(i,_)| i );
+
+            for (axis,dir,coord) in open_sides {
+                let side = edge_signature(&loaded.image, visible.clone(), axis, dir, rooms_size);
+                let opposite = SIDES.iter().position(|&v| v == (axis,!dir) ).unwrap();
+                let Some(diff) = edge_difference(&side, &candidate[opposite]) else {continue};
+                let entry = matches.entry(coord).or_default();
+                entry.0 += diff;
+                entry.1.push((axis,!dir));
+            }
+        }
+
+        let mut suggestions = matches.into_iter()
+            .map(|(coord,(sum,edges))| PlacementSuggestion {
+                coord,
+                score: sum / edges.len() as f32,
+                edges,
+            })
+            .collect::<Vec<_>>();
+        // more matched edges are more reliable
+        suggestions.sort_by(|a,b| a.score.total_cmp(&b.score).then(b.edges.len().cmp(&a.edges.len())) );
+        suggestions.truncate(MAX_SUGGESTIONS);
+        suggestions
+    }
+
+    pub(super) fn ui_edge_match_window(&mut self, ctx: &egui::Context, uuidmap: &mut UUIDMap) {
+        let Some(mut view) = self.edge_match.take() else {return};
+
+        let mut open = true;
+        let mut refit = false;
+        let mut refresh = view.level != self.state.current_level;
+        let mut place = None;
+
+        egui::Window::new(format!("Match Image - {}", self.state.title))
+            .id(self.id.egui_id_map().with("edge_match"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label(view.path.to_string_lossy());
+                ui.horizontal(|ui| {
+                    refit |= ui.radio_value(&mut view.fit, CaptureFit::Crop, "Crop").changed();
+                    refit |= ui.radio_value(&mut view.fit, CaptureFit::Scale, "Scale").changed();
+                }).response.on_hover_text("Cut out the center of an image of another size, or stretch it to the rooms size");
+                ui.horizontal(|ui| {
+                    ui.label("Profile");
+                    refit |= ui_import_profile_select(ui, self.id.egui_id_map().with("edge_match_profile"), &self.state.import_profiles, &mut view.profile);
+                });
+                ui.checkbox(&mut view.connect, "Connect matched edges")
+                    .on_hover_text("Connect the placed room to the rooms next to its matched edges");
+                refresh |= ui.button("Refresh").clicked();
+
+                ui.separator();
+
+                if view.suggestions.is_empty() {
+                    ui.label("No free place next to the rooms of this level");
+                }
+
+                view.hovered = None;
+                for (i,s) in view.suggestions.iter().enumerate() {
+                    let resp = ui.horizontal(|ui| {
+                        ui.label(format!("#{} X{} Y{}", i + 1, s.coord[0], s.coord[1]));
+                        ui.label(format!("difference {:.1}", s.score));
+                        let sides = s.edges.iter()
+                            .map(|&(axis,dir)| short_direction(axis, dir) )
+                            .collect::<Vec<_>>();
+                        ui.label(sides.join(" "))
+                            .on_hover_text("Sides of the image matched with existing rooms");
+                        if ui.button("Place").clicked() {
+                            place = Some(i);
+                        }
+                    }).response;
+                    if resp.contains_pointer() {
+                        view.hovered = Some(i);
+                    }
+                }
+            });
+
+        if refit {
+            (view.image.img,view.layer) = self.prepare_import_image(&view.source, view.profile, view.fit);
+            refresh = true;
+        }
+        if refresh {
+            view.level = self.state.current_level;
+            view.suggestions = self.edge_match_suggestions(&view.image, view.level);
+        }
+
+        if open {
+            self.edge_match = Some(view);
+        }
+
+        if let Some(i) = place {
+            self.edge_match_place(i, uuidmap);
+        }
+    }
+
+    /// Place the image at the suggestion at the clicked coord, if any
+    pub(super) fn edge_match_place_at(&mut self, coord: [u8;3], uuidmap: &mut UUIDMap) {
+        let Some(view) = &self.edge_match else {return};
+        if let Some(i) = view.suggestions.iter().position(|s| s.coord == coord ) {
+            self.edge_match_place(i, uuidmap);
+        }
+    }
+
+    fn edge_match_place(&mut self, i: usize, uuidmap: &mut UUIDMap) {
+        let Some(view) = &self.edge_match else {return};
+        let Some(s) = view.suggestions.get(i) else {return};
+        let coord = s.coord;
+        let conns = if view.connect {s.edges.clone()} else {vec![]};
+        let image = view.image.img.clone();
+        let layer = view.layer;
+
+        let op = self.create_image_room(coord, image, layer, uuidmap)
+            .map(|ins| {
+                let mut ops = vec![ins];
+                ops.extend(conns.into_iter().map(|(axis,dir)| RoomOp::Conn(coord, axis, dir, [CONN_OPEN,CONN_OPEN]) ));
+                RoomOp::Multi(ops)
+            });
+
+        if self.ui_apply_group_op(op, "place image", uuidmap) {
+            self.edge_match = None;
+            self.multisel.clear();
+            self.state.ssel_coord = Some(coord);
+            self.ssel_room = self.room_matrix.get(coord).cloned();
+            self.ssel_updated();
+        }
+    }
+
+    pub(super) fn edge_match_overlay_shapes(&self, mut dest: impl FnMut(egui::Shape)) {
+        let Some(view) = &self.edge_match else {return};
+        if view.level != self.state.current_level {return;}
+        let rs = self.state.rooms_size;
+
+        let color = Color32::from_rgb(0,200,255);
+
+        for (i,s) in view.suggestions.iter().enumerate() {
+            let [x,y,_] = s.coord;
+            let rect = rector(
+                x as u32 * rs[0] + 2, y as u32 * rs[1] + 2,
+                (x as u32 + 1) * rs[0] - 2, (y as u32 + 1) * rs[1] - 2,
+            );
+            // the better ranked the more opaque
+            let alpha = 0.4 - 0.3 * i as f32 / MAX_SUGGESTIONS as f32;
+            let width = if view.hovered == Some(i) {4.} else if i == 0 {2.} else {1.};
+            dest(egui::Shape::rect_filled(rect, CornerRadius::ZERO, color.gamma_multiply(alpha)));
+            dest(egui::Shape::rect_stroke(rect, CornerRadius::ZERO, egui::Stroke::new(width, color), StrokeKind::Inside));
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use crate::export::graph::{GraphDoc, GraphFormat};
+use crate::export::guide::{export_guide, GuideOrder, GuideSections};
+use crate::export::html::export_html;
+use crate::export::ldtk::export_map_ldtk;
+use crate::export::rooms::{export_rooms, RoomsNaming};
+use crate::export::tiled::export_map_tiled;
+use crate::gui::util::RfdUtil;
+use crate::gui::window_states::map::Maps;
+use crate::util::{attached_to_path, gui_error, ResultExt};
+
+use super::{Map, RoomId};
+
+impl Map {
+    pub(super) fn ui_export_menu(&mut self, ui: &mut egui::Ui, other_maps: &Maps) {
+        ui.menu_button("Export", |ui| {
+            if ui.button("Graph…").on_hover_text("Write the rooms, connections and warps as Graphviz DOT, GraphML or JSON, chosen by the file extension").clicked() {
+                ui.close_menu();
+                self.export_graph_dialog();
+            }
+            ui.checkbox(&mut self.graph_cluster_z, "Cluster Z levels")
+                .on_hover_text("Group the rooms of every Z level in the graph");
+            ui.separator();
+            if ui.button("Tiled…").on_hover_text("Write every room as Tiled map into a folder, with a Tiled world per Z level").clicked() {
+                ui.close_menu();
+                self.export_tiled_dialog();
+            }
+            if ui.button("LDtk…").on_hover_text("Write the map as LDtk project, with a level per room and the Z levels as world depth").clicked() {
+                ui.close_menu();
+                self.export_ldtk_dialog();
+            }
+            ui.separator();
+            if ui.button("HTML viewer…").on_hover_text("Write a viewer of all open maps into a folder, which can be shared and opened in a browser from disk").clicked() {
+                ui.close_menu();
+                self.export_html_dialog(other_maps);
+            }
+            if ui.button("Guide…").on_hover_text("Write a Markdown document with images describing every room, in breadth first order from the selected room if any").clicked() {
+                ui.close_menu();
+                self.export_guide_dialog();
+            }
+            ui.checkbox(&mut self.guide_per_level, "Guide per Z level")
+                .on_hover_text("A section with overview image per Z level instead of per room");
+            ui.separator();
+            ui.menu_button("Room PNGs", |ui| {
+                if ui.button("All rooms…").clicked() {
+                    ui.close_menu();
+                    self.export_rooms_dialog(self.state.rooms.keys().collect());
+                }
+                if ui.button("Current Z level…").clicked() {
+                    ui.close_menu();
+                    let z = self.state.current_level;
+                    let rooms = self.state.rooms.iter().filter(|(_,r)| r.coord[2] == z ).map(|(id,_)| id ).collect();
+                    self.export_rooms_dialog(rooms);
+                }
+                ui.separator();
+                self.ui_rooms_export_opts(ui);
+            });
+        });
+    }
+
+    fn export_graph_dialog(&mut self) {
+        let file_name = attached_to_path(self.path.file_name().unwrap_or_default(), ".dot");
+        let mut dialog = rfd::FileDialog::new()
+            .add_filter("Graphviz DOT", &["dot","gv"])
+            .add_filter("GraphML", &["graphml"])
+            .add_filter("JSON", &["json"])
+            .set_file_name(file_name.to_string_lossy())
+            .set_title("Export graph");
+        if let Some(dir) = self.path.parent() {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(path) = dialog.try_set_parent().save_file() else {return};
+
+        let format = GraphFormat::from_path(&path).unwrap_or(GraphFormat::Dot);
+        GraphDoc::build([&self.state], self.graph_cluster_z)
+            .render(format)
+            .and_then(|v| Ok(std::fs::write(&path, v)?) )
+            .unwrap_gui("Failed to export graph");
+    }
+
+    fn export_tiled_dialog(&mut self) {
+        let mut dialog = rfd::FileDialog::new()
+            .set_title("Export Tiled world into folder");
+        if let Some(dir) = self.path.parent() {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(path) = dialog.try_set_parent().pick_folder() else {return};
+
+        let Some(failed) = export_map_tiled(self, &path).unwrap_gui("Failed to export Tiled world") else {return};
+        if !failed.is_empty() {
+            gui_error("Some rooms were exported without image", failed.join("\n"));
+        }
+    }
+
+    fn export_ldtk_dialog(&mut self) {
+        let file_name = attached_to_path(self.path.file_name().unwrap_or_default(), ".ldtk");
+        let mut dialog = rfd::FileDialog::new()
+            .add_filter("LDtk project", &["ldtk"])
+            .set_file_name(file_name.to_string_lossy())
+            .set_title("Export LDtk project");
+        if let Some(dir) = self.path.parent() {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(path) = dialog.try_set_parent().save_file() else {return};
+
+        let Some(failed) = export_map_ldtk(self, &path).unwrap_gui("Failed to export LDtk project") else {return};
+        if !failed.is_empty() {
+            gui_error("Some rooms were exported without image", failed.join("\n"));
+        }
+    }
+
+    fn export_html_dialog(&mut self, other_maps: &Maps) {
+        let mut dialog = rfd::FileDialog::new()
+            .set_title("Export HTML viewer into folder");
+        if let Some(dir) = self.path.parent() {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(path) = dialog.try_set_parent().pick_folder() else {return};
+
+        let others = other_maps.open_maps.iter()
+            .filter(|&(&id,_)| id != self.id )
+            .filter_map(|(_,m)| m.try_borrow().ok() )
+            .collect::<Vec<_>>();
+        let maps = std::iter::once(&*self).chain(others.iter().map(|m| &**m )).collect::<Vec<_>>();
+
+        let Some(failed) = export_html(&maps, &path).unwrap_gui("Failed to export HTML viewer") else {return};
+        if !failed.is_empty() {
+            gui_error("Some rooms were exported without image", failed.join("\n"));
+        }
+    }
+
+    fn export_guide_dialog(&mut self) {
+        let file_name = attached_to_path(self.path.file_name().unwrap_or_default(), ".md");
+        let mut dialog = rfd::FileDialog::new()
+            .add_filter("Markdown", &["md"])
+            .set_file_name(file_name.to_string_lossy())
+            .set_title("Export guide");
+        if let Some(dir) = self.path.parent() {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(path) = dialog.try_set_parent().save_file() else {return};
+
+        let sections = if self.guide_per_level {GuideSections::Level} else {GuideSections::Room};
+        let start = self.selected_room();
+        let order = if start.is_some() {GuideOrder::Bfs} else {GuideOrder::Coords};
+
+        let Some(failed) = export_guide(self, &path, sections, order, start).unwrap_gui("Failed to export guide") else {return};
+        if !failed.is_empty() {
+            gui_error("Some images failed to export", failed.join("\n"));
+        }
+    }
+
+    pub(super) fn ui_rooms_export_opts(&mut self, ui: &mut egui::Ui) {
+        let opts = &mut self.rooms_export;
+        ui.checkbox(&mut opts.separate_layers, "Layers separately")
+            .on_hover_text("Write every visible layer into its own file instead of flattening them");
+        let mut title = opts.naming == RoomsNaming::Title;
+        if ui.checkbox(&mut title, "Name by title").on_hover_text("Name the files by room title instead of coords").changed() {
+            opts.naming = if title {RoomsNaming::Title} else {RoomsNaming::Coords};
+        }
+        ui.checkbox(&mut opts.manifest, "Manifest")
+            .on_hover_text("Also write a manifest.json mapping the files to room UUID, coord and tags");
+        ui.horizontal(|ui| {
+            ui.label("Scale");
+            ui.add(egui::DragValue::new(&mut opts.scale).range(1 ..= 16));
+        });
+    }
+
+    pub(super) fn export_rooms_dialog(&mut self, rooms: Vec<RoomId>) {
+        let mut dialog = rfd::FileDialog::new()
+            .set_title("Export room PNGs into folder");
+        if let Some(dir) = self.path.parent() {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(path) = dialog.try_set_parent().pick_folder() else {return};
+
+        let Some(failed) = export_rooms(self, &rooms, &path, &self.rooms_export).unwrap_gui("Failed to export room PNGs") else {return};
+        if !failed.is_empty() {
+            gui_error("Some rooms failed to load and were skipped", failed.join("\n"));
+        }
+    }
+}
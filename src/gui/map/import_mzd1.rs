@@ -5,15 +5,18 @@ use image::GenericImageView;
 use regex::Regex;
 
 use crate::gui::map::next_ur_op_id;
+use crate::gui::room::{CONN_NONE, CONN_OPEN};
 use crate::gui::map::room_ops::{RoomOp, try_6_sides};
 use crate::gui::sel_matrix::{SelEntryWrite, SelEntry};
-use crate::gui::util::{ArrUtl, RfdUtil};
+use crate::gui::util::RfdUtil;
 use crate::util::img::load_image;
 use crate::util::{gui_error, next_op_gen_evo};
 
 use super::uuid::UUIDMap;
 use super::Map;
 
+const MZD1_ROOM_SIZE: [u32;2] = [160,128];
+
 impl Map {
     pub(super) fn ui_import_mzd1(&mut self, uuidmap: &mut UUIDMap) -> bool {
         let Some(ssel_coord) = self.state.ssel_coord else {return false};
@@ -35,11 +38,6 @@ impl Map {
     }
 
     pub fn import_mzd1(&mut self, dest: [u8;3], mut level_dir: PathBuf, uuidmap: &mut UUIDMap) -> anyhow::Result<()> {
-        ensure!(
-            self.state.rooms_size[0] >= 160 && self.state.rooms_size[1] >= 128,
-            "map rooms_size not large enough to hold mzd1 rooms (160x128)"
-        );
-
         let lvl_sub_folder = {
             let mut p = level_dir.clone();
             p.push("level");
@@ -80,7 +78,7 @@ impl Map {
             }
 
             let image = load_image(f.path())?;
-            ensure!(image.dimensions() == (160,128), "mzd1 image file with wrong dims");
+            ensure!(image.dimensions() == (MZD1_ROOM_SIZE[0],MZD1_ROOM_SIZE[1]), "mzd1 image file with wrong dims");
 
             rooms.push((dest,image));
         }
@@ -104,19 +102,23 @@ impl Map {
             };
             undo_ops.push(ur);
 
-            let overlay_pos = self.state.rooms_size.sub([160,128]).div([2,2]).div8().mul8();
+            // centered, and cropped if the rooms are smaller
+            let rooms_size = self.state.rooms_size;
+            let overlay_pos = [0,1].map(|i| (rooms_size[i] as i64 - MZD1_ROOM_SIZE[i] as i64) / 2 / 8 * 8 );
 
             let room = self.state.rooms.get_mut(room_id).unwrap();
 
             image::imageops::replace(
                 &mut room.loaded.as_mut().unwrap().image.img,
                 image,
-                overlay_pos[0] as i64,
-                overlay_pos[1] as i64,
+                overlay_pos[0],
+                overlay_pos[1],
             );
             
-            for y in overlay_pos[1] .. overlay_pos[1] + 128 {
-                for x in overlay_pos[0] .. overlay_pos[0] + 160 {
+            let [x0,y0] = [0,1].map(|i| overlay_pos[i].max(0) as u32 );
+            let [x1,y1] = [0,1].map(|i| (overlay_pos[i] + MZD1_ROOM_SIZE[i] as i64).min(rooms_size[i] as i64) as u32 );
+            for y in y0 .. y1 {
+                for x in x0 .. x1 {
                     *room.loaded.as_mut().unwrap().sel_matrix.layers[0].get_mut([x/8,y/8]).unwrap() = SelEntry { start: [0,0], size: [1,1] };
                 }
             }
@@ -127,7 +129,7 @@ impl Map {
         for (coord,_) in &rooms {
             try_6_sides(*coord, |c,ax,dir| {
                 if let Some(room) = self.room_matrix.get(c).and_then(|&r| self.state.rooms.get_mut(r) ) {
-                    let set_conn = room.op_evo == op_evo;
+                    let set_conn = if room.op_evo == op_evo {CONN_OPEN} else {CONN_NONE};
                     room.dirconn[ax as usize][!dir as usize] = set_conn;
                     if let Some(room) = self.room_matrix.get(*coord).and_then(|&r| self.state.rooms.get_mut(r) ) {
                         room.dirconn[ax as usize][dir as usize] = set_conn;
@@ -0,0 +1,310 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{ensure, Context};
+use image::{GenericImageView, RgbaImage};
+use lab::Lab;
+use serde::{Deserialize, Serialize};
+
+use crate::gui::room::{Layer, Room};
+use crate::gui::sel_matrix::{SelEntry, SelEntryWrite, SelMatrix};
+use crate::gui::util::RfdUtil;
+use crate::util::img::load_image;
+use crate::util::ResultExt;
+
+use super::capture::{fit_image, CaptureFit};
+use super::uuid::UUIDMap;
+use super::{next_ur_op_id, Map};
+
+const MAX_PALETTE: usize = 256;
+
+/// Pre-processing of imported screenshots, like cutting off the HUD of an emulator screenshot
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct ImportProfile {
+    pub name: String,
+    /// x, y, width and height cut out of the image. A width or height of 0 extends to the border
+    #[serde(default)]
+    pub crop: [u32;4],
+    /// Nearest neighbour integer downscale after cropping
+    #[serde(default = "one")]
+    pub downscale: u32,
+    /// Nearest neighbour integer upscale after downscaling
+    #[serde(default = "one")]
+    pub upscale: u32,
+    /// Snap the colors to the nearest of the palette, if not empty
+    #[serde(default)]
+    pub palette: Vec<[u8;3]>,
+    /// Room layer receiving the image
+    #[serde(default)]
+    pub layer: usize,
+}
+
+fn one() -> u32 {1}
+
+impl ImportProfile {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            crop: [0;4],
+            downscale: 1,
+            upscale: 1,
+            palette: vec![],
+            layer: 0,
+        }
+    }
+
+    pub fn process(&self, image: &RgbaImage) -> RgbaImage {
+        let [x,y,w,h] = self.crop;
+        let x = x.min(image.width());
+        let y = y.min(image.height());
+        let w = if w == 0 {image.width() - x} else {w.min(image.width() - x)};
+        let h = if h == 0 {image.height() - y} else {h.min(image.height() - y)};
+
+        let mut image = image.view(x, y, w, h).to_image();
+
+        let d = self.downscale.max(1);
+        let u = self.upscale.max(1);
+        if d != 1 || u != 1 {
+            let w = (w / d).max(1) * u;
+            let h = (h / d).max(1) * u;
+            image = image::imageops::resize(&image, w, h, image::imageops::FilterType::Nearest);
+        }
+
+        if !self.palette.is_empty() {
+            snap_to_palette(&mut image, &self.palette);
+        }
+
+        image
+    }
+}
+
+fn snap_to_palette(image: &mut RgbaImage, palette: &[[u8;3]]) {
+    let palette_lab = palette.iter().map(Lab::from_rgb).collect::<Vec<_>>();
+    let mut snapped = HashMap::new();
+
+    for p in image.pixels_mut() {
+        if p.0[3] == 0 {continue;}
+        let rgb = [p.0[0],p.0[1],p.0[2]];
+        let v = *snapped.entry(rgb).or_insert_with(|| {
+            let lab = Lab::from_rgb(&rgb);
+            let nearest = palette_lab.iter()
+                .enumerate()
+                .min_by(|(_,a),(_,b)| a.squared_distance(&lab).total_cmp(&b.squared_distance(&lab)) )
+                .unwrap().0;
+            palette[nearest]
+        });
+        p.0[..3].copy_from_slice(&v);
+    }
+}
+
+/// The distinct opaque colors of the image, like a palette swatch or a screenshot of the game
+fn palette_of_image(image: &RgbaImage) -> anyhow::Result<Vec<[u8;3]>> {
+    let mut seen = HashSet::new();
+    let mut palette = vec![];
+    for p in image.pixels() {
+        if p.0[3] == 0 {continue;}
+        let rgb = [p.0[0],p.0[1],p.0[2]];
+        if seen.insert(rgb) {
+            palette.push(rgb);
+            ensure!(palette.len() <= MAX_PALETTE, "The image has more than {MAX_PALETTE} colors");
+        }
+    }
+    Ok(palette)
+}
+
+/// Draw the image into the layer of the loaded room, adding empty layers below if missing, and make its content selectable
+pub(super) fn write_room_layer(room: &mut Room, layer: usize, image: &RgbaImage, rooms_size: [u32;2]) {
+    let loaded = room.loaded.as_mut().unwrap();
+    while loaded.image.layers <= layer {
+        let n = loaded.image.layers;
+        loaded.image.insert_layer(rooms_size, n);
+        loaded.sel_matrix.layers.push(SelMatrix::new_empty(loaded.sel_matrix.dims));
+        room.layers.push(Layer::new_visible());
+    }
+
+    image::imageops::replace(&mut loaded.image.img, image, 0, (layer as u32 * rooms_size[1]) as i64);
+
+    let sel_matrix = &mut loaded.sel_matrix.layers[layer];
+    *sel_matrix = SelMatrix::new_empty(sel_matrix.dims);
+    for cy in 0 .. rooms_size[1] / 8 {
+        for cx in 0 .. rooms_size[0] / 8 {
+            let opaque = image.view(cx * 8, cy * 8, 8, 8).pixels().any(|(_,_,p)| p.0[3] != 0 );
+            if opaque {
+                *sel_matrix.get_mut([cx,cy]).unwrap() = SelEntry { start: [0,0], size: [1,1] };
+            }
+        }
+    }
+
+    if let Some(t) = &mut loaded.image.tex {
+        t.dirty();
+    }
+}
+
+/// Returns whether the selection changed
+pub(super) fn ui_import_profile_select(ui: &mut egui::Ui, id: egui::Id, profiles: &[ImportProfile], sel: &mut Option<usize>) -> bool {
+    let selected = sel.and_then(|i| profiles.get(i) ).map_or("None", |p| &p.name );
+    let old = *sel;
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(selected)
+        .show_ui(ui, |ui| {
+            ui.selectable_value(sel, None, "None");
+            for (i,p) in profiles.iter().enumerate() {
+                ui.selectable_value(sel, Some(i), &p.name);
+            }
+        })
+        .response
+        .on_hover_text("Import profile cropping, scaling and palette snapping the image, edited in \"Import Profiles\"");
+    *sel != old
+}
+
+impl Map {
+    /// Apply the profile and fit the result to the rooms size. Returns the image and the layer it goes into
+    pub fn prepare_import_image(&self, image: &RgbaImage, profile: Option<usize>, fit: CaptureFit) -> (RgbaImage,usize) {
+        match profile.and_then(|i| self.state.import_profiles.get(i) ) {
+            Some(p) => (fit_image(p.process(image), self.state.rooms_size, fit), p.layer),
+            None => (fit_image(image.clone(), self.state.rooms_size, fit), 0),
+        }
+    }
+
+    /// Import the image into the selected room with the drop profile of the map, creating the room if needed
+    pub fn import_dropped_image(&mut self, image: &RgbaImage, uuidmap: &mut UUIDMap) -> anyhow::Result<()> {
+        let rooms_size = self.state.rooms_size;
+        let coord = self.state.ssel_coord.context("No room selected")?;
+        let (image,layer) = self.prepare_import_image(image, self.state.drop_profile, CaptureFit::Crop);
+
+        if let Some(room_id) = self.room_matrix.get(coord).cloned() {
+            let room = &mut self.state.rooms[room_id];
+            ensure!(room.ensure_loaded(&self.path, rooms_size), "Failed to load the selected room");
+            let loaded = room.loaded.as_mut().unwrap();
+            loaded.pre_img_draw(&room.layers, room.selected_layer);
+            write_room_layer(room, layer, &image, rooms_size);
+            room.loaded.as_mut().unwrap().ur_snapshot_required = true;
+            room.transient = false;
+            self.dirty_rooms.insert(room_id);
+            self.imglru.pop(&room_id);
+        } else {
+            let op = self.create_image_room(coord, image, layer, uuidmap).context("The selected coord is occupied")?;
+            let mut msg = String::new();
+            ensure!(self.validate_apply(&op, &mut msg), "{msg}");
+            let ur = self.apply_room_op(op, uuidmap);
+            self.undo_buf.push_back((ur,next_ur_op_id()));
+            self.after_room_op_apply_invalidation(false);
+            self.ssel_room = self.room_matrix.get(coord).cloned();
+            self.ssel_updated();
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn ui_import_profiles_window(&mut self, ctx: &egui::Context) {
+        if !self.import_profiles_window {return;}
+
+        let mut open = true;
+
+        egui::Window::new(format!("Import Profiles - {}", self.state.title))
+            .id(self.id.egui_id_map().with("import_profiles"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Dropped images");
+                    let selected = self.state.drop_profile
+                        .and_then(|i| self.state.import_profiles.get(i) )
+                        .map_or("Open as tileset", |p| &p.name );
+                    egui::ComboBox::from_id_salt(self.id.egui_id_map().with("drop_profile"))
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.state.drop_profile, None, "Open as tileset");
+                            for (i,p) in self.state.import_profiles.iter().enumerate() {
+                                ui.selectable_value(&mut self.state.drop_profile, Some(i), &p.name);
+                            }
+                        });
+                }).response.on_hover_text("Images dropped onto the window while this map was focused last are imported with the profile into the selected room, which is created if it doesn't exist");
+
+                ui.separator();
+
+                let mut remove = None;
+
+                for (i,p) in self.state.import_profiles.iter_mut().enumerate() {
+                    egui::CollapsingHeader::new(&p.name)
+                        .id_salt(self.id.egui_id_map().with("import_profile").with(i))
+                        .show(ui, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.label("Name");
+                                ui.text_edit_singleline(&mut p.name);
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("Crop X");
+                                ui.add(egui::DragValue::new(&mut p.crop[0]));
+                                ui.label("Y");
+                                ui.add(egui::DragValue::new(&mut p.crop[1]));
+                                ui.label("W");
+                                ui.add(egui::DragValue::new(&mut p.crop[2]));
+                                ui.label("H");
+                                ui.add(egui::DragValue::new(&mut p.crop[3]));
+                            }).response.on_hover_text("Rectangle cut out of the image, like the screen without the HUD or border. A width or height of 0 extends to the border");
+                            ui.horizontal(|ui| {
+                                ui.label("Downscale");
+                                ui.add(egui::DragValue::new(&mut p.downscale).range(1 ..= 16));
+                                ui.label("Upscale");
+                                ui.add(egui::DragValue::new(&mut p.upscale).range(1 ..= 16));
+                                ui.label("Layer");
+                                ui.add(egui::DragValue::new(&mut p.layer).range(0 ..= 15));
+                            }).response.on_hover_text("Integer nearest neighbour scaling after cropping, and the room layer receiving the image. The result is cropped around its center to the rooms size");
+                            ui.horizontal_wrapped(|ui| {
+                                ui.label(format!("Palette ({} colors)", p.palette.len()))
+                                    .on_hover_text("Snap the colors of the image to the nearest palette color. No snapping if empty. Right click a color to remove it");
+                                let mut remove_color = None;
+                                for (j,c) in p.palette.iter_mut().enumerate() {
+                                    if ui.color_edit_button_srgb(c).secondary_clicked() {
+                                        remove_color = Some(j);
+                                    }
+                                }
+                                if let Some(j) = remove_color {
+                                    p.palette.remove(j);
+                                }
+                                if p.palette.len() < MAX_PALETTE && ui.button("+").clicked() {
+                                    p.palette.push([0,0,0]);
+                                }
+                                if ui.button("From image").on_hover_text("Replace the palette with the colors of an image, like a palette swatch").clicked()
+                                    && let Some(path) = rfd::FileDialog::new()
+                                        .add_filter("Image", &["png","bmp","gif","qoi"])
+                                        .set_title("Load palette from image")
+                                        .try_set_parent()
+                                        .pick_file()
+                                {
+                                    let palette = load_image(&path)
+                                        .map_err(anyhow::Error::from)
+                                        .and_then(|v| palette_of_image(&v.to_rgba8()) );
+                                    if let Some(v) = palette.unwrap_gui("Failed to load palette") {
+                                        p.palette = v;
+                                    }
+                                }
+                                if ui.button("Clear").clicked() {
+                                    p.palette.clear();
+                                }
+                            });
+                            if ui.button("Delete profile").clicked() {
+                                remove = Some(i);
+                            }
+                        });
+                }
+
+                if let Some(i) = remove {
+                    self.state.import_profiles.remove(i);
+                    self.state.drop_profile = match self.state.drop_profile {
+                        Some(d) if d == i => None,
+                        Some(d) if d > i => Some(d - 1),
+                        v => v,
+                    };
+                }
+
+                if ui.button("Add").clicked() {
+                    let name = format!("Profile {}", self.state.import_profiles.len() + 1);
+                    self.state.import_profiles.push(ImportProfile::new(name));
+                }
+            });
+
+        if !open {
+            self.import_profiles_window = false;
+        }
+    }
+}
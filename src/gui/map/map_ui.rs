@@ -18,7 +18,7 @@ use crate::util::{MapId, gui_error};
 
 use super::room_ops::{render_picomap, RoomOp, OpAxis};
 use super::uuid::UUIDMap;
-use super::{next_ur_op_id, zoomf, Map, MapEditMode, RoomId};
+use super::{next_ur_op_id, zoomf, Map, MapEditMode, ResStorage, RoomId, SerLayout};
 
 impl Map {
     fn ui_create_room(&mut self, coord: [u8;3], uuidmap: &mut UUIDMap) -> Option<RoomId> {
@@ -171,6 +171,17 @@ impl Map {
             debug_map_hatter(self);
         }
 
+        self.ui_check_window(&ui.ctx().clone(), sam);
+        self.ui_diff_window(&ui.ctx().clone());
+        self.ui_conn_types_window(&ui.ctx().clone());
+        self.ui_route_window(&ui.ctx().clone(), other_maps);
+        self.ui_solve_window(&ui.ctx().clone(), other_maps);
+        self.ui_slice_import_window(&ui.ctx().clone(), &mut sam.uuidmap);
+        self.ui_capture_window(&ui.ctx().clone(), &mut sam.uuidmap);
+        self.ui_edge_match_window(&ui.ctx().clone(), &mut sam.uuidmap);
+        self.ui_import_profiles_window(&ui.ctx().clone());
+        self.ui_resize_window(&ui.ctx().clone(), &mut sam.uuidmap);
+
         let mods = ui.input(|i| i.modifiers );
 
         // on close of the map, palette textures should be unchained
@@ -199,6 +210,35 @@ impl Map {
                         sam.uuidmap.remove(&self.state.uuid);
                         sam.mut_queue.push(Box::new(move |state: &mut SharedApp| {state.maps.open_maps.remove(&id);} ))
                     }
+                    if ui.button("Check").on_hover_text("Check the map and its room files for corruption and unreferenced files").clicked() {
+                        self.open_check_window(sam);
+                    }
+                    if ui.button("Diff").on_hover_text("Compare the map with another version of its map file").clicked() {
+                        self.open_diff_dialog();
+                    }
+                    if ui.button("Reach").on_hover_text("Show the rooms reachable from the selected room, over connections and warps of all open maps, and the shortest route to another room").clicked() {
+                        self.open_route_view(other_maps);
+                    }
+                    if ui.button("Solve").on_hover_text("Simulate collecting the items of the tags from the selected room, and find softlocks and unreachable items").clicked() {
+                        self.open_solve_view(other_maps);
+                    }
+                    if ui.button("Import Image").on_hover_text("Slice a large image, like a world map, into new rooms").clicked() {
+                        self.open_slice_import_dialog();
+                    }
+                    let capture_text = if self.capture.is_some() {"Stop Capture"} else {"Capture"};
+                    if ui.button(capture_text).on_hover_text("Watch a folder for new screenshots, and place each next to the selected room").clicked() {
+                        self.toggle_capture();
+                    }
+                    if ui.button("Match Image").on_hover_text("Find where a screen image continues the open edges of the rooms on this level").clicked() {
+                        self.open_edge_match_dialog();
+                    }
+                    if ui.button("Import Profiles").on_hover_text("Edit the cropping, scaling and palette snapping of imported screenshots, and what happens with dropped images").clicked() {
+                        self.import_profiles_window = !self.import_profiles_window;
+                    }
+                    if ui.button("Resize Rooms").on_hover_text("Pad or crop every room to another room size").clicked() {
+                        self.open_resize_dialog();
+                    }
+                    self.ui_export_menu(ui, other_maps);
                     ui.add(egui::TextEdit::singleline(&mut self.state.title).desired_width(200. * sam.dpi_scale));
                     ui.separator();
                     ui.label("Zoom: ");
@@ -256,6 +296,19 @@ impl Map {
                     }
                     ui.separator();
                     ui.checkbox(&mut self.state.set_dssel_merged, "Merge ssel/dsel");
+                    let mut content_storage = self.state.res_storage == ResStorage::Content;
+                    ui.checkbox(&mut content_storage, "Dedup files")
+                        .on_hover_text("Name room files by their content, so identical rooms share them and unchanged rooms keep them. Applies to rooms saved from now on, use \"mzd2 storage\" to convert all rooms");
+                    self.state.res_storage = if content_storage {ResStorage::Content} else {ResStorage::Uuid};
+                    egui::ComboBox::from_id_salt(self.id.egui_id_map().with("ser_layout"))
+                        .selected_text(format!("{:?}", self.state.ser_layout))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.state.ser_layout, SerLayout::Compact, "Compact");
+                            ui.selectable_value(&mut self.state.ser_layout, SerLayout::Lines, "Lines");
+                            ui.selectable_value(&mut self.state.ser_layout, SerLayout::Split, "Split");
+                        })
+                        .response
+                        .on_hover_text("Map file layout. Lines pretty prints the map file for version control, Split also puts every room into its own file in the _data/rooms folder");
                 });
                 ui.horizontal(|ui| {
                     let resp = ui.add_enabled(
@@ -420,11 +473,18 @@ impl Map {
                                 }
                             }
 
+                            self.ui_multisel_header(ui, sam);
+
                             ui.separator();
                             ui.label("ShiftAway/Collapse Size: ").doc(DOC_MAP_SHIFTSIZE);
                             dragvalion_up(&mut self.state.smart_move_size, 0.015625, 0..=16, 1, ui);
 
                             ui.checkbox(&mut self.state.smart_awaylock_mode, "SmartMove AwayLock");
+
+                            if matches!(self.state.edit_mode, MapEditMode::ConnXY | MapEditMode::ConnDown | MapEditMode::ConnUp) {
+                                ui.separator();
+                                self.ui_conn_type_select(ui);
+                            }
                         },
                     }
                 });
@@ -432,11 +492,16 @@ impl Map {
                     MapEditMode::DrawSel => {
                         if let Some(v) = self.dsel_room && self.state.rooms.contains_key(v) {
                             let room = self.state.rooms.get_mut(v).unwrap();
+                            ui.add(
+                                egui::TextEdit::singleline(&mut room.title)
+                                .id_source(("RoomTitleTB",v))
+                                .hint_text("Title")
+                            );
                             ui.add(
                                 egui::TextEdit::multiline(&mut room.desc_text)
                                 .id_source(("RoomDescTB",v))
                             );
-                            if !room.desc_text.is_empty() {
+                            if !room.title.is_empty() || !room.desc_text.is_empty() {
                                 room.transient = false;
                             }
                         }
@@ -505,6 +570,7 @@ impl Map {
                                     self.ui_do_smart(clicked, axis, dir, &mut sam.uuidmap);
                                 },
                             ).doc(DOC_MAP_SMARTMOVE);
+                            self.ui_multisel_dpads(ui, sam);
                         });
                     }
                 };
@@ -602,6 +668,7 @@ impl Map {
                 match self.state.edit_mode {
                     MapEditMode::DrawSel => {
                         if super_map.response.clicked_by(egui::PointerButton::Primary) {
+                            self.diff_select_at(click_coord);
                             if !mods.ctrl {
                                 self.state.dsel_coord = Some(click_coord);
                                 self.dsel_room = self.room_matrix.get(click_coord).cloned();
@@ -627,10 +694,19 @@ impl Map {
                     },
                     MapEditMode::RoomSel => {
                         if super_map.response.clicked_by(egui::PointerButton::Primary) {
-                            self.state.ssel_coord = Some(click_coord);
-                            self.ssel_room = self.room_matrix.get(click_coord).cloned();
-                            self.ssel_updated();
+                            self.diff_select_at(click_coord);
+                            if mods.ctrl {
+                                self.multisel_toggle(click_coord);
+                            } else {
+                                self.multisel.clear();
+                                self.state.ssel_coord = Some(click_coord);
+                                self.ssel_room = self.room_matrix.get(click_coord).cloned();
+                                self.ssel_updated();
+                                self.edge_match_place_at(click_coord, &mut sam.uuidmap);
+                            }
                         }
+                        let op = super_map.drag_decode(PointerButton::Primary, ui);
+                        self.multisel_drag_op(op, mods.ctrl);
                     },
                     MapEditMode::Tags => {
                         self.ui_tag_mouse_op(&mut super_map, ui, sam, other_maps, click_coord, sub_click_coord, &mut tag_hovered);
@@ -792,6 +868,7 @@ impl Map {
                                 self.state.edit_mode,
                                 [cx,cy].mul(self.state.rooms_size),
                                 self.state.rooms_size,
+                                &self.state.conn_types,
                                 |s| shapes.push(s),
                                 ui.ctx(),
                             );
@@ -831,6 +908,15 @@ impl Map {
                     }
                 }
 
+                if self.state.edit_mode != MapEditMode::DrawSel {
+                    self.multisel_shapes(|s| shapes.push(s) );
+                }
+
+                self.route_overlay_shapes(|s| shapes.push(s) );
+                self.solve_overlay_shapes(|s| shapes.push(s) );
+                self.diff_overlay_shapes(|s| shapes.push(s) );
+                self.edge_match_overlay_shapes(|s| shapes.push(s) );
+
                 super_map.extend_rel_fixtex(shapes);
             }
 
@@ -866,13 +952,13 @@ impl Map {
         };
         let args = match self.state.edit_mode {
             MapEditMode::DrawSel if !self.editsel.rooms.is_empty() && self.editsel.rooms.len() < 4 =>
-                format!(" | [🖱left] Select Room | [ctrl + 🖱left] Extend draw room selection"),
+                " | [🖱left] Select Room | [ctrl + 🖱left] Extend draw room selection".to_owned(),
             MapEditMode::DrawSel =>
-                format!(" | [🖱left] Select Room"),
+                " | [🖱left] Select Room".to_owned(),
             MapEditMode::RoomSel => 
-                format!(" | [🖱left] Select Room"),
+                " | [🖱left] Select Room | [ctrl + 🖱left] Add/Remove room to group | [🖱left drag] Select group".to_owned(),
             MapEditMode::ConnXY | MapEditMode::ConnDown | MapEditMode::ConnUp =>
-                format!(" | [🖱left] Connect Rooms | [🖱right] Disconnect Rooms"),
+                " | [🖱left] Connect Rooms with selected type | [🖱right] Disconnect Rooms".to_owned(),
             MapEditMode::Tags => format!(
                 "{}{}",
                 if tag_hovered {
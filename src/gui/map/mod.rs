@@ -1,7 +1,7 @@
 use std::collections::VecDeque;
 use std::hash::BuildHasherDefault;
 use std::io::ErrorKind;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use egui::TextureOptions;
 use egui::epaint::ahash::{HashSet, AHasher};
@@ -9,11 +9,22 @@ use serde::{Serialize, Deserialize};
 use slotmap::{HopSlotMap, Key, SlotMap};
 use ::uuid::Uuid;
 
+use crate::export::rooms::RoomsExportOpts;
 use crate::gui::map::uuid::UUIDTarget;
 use crate::map::coord_store::CoordStore;
-use crate::util::uuid::generate_uuid;
+use crate::util::uuid::{generate_uuid, retain_res};
 use crate::util::*;
 
+use self::capture::CaptureState;
+use self::edge_match::EdgeMatchView;
+use self::import_profile::ImportProfile;
+use self::resize_ui::ResizeDialog;
+use self::check_ui::CheckWindow;
+use self::conn_types::{default_conn_types, ConnTypeDef};
+use self::diff_ui::DiffView;
+use self::route_ui::RouteView;
+use self::slice_import::SliceImport;
+use self::solver_ui::SolveView;
 use self::room_ops::{RoomOp, ShiftSmartCollected};
 use self::uuid::UUIDMap;
 
@@ -35,6 +46,18 @@ pub mod draw_ui;
 pub mod draw_layers_ui;
 pub mod import_mzd1;
 pub mod room_template_icon;
+pub mod check_ui;
+pub mod diff_ui;
+pub mod export_ui;
+pub mod multisel_ui;
+pub mod conn_types;
+pub mod route_ui;
+pub mod solver_ui;
+pub mod slice_import;
+pub mod capture;
+pub mod edge_match;
+pub mod import_profile;
+pub mod resize_ui;
 
 pub type DirtyRooms = HashSet<RoomId>;
 pub type LruCache = lru::LruCache<RoomId,u64,BuildHasherDefault<AHasher>>;
@@ -74,6 +97,22 @@ pub struct Map {
     pub tag_sel: Option<(RoomId,Uuid)>,
     pub(crate) matrix_debug_corrupt_flag: bool,
     pub show_green_save_until: f64,
+    pub check_window: Option<CheckWindow>,
+    pub diff_view: Option<DiffView>,
+    pub multisel: HashSet<RoomId>,
+    /// Start and end of the rubber band selection on the current level
+    pub multisel_drag: Option<([u8;2],[u8;2])>,
+    pub conn_types_window: bool,
+    pub import_profiles_window: bool,
+    pub route_view: Option<RouteView>,
+    pub solve_view: Option<SolveView>,
+    pub graph_cluster_z: bool,
+    pub guide_per_level: bool,
+    pub rooms_export: RoomsExportOpts,
+    pub slice_import: Option<SliceImport>,
+    pub capture: Option<CaptureState>,
+    pub edge_match: Option<EdgeMatchView>,
+    pub resize_dialog: Option<ResizeDialog>,
 }
 
 pub type RoomMap = HopSlotMap<RoomId,Room>;
@@ -114,6 +153,123 @@ pub struct MapState {
     pub quickroom_template: Vec<Option<Room>>,
     pub set_dssel_merged: bool,
     pub quick_shift_keep_gap: bool,
+    #[serde(default)]
+    pub res_storage: ResStorage,
+    #[serde(default)]
+    pub ser_layout: SerLayout,
+    #[serde(default = "default_conn_types")]
+    pub conn_types: Vec<ConnTypeDef>,
+    #[serde(default)]
+    pub import_profiles: Vec<ImportProfile>,
+    /// Import profile of images dropped onto the window, opened as tileset if None
+    #[serde(default)]
+    pub drop_profile: Option<usize>,
+}
+
+impl MapState {
+    /// Read a map file without loading it into the GUI
+    pub fn read_file(path: &Path) -> anyhow::Result<Self> {
+        let data = std::fs::read(path)?;
+
+        let header = serde_json::from_slice::<MapDeserProbe>(&data)?;
+
+        anyhow::ensure!(header.mzd_format == 2, "Unsupported mzd_format {}", header.mzd_format);
+
+        let mut state = serde_json::from_slice::<MapState>(&data)?;
+
+        state.read_split_rooms(path)?;
+
+        Ok(state)
+    }
+
+    pub fn write_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        let ident = self.ser_layout.json_ident(self.json_ident);
+
+        if self.ser_layout != SerLayout::Split {
+            let dest = json_ser_with_ident(self, ident)?;
+            std::fs::write(path, dest)?;
+            // the rooms are in the map file now. Files of rooms not in this map are left alone
+            let inline = self.rooms.values()
+                .map(|r| r.uuid )
+                .collect::<HashSet<_>>();
+            remove_split_rooms(path, |uuid| !inline.contains(uuid) )?;
+            return Ok(());
+        }
+
+        let dir = room_resource_dir(path);
+        if let Err(e) = std::fs::create_dir_all(&dir) && e.kind() != ErrorKind::AlreadyExists {
+            return Err(e.into());
+        }
+
+        for room in self.rooms.values() {
+            if room.transient {continue;}
+            let room_path = room_resource_path(path, &room.uuid);
+            let dest = json_ser_with_ident(room, ident)?;
+            // only touch changed rooms
+            if std::fs::read(&room_path).is_ok_and(|v| v == dest) {continue;}
+            std::fs::write(room_path, dest)?;
+        }
+
+        let rooms = std::mem::take(&mut self.rooms);
+        let dest = json_ser_with_ident(self, ident);
+        self.rooms = rooms;
+
+        std::fs::write(path, dest?)?;
+
+        // remove the files of deleted rooms
+        let keep = self.rooms.values()
+            .filter(|r| !r.transient )
+            .map(|r| r.uuid )
+            .collect::<HashSet<_>>();
+
+        remove_split_rooms(path, |uuid| keep.contains(uuid) )
+    }
+
+    /// With the split layout, fill the rooms from the _data/rooms folder
+    pub(crate) fn read_split_rooms(&mut self, path: &Path) -> anyhow::Result<()> {
+        if self.ser_layout != SerLayout::Split {return Ok(());}
+
+        for (uuid,room_path) in split_room_files(path)? {
+            let data = std::fs::read(&room_path)?;
+            let mut room = serde_json::from_slice::<Room>(&data)
+                .map_err(|e| anyhow::anyhow!("{}: {e}", room_path.to_string_lossy()) )?;
+            room.uuid = uuid;
+            self.rooms.insert(room);
+        }
+
+        Ok(())
+    }
+}
+
+/// The room files in _data/rooms, ordered by UUID
+fn split_room_files(map_path: &Path) -> anyhow::Result<Vec<(Uuid,PathBuf)>> {
+    let entries = match std::fs::read_dir(room_resource_dir(map_path)) {
+        Ok(v) => v,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut dest = vec![];
+    for entry in entries {
+        let entry = entry?;
+        let Some(uuid) = entry.file_name().to_str()
+            .and_then(|v| v.strip_suffix(".json") )
+            .and_then(|v| Uuid::parse_str(v).ok() )
+        else {continue};
+        dest.push((uuid,entry.path()));
+    }
+    dest.sort_by_key(|&(uuid,_)| uuid );
+
+    Ok(dest)
+}
+
+fn remove_split_rooms(map_path: &Path, keep: impl Fn(&Uuid) -> bool) -> anyhow::Result<()> {
+    for (uuid,room_path) in split_room_files(map_path)? {
+        if !keep(&uuid) {
+            std::fs::remove_file(room_path)?;
+        }
+    }
+    Ok(())
 }
 
 #[derive(Deserialize)]
@@ -129,68 +285,103 @@ slotmap::new_key_type! {
 
 impl Map {
     pub fn save_map(&mut self, uuidmap: &mut UUIDMap) {
+        let _ = self.try_save_map(uuidmap).unwrap_gui("Error saving map");
+    }
+
+    /// Save the dirty rooms and the map. The map is also written if rooms failed to save, which stay dirty and are listed in the error
+    pub fn try_save_map(&mut self, uuidmap: &mut UUIDMap) -> anyhow::Result<()> {
         let mut errors = vec![];
         let mut cleanup_res = vec![];
 
         let create_dir = |dir| {
             if let Err(e) = std::fs::create_dir_all(dir) {
-                if e.kind() != ErrorKind::AlreadyExists {
-                    gui_error("Failed to create dir for rooms", &e);
-                    if !self.dirty_rooms.is_empty() {
-                        return Err(e);
-                    }
+                if e.kind() != ErrorKind::AlreadyExists && !self.dirty_rooms.is_empty() {
+                    return Err(anyhow::Error::from(e).context("Failed to create dir for rooms"));
                 }
             }
             Ok(())
         };
 
-        let create_dirs = || -> anyhow::Result<()> {
-            create_dir(tex_resource_dir(&self.path))?;
-            create_dir(seltrix_resource_dir(&self.path))?;
-            Ok(())
-        };
-
-        if create_dirs().is_err() {
-            return;
-        }
+        create_dir(tex_resource_dir(&self.path))?;
+        create_dir(seltrix_resource_dir(&self.path))?;
 
         let current_time = chrono::Utc::now();
 
-        for dirty_room in self.dirty_rooms.drain() {
+        let mut failed_rooms = vec![];
+
+        for dirty_room in std::mem::take(&mut self.dirty_rooms) {
             if let Some(room) = self.state.rooms.get_mut(dirty_room) {
                 if room.loaded.as_ref().is_some_and(|v| v.dirty_file) && !room.transient {
                     room.mtime = current_time;
-                    if let Err(e) = room.save_room_res(self.path.clone(), &mut cleanup_res, uuidmap, self.id, dirty_room) {
-                        errors.push(e);
-                    } else {
-                        if let Some(v) = &mut room.loaded {v.dirty_file = false;}
+                    match room.save_room_res(self.path.clone(), self.state.res_storage, &self.state.uuid, uuidmap, self.id, dirty_room) {
+                        Ok(old) => {
+                            cleanup_res.extend(old);
+                            if let Some(v) = &mut room.loaded {v.dirty_file = false;}
+                        },
+                        Err(e) => {
+                            errors.push(format!("X{}Y{}Z{}: {e:#}", room.coord[0], room.coord[1], room.coord[2]));
+                            failed_rooms.push(dirty_room);
+                        },
                     }
                 }
             }
         }
+        // retried at the next save
+        self.dirty_rooms.extend(failed_rooms);
 
-        for room in self.state.quickroom_template.iter_mut().filter_map(Option::as_mut) {
+        for (i,room) in self.state.quickroom_template.iter_mut().enumerate() {
+            let Some(room) = room else {continue};
             if room.loaded.as_ref().is_some_and(|v| v.dirty_file) && !room.transient {
                 room.mtime = current_time;
-                if let Err(e) = room.save_room_res(self.path.clone(), &mut cleanup_res, uuidmap, self.id, RoomId::null()) {
-                    errors.push(e);
-                } else {
-                    if let Some(v) = &mut room.loaded {v.dirty_file = false;}
+                match room.save_room_res(self.path.clone(), self.state.res_storage, &self.state.uuid, uuidmap, self.id, RoomId::null()) {
+                    Ok(old) => {
+                        cleanup_res.extend(old);
+                        if let Some(v) = &mut room.loaded {v.dirty_file = false;}
+                    },
+                    Err(e) => errors.push(format!("Template {}: {e:#}", i + 1)),
                 }
             }
         }
 
-        if let Some(e) = errors.first() {
-            gui_error(&format!("Failed to save img of {} rooms", errors.len()), e);
+        self.state.mtime = current_time;
+
+        self.save_map2()?;
+
+        // with content storage, other rooms may still use the old files
+        let in_use = self.resources_in_use();
+
+        for resuuid in cleanup_res {
+            if in_use.contains(&resuuid) {continue;}
+            let _ = std::fs::remove_file(tex_resource_path(&self.path, &resuuid));
+            let _ = std::fs::remove_file(seltrix_resource_path(&self.path, &resuuid));
         }
 
-        self.state.mtime = current_time;
+        anyhow::ensure!(errors.is_empty(), "Failed to save img of {} rooms\n{}", errors.len(), errors.join("\n"));
+
+        Ok(())
+    }
 
-        let Some(_) = self.save_map2().unwrap_gui("Error saving map") else {return;};
+    /// resuuids of the rooms, the templates and the rooms deleted into the undo/redo buffers
+    pub fn resources_in_use(&self) -> HashSet<Uuid> {
+        fn walk_op(op: &RoomOp, dest: &mut HashSet<Uuid>) {
+            match op {
+                RoomOp::Ins(room) => {dest.insert(room.resuuid);},
+                RoomOp::Multi(ops) => for op in ops {walk_op(op, dest)},
+                _ => {},
+            }
+        }
 
-        for path in cleanup_res {
-            let _ = std::fs::remove_file(path);
+        let mut dest = HashSet::default();
+        for room in self.state.rooms.values() {
+            dest.insert(room.resuuid);
+        }
+        for room in self.state.quickroom_template.iter().filter_map(Option::as_ref) {
+            dest.insert(room.resuuid);
+        }
+        for (op,_) in self.undo_buf.iter().chain(&self.redo_buf) {
+            walk_op(op, &mut dest);
         }
+        dest
     }
 
     fn save_map2(&mut self) -> anyhow::Result<()> {
@@ -198,11 +389,7 @@ impl Map {
         self.state._serde_ssel_room = self.ssel_room.and_then(|r| self.state.rooms.get(r) ).map(|r| r.uuid );
         self.state._serde_template_room = self.template_room.and_then(|r| self.state.rooms.get(r) ).map(|r| r.uuid );
 
-        let dest = json_ser_with_ident(&self.state, self.state.json_ident)?;
-
-        std::fs::write(&self.path, dest)?;
-
-        Ok(())
+        self.state.write_file(&self.path)
     }
 
     fn unload_map(&self, uuidmap: &mut UUIDMap) {
@@ -248,10 +435,12 @@ impl Map {
             anyhow::bail!("Map already loaded: {}", header.uuid);
         }
 
-        let state = serde_json::from_slice::<MapState>(&data)?;
+        let mut state = serde_json::from_slice::<MapState>(&data)?;
 
         drop(data);
 
+        state.read_split_rooms(&path)?;
+
         let id = MapId::new();
 
         // check for room uuid collisions
@@ -262,8 +451,8 @@ impl Map {
             }
         }
         for (room_id,r) in &state.rooms {
-            if let Some(prev) = uuidmap.insert(r.resuuid, UUIDTarget::Resource(id, room_id)) {
-                uuidmap.insert(r.resuuid, prev);
+            // content storage shares resources between rooms of the map, also if the map was switched back to UUID storage
+            if !retain_res(uuidmap, r.resuuid, id, room_id) {
                 anyhow::bail!("UUID COLLISION {}", r.uuid);
             }
         }
@@ -323,6 +512,21 @@ impl Map {
             adaptpush_preview: None,
             adaptpush_show_preview: false,
             show_green_save_until: -1.0,
+            check_window: None,
+            diff_view: None,
+            multisel: Default::default(),
+            conn_types_window: false,
+            import_profiles_window: false,
+            route_view: None,
+            solve_view: None,
+            graph_cluster_z: false,
+            guide_per_level: false,
+            rooms_export: Default::default(),
+            slice_import: None,
+            capture: None,
+            edge_match: None,
+            resize_dialog: None,
+            multisel_drag: None,
         };
 
         if map.state.quickroom_template.is_empty() {
@@ -339,15 +543,24 @@ impl Map {
                 map.dirty_rooms.insert(id);
             }
             // eprintln!("Romer X{}Y{}Z{}",room.coord[0],room.coord[1],room.coord[2]);
-            if let Some(prev) = map.room_matrix.insert(room.coord, id) {
+            if map.room_matrix.get(room.coord).is_some() {
                 eprintln!("CORRUPTED ROOM @ X{}Y{}Z{}",room.coord[0],room.coord[1],room.coord[2]);
-                corrupted.push(prev);
+                corrupted.push(id);
+                continue;
             }
+            map.room_matrix.insert(room.coord, id);
         }
 
-        for room in corrupted {
-            //TODO try to put it into empty spaces
-            map.state.rooms.remove(room);
+        // move colliding rooms into the nearest empty space
+        for id in corrupted {
+            let room = &mut map.state.rooms[id];
+            if let Some(coord) = map.room_matrix.find_free_near(room.coord) {
+                eprintln!("Relocated room {} to X{}Y{}Z{}",room.uuid,coord[0],coord[1],coord[2]);
+                room.coord = coord;
+                map.room_matrix.insert(coord, id);
+            } else {
+                map.state.rooms.remove(id);
+            }
         }
 
         if map.dsel_room.is_none() {
@@ -382,7 +595,7 @@ impl Map {
     }
 
     pub fn new(path: PathBuf, rooms_size: [u32;2], uuidmap: &mut UUIDMap) -> Self {
-        assert!(rooms_size[0] % 16 == 0 && rooms_size[1] % 16 == 0);
+        assert!(rooms_size[0] % 8 == 0 && rooms_size[1] % 8 == 0);
 
         let current_time = chrono::Utc::now();
 
@@ -426,6 +639,11 @@ impl Map {
                 quickroom_template: std::iter::repeat_with(|| None).take(4).collect(),
                 set_dssel_merged: false,
                 quick_shift_keep_gap: true,
+                res_storage: ResStorage::Uuid,
+                ser_layout: SerLayout::Compact,
+                conn_types: default_conn_types(),
+                import_profiles: vec![],
+                drop_profile: None,
             },
             path,
             dirty_rooms: Default::default(),
@@ -459,6 +677,21 @@ impl Map {
             adaptpush_preview: None,
             adaptpush_show_preview: false,
             show_green_save_until: -1.0,
+            check_window: None,
+            diff_view: None,
+            multisel: Default::default(),
+            conn_types_window: false,
+            import_profiles_window: false,
+            route_view: None,
+            solve_view: None,
+            graph_cluster_z: false,
+            guide_per_level: false,
+            rooms_export: Default::default(),
+            slice_import: None,
+            capture: None,
+            edge_match: None,
+            resize_dialog: None,
+            multisel_drag: None,
         };
 
         uuidmap.insert(this.state.uuid, UUIDTarget::Map(this.id));
@@ -540,6 +773,39 @@ pub enum MapEditMode {
     ConnUp,
 }
 
+/// How the room image and sel matrix files are named
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[derive(Serialize, Deserialize, clap::ValueEnum)]
+pub enum ResStorage {
+    /// A fresh UUID on every save
+    #[default]
+    Uuid,
+    /// Named by the content. Identical rooms share the files and unchanged rooms keep them
+    Content,
+}
+
+/// How the map file is laid out
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[derive(Serialize, Deserialize)]
+pub enum SerLayout {
+    /// Everything in the map file, only pretty printed if json_ident is set
+    #[default]
+    Compact,
+    /// Pretty printed, so that every room field is on its own line
+    Lines,
+    /// Like Lines, but every room is in its own file in _data/rooms
+    Split,
+}
+
+impl SerLayout {
+    pub(crate) fn json_ident(self, json_ident: Option<u8>) -> Option<u8> {
+        match self {
+            Self::Compact => json_ident,
+            Self::Lines | Self::Split => Some(json_ident.unwrap_or(2)),
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 #[derive(Serialize, Deserialize)]
 pub enum DrawOp {
@@ -580,10 +846,12 @@ mod roommap_serde {
     where
         S: serde::Serializer
     {
-        let iter = v.iter()
-            .filter(|(_,r)| !r.transient )
-            .map(|(_,r)| (r.uuid,r) );
-        serializer.collect_map(iter)
+        // ordered by UUID, so that saving the same map gives the same file
+        let mut rooms = v.values()
+            .filter(|r| !r.transient )
+            .collect::<Vec<_>>();
+        rooms.sort_by_key(|r| r.uuid );
+        serializer.collect_map(rooms.into_iter().map(|r| (r.uuid,r) ))
     }
 
     pub(super) fn deserialize<'de,D>(deserializer: D) -> Result<RoomMap, D::Error>
@@ -0,0 +1,194 @@
+use egui::{Color32, CornerRadius, Pos2, StrokeKind};
+
+use crate::gui::doc::{DOC_MAP_GROUPDUP, DOC_MAP_GROUPMOVE};
+use crate::gui::init::SAM;
+use crate::gui::rector;
+use crate::gui::room::{CONN_NONE, CONN_OPEN};
+use crate::gui::room::draw_image::DrawImageGroup;
+use crate::gui::util::{dpad, ArrUtl, DragOp, ResponseUtil};
+use crate::util::gui_error;
+
+use super::room_ops::RoomOp;
+use super::uuid::UUIDMap;
+use super::{next_ur_op_id, Map, RoomId};
+
+impl Map {
+    fn multisel_rooms(&self) -> Vec<RoomId> {
+        self.multisel.iter()
+            .cloned()
+            .filter(|&id| self.state.rooms.contains_key(id) )
+            .collect()
+    }
+
+    fn coord_at(&self, pos: Pos2) -> [u8;2] {
+        let [x,y] = <[f32;2]>::from(pos).as_u32().div(self.state.rooms_size);
+        [x.min(255) as u8, y.min(255) as u8]
+    }
+
+    /// ctrl-click on the map adds or removes a room
+    pub(super) fn multisel_toggle(&mut self, coord: [u8;3]) {
+        let Some(&id) = self.room_matrix.get(coord) else {return};
+        if self.state.rooms.get(id).is_some_and(|r| r.transient ) {return;}
+        if !self.multisel.remove(&id) {
+            self.multisel.insert(id);
+        }
+    }
+
+    /// Rubber band selection on the current level. Replaces the selection unless add
+    pub(super) fn multisel_drag_op(&mut self, op: DragOp, add: bool) {
+        match op {
+            DragOp::Start(p) => {
+                let c = self.coord_at(p);
+                self.multisel_drag = Some((c,c));
+            },
+            DragOp::Tick(Some(p)) => {
+                let c = self.coord_at(p);
+                if let Some((_,end)) = &mut self.multisel_drag {
+                    *end = c;
+                }
+            },
+            DragOp::End(p) => {
+                let c = self.coord_at(p);
+                let Some((start,_)) = self.multisel_drag.take() else {return};
+                if !add {
+                    self.multisel.clear();
+                }
+                let z = self.state.current_level;
+                for y in start[1].min(c[1]) ..= start[1].max(c[1]) {
+                    for x in start[0].min(c[0]) ..= start[0].max(c[0]) {
+                        if let Some(&id) = self.room_matrix.get([x,y,z]) && !self.state.rooms[id].transient {
+                            self.multisel.insert(id);
+                        }
+                    }
+                }
+            },
+            DragOp::Abort => self.multisel_drag = None,
+            _ => {},
+        }
+    }
+
+    /// Validate the whole group op before applying it as one undo entry
+    pub(super) fn ui_apply_group_op(&mut self, op: Option<RoomOp>, what: &str, uuidmap: &mut UUIDMap) -> bool {
+        let Some(op) = op else {
+            gui_error(&format!("Cannot {what}"), "Destination is occupied or outside of the map");
+            return false;
+        };
+        let mut msg = String::new();
+        if !self.validate_apply(&op, &mut msg) {
+            gui_error(&format!("Cannot {what}"), msg);
+            return false;
+        }
+        let ur = self.apply_room_op(op, uuidmap);
+        self.undo_buf.push_back((ur,next_ur_op_id()));
+        self.after_room_op_apply_invalidation(false);
+        true
+    }
+
+    pub(super) fn ui_multisel_header(&mut self, ui: &mut egui::Ui, sam: &mut SAM) {
+        if self.multisel.is_empty() {return;}
+        let rooms = self.multisel_rooms();
+
+        ui.label(format!("{} rooms in group", rooms.len()));
+        if ui.button("Connect").on_hover_text("Connect all neighbored rooms of the group").clicked() {
+            let op = self.create_group_connect(&rooms, CONN_OPEN);
+            if op.is_some() {
+                self.ui_apply_group_op(op, "connect group", &mut sam.uuidmap);
+            }
+        }
+        if ui.button("Disconnect").on_hover_text("Disconnect the rooms of the group from each other").clicked() {
+            let op = self.create_group_connect(&rooms, CONN_NONE);
+            if op.is_some() {
+                self.ui_apply_group_op(op, "disconnect group", &mut sam.uuidmap);
+            }
+        }
+        if ui.button("Delete Group").on_hover_text("Must be double clicked").double_clicked() {
+            if self.dsel_room.is_some_and(|r| rooms.contains(&r) ) {
+                self.dsel_room = None;
+                self.editsel = DrawImageGroup::unsel(self.state.rooms_size);
+                self.post_drawroom_switch(&mut sam.uuidmap);
+            }
+            if self.ssel_room.is_some_and(|r| rooms.contains(&r) ) {
+                self.ssel_room = None;
+            }
+            let op = self.create_group_delete(&rooms);
+            if self.ui_apply_group_op(op, "delete group", &mut sam.uuidmap) {
+                self.multisel.clear();
+                self.dsel_updated();
+                self.ssel_updated();
+            }
+        }
+        ui.menu_button("Export PNGs", |ui| {
+            if ui.button("Export…").clicked() {
+                ui.close_menu();
+                self.export_rooms_dialog(rooms.clone());
+            }
+            ui.separator();
+            self.ui_rooms_export_opts(ui);
+        });
+        if ui.button("Clear Group").clicked() {
+            self.multisel.clear();
+        }
+    }
+
+    pub(super) fn ui_multisel_dpads(&mut self, ui: &mut egui::Ui, sam: &mut SAM) {
+        let visible = !self.multisel.is_empty();
+        dpad(
+            "Group Move",
+            20. * sam.dpi_scale, 32. * sam.dpi_scale, sam.dpi_scale, false, visible,
+            ui,
+            |_,clicked,axis,dir| {
+                if !clicked {return;}
+                let op = self.create_group_move(&self.multisel_rooms(), axis, dir);
+                self.ui_apply_group_op(op, "move group", &mut sam.uuidmap);
+            },
+        ).doc(DOC_MAP_GROUPMOVE);
+        dpad(
+            "Group Duplicate",
+            20. * sam.dpi_scale, 32. * sam.dpi_scale, sam.dpi_scale, false, visible,
+            ui,
+            |_,clicked,axis,dir| {
+                if !clicked {return;}
+                let op = self.create_group_duplicate(&self.multisel_rooms(), axis, dir, &mut sam.uuidmap);
+                if !self.ui_apply_group_op(op, "duplicate group", &mut sam.uuidmap) {return;}
+                // the undo of the inserts deletes the new rooms
+                let Some((RoomOp::Multi(ops),_)) = self.undo_buf.back() else {return};
+                let new_rooms = ops.iter()
+                    .filter_map(|op| match op {
+                        &RoomOp::Del(id) => Some(id),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                for &id in &new_rooms {
+                    self.state.rooms[id].update_uuidmap(id, &mut sam.uuidmap, self.id);
+                }
+                self.multisel = new_rooms.into_iter().collect();
+            },
+        ).doc(DOC_MAP_GROUPDUP);
+    }
+
+    pub(super) fn multisel_shapes(&self, mut dest: impl FnMut(egui::Shape)) {
+        let rs = self.state.rooms_size;
+        let color = Color32::from_rgb(0, 200, 255);
+        let stroke = egui::Stroke::new(2., color);
+
+        for &id in &self.multisel {
+            let Some(room) = self.state.rooms.get(id) else {continue};
+            let [x,y,z] = room.coord;
+            if z != self.state.current_level {continue;}
+            let rect = rector(
+                x as u32 * rs[0], y as u32 * rs[1],
+                (x as u32 + 1) * rs[0], (y as u32 + 1) * rs[1],
+            );
+            dest(egui::Shape::rect_filled(rect, CornerRadius::ZERO, color.gamma_multiply(0.2)));
+            dest(egui::Shape::rect_stroke(rect, CornerRadius::ZERO, stroke, StrokeKind::Inside));
+        }
+
+        if let Some((a,b)) = self.multisel_drag {
+            let rect = rector(
+                a[0].min(b[0]) as u32 * rs[0], a[1].min(b[1]) as u32 * rs[1],
+                (a[0].max(b[0]) as u32 + 1) * rs[0], (a[1].max(b[1]) as u32 + 1) * rs[1],
+            );
+            dest(egui::Shape::rect_stroke(rect, CornerRadius::ZERO, egui::Stroke::new(1., color), StrokeKind::Middle));
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::path::PathBuf;
+
+use anyhow::{ensure, Context};
+
+use crate::gui::room::draw_image::DrawImageGroup;
+use crate::map::resize::{backup_map, check_rooms_size, resize_room, resize_warp_dests, ResizeAnchor};
+use crate::util::gui_error;
+
+use super::uuid::UUIDMap;
+use super::Map;
+
+pub struct ResizeDialog {
+    pub size: [u32;2],
+    pub anchor: ResizeAnchor,
+}
+
+const ANCHOR_ICONS: [&str;9] = ["↖","↑","↗","←","•","→","↙","↓","↘"];
+
+impl Map {
+    pub(super) fn open_resize_dialog(&mut self) {
+        self.resize_dialog = Some(ResizeDialog {
+            size: self.state.rooms_size,
+            anchor: ResizeAnchor::Center,
+        });
+    }
+
+    pub(super) fn ui_resize_window(&mut self, ctx: &egui::Context, uuidmap: &mut UUIDMap) {
+        let Some(dialog) = &mut self.resize_dialog else {return};
+
+        let mut open = true;
+        let mut resize = false;
+
+        egui::Window::new(format!("Resize Rooms - {}", self.state.title))
+            .id(self.id.egui_id_map().with("resize"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label(format!("Currently {}x{}", self.state.rooms_size[0], self.state.rooms_size[1]));
+                ui.horizontal(|ui| {
+                    ui.label("Width");
+                    ui.add(egui::DragValue::new(&mut dialog.size[0]).speed(8).range(8 ..= 4096));
+                    ui.label("Height");
+                    ui.add(egui::DragValue::new(&mut dialog.size[1]).speed(8).range(8 ..= 4096));
+                });
+                dialog.size = dialog.size.map(|v| v / 8 * 8 );
+
+                ui.horizontal(|ui| {
+                    ui.label("Anchor");
+                    egui::Grid::new(self.id.egui_id_map().with("resize_anchor")).show(ui, |ui| {
+                        for (i,anchor) in ResizeAnchor::ALL.into_iter().enumerate() {
+                            ui.selectable_value(&mut dialog.anchor, anchor, ANCHOR_ICONS[i]);
+                            if i % 3 == 2 {
+                                ui.end_row();
+                            }
+                        }
+                    });
+                }).response.on_hover_text("Where the old room content stays, the rooms are padded with transparent pixels or cropped around it");
+
+                ui.label("The map is saved and backed up with its room files before. This can't be undone, except by the backup");
+                ui.label("Warps from other maps into this map keep their old destination positions");
+
+                resize = ui.add_enabled(dialog.size != self.state.rooms_size, egui::Button::new("Resize")).clicked();
+            });
+
+        if resize {
+            let ResizeDialog { size, anchor } = *dialog;
+            match self.resize_rooms(size, anchor, uuidmap) {
+                Ok(backup) => {
+                    eprintln!("Backup of {} at {}", self.path.to_string_lossy(), backup.to_string_lossy());
+                    open = false;
+                },
+                // with the failed rooms from the context
+                Err(e) => gui_error("Failed to resize rooms", format_args!("{e:#}")),
+            }
+        }
+
+        if !open {
+            self.resize_dialog = None;
+        }
+    }
+
+    /// Pad or crop all rooms and templates to the new size, after saving the map and backing it up with its room files.
+    ///
+    /// Clears the undo history, as it holds rooms of the old size. Returns the path of the backup.
+    pub fn resize_rooms(&mut self, new_size: [u32;2], anchor: ResizeAnchor, uuidmap: &mut UUIDMap) -> anyhow::Result<PathBuf> {
+        check_rooms_size(new_size)?;
+        let old_size = self.state.rooms_size;
+        ensure!(old_size != new_size, "The rooms are already {}x{}", new_size[0], new_size[1]);
+
+        self.post_drawroom_switch(uuidmap);
+
+        let rooms = self.state.rooms.values_mut()
+            .chain(self.state.quickroom_template.iter_mut().filter_map(Option::as_mut));
+        for room in rooms {
+            if room.transient {continue;}
+            ensure!(
+                room.ensure_loaded(&self.path, old_size),
+                "Failed to load room @ X{}Y{}Z{}", room.coord[0], room.coord[1], room.coord[2],
+            );
+        }
+
+        self.try_save_map(uuidmap).context("Saving failed, nothing was resized")?;
+        let backup = backup_map(&self.path).context("Backup failed, nothing was resized")?;
+
+        for (id,room) in &mut self.state.rooms {
+            if room.loaded.is_none() {continue;}
+            resize_room(room, old_size, new_size, anchor);
+            self.dirty_rooms.insert(id);
+        }
+        for room in self.state.quickroom_template.iter_mut().filter_map(Option::as_mut) {
+            if room.loaded.is_none() {continue;}
+            resize_room(room, old_size, new_size, anchor);
+        }
+
+        resize_warp_dests(&mut self.state, old_size, new_size, anchor);
+
+        self.state.view_pos = [0,1].map(|a| self.state.view_pos[a] * new_size[a] as f32 / old_size[a] as f32 );
+        self.state.rooms_size = new_size;
+
+        self.undo_buf.clear();
+        self.redo_buf.clear();
+        self.dsel_room = None;
+        self.editsel = DrawImageGroup::unsel(new_size);
+        self.smartmove_preview = None;
+        self.adaptpush_preview = None;
+        // fitted to or comparing with the old size
+        self.edge_match = None;
+        self.diff_view = None;
+        self.check_window = None;
+        self.after_room_op_apply_invalidation(false);
+
+        // the rooms which failed stay dirty and can be saved again, or the backup restored
+        self.try_save_map(uuidmap).with_context(|| format!(
+            "The rooms were resized, but not all could be saved. Save again, or restore the backup at {}",
+            backup.to_string_lossy(),
+        ))?;
+
+        Ok(backup)
+    }
+}
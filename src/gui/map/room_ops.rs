@@ -1,16 +1,17 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Write;
 
 use egui::{ColorImage, Color32};
 use image::RgbaImage;
+use slotmap::Key;
 
 use crate::gui::room::draw_image::DrawImageGroup;
-use crate::gui::room::Room;
+use crate::gui::room::{conn_exit, ConnType, Room, CONN_NONE, CONN_ONEWAY_IN};
 use crate::map::coord_store::CoordStore;
 use crate::util::next_op_gen_evo;
 use crate::SRc;
 
-use super::uuid::{UUIDMap, UUIDTarget};
+use super::uuid::{update_res, UUIDMap, UUIDTarget};
 use super::{next_op_gen_evo_n, Map, MapState, RoomId};
 
 pub enum RoomOp {
@@ -20,6 +21,8 @@ pub enum RoomOp {
     Collapse([u8;3],u8,OpAxis,bool,bool),
     Del(RoomId),
     Ins(Box<Room>),
+    /// Set the connection of the room at coord and the connection back of its neighbor
+    Conn([u8;3],OpAxis,bool,[ConnType;2]),
     Multi(Vec<RoomOp>),
 }
 
@@ -38,12 +41,32 @@ impl RoomOp {
                 format!("Delete room {}",try_print_roomcoord(state,id)),
             RoomOp::Ins(room) =>
                 format!("Insert room at x{}y{}z{}",room.coord[0],room.coord[1],room.coord[2]),
+            &RoomOp::Conn(c, ax, dir, v) => {
+                let what = match v {
+                    [CONN_NONE,_] => "Disconnect",
+                    [a,b] if (a | b) & CONN_ONEWAY_IN != 0 => "One-way connect",
+                    _ => "Connect",
+                };
+                format!("{what} room x{}y{}z{} {}",c[0],c[1],c[2],describe_direction(ax,dir))
+            },
             RoomOp::Multi(n) =>
                 format!("Multiple ops n{}",n.len()),
         }
     }
 }
 
+/// Like "X+", for exports
+pub fn short_direction(axis: OpAxis, dir: bool) -> &'static str {
+    match (axis,dir) {
+        (OpAxis::X, true) => "X+",
+        (OpAxis::X, false) => "X-",
+        (OpAxis::Y, true) => "Y+",
+        (OpAxis::Y, false) => "Y-",
+        (OpAxis::Z, true) => "Z+",
+        (OpAxis::Z, false) => "Z-",
+    }
+}
+
 pub fn describe_direction(axis: OpAxis, dir: bool) -> &'static str {
     match (axis,dir) {
         (OpAxis::X, true) => "Right (East)",
@@ -109,11 +132,16 @@ impl Map {
 
                 RoomOp::SiftSmart(opts.clone().flip_dir(), false)
             },
+            RoomOp::Conn(c, ax, dir, v) => {
+                let prev = self.set_conn_at(c, ax, dir, v);
+
+                RoomOp::Conn(c, ax, dir, prev)
+            },
             RoomOp::Multi(v) => {
-                let v = v.into_iter()
+                let mut v = v.into_iter()
                     .map(|v| self.apply_room_op(v, uuidmap) )
-                    .rev() // The ops obviously needs to reverted in reverse
-                    .collect();
+                    .collect::<Vec<_>>();
+                v.reverse(); // The ops obviously needs to reverted in reverse
 
                 RoomOp::Multi(v)
             },
@@ -121,6 +149,11 @@ impl Map {
     }
 
     pub fn validate_apply(&mut self, op: &RoomOp, messages: &mut String) -> bool {
+        self.validate_apply_sim(op, &mut ValidateSim::default(), messages)
+    }
+
+    /// Validate against the map with the changes of the previous ops of a Multi in sim
+    fn validate_apply_sim(&mut self, op: &RoomOp, sim: &mut ValidateSim, messages: &mut String) -> bool {
         let mut ok = true;
 
         macro_rules! testo {
@@ -132,17 +165,36 @@ impl Map {
             };
         }
 
+        let occupied = |sim: &ValidateSim, c: [u8;3]| sim.coords.get(&c).map_or(self.room_matrix.get(c).is_some(), Option::is_some);
+        let room_coord = |sim: &ValidateSim, r: RoomId| sim.rooms.get(&r).map_or(self.state.rooms.get(r).map(|r| r.coord ), |&c| c);
+
         match op {
-            RoomOp::Move(r,c) => {
-                testo!(self.state.rooms.contains_key(*r), "to-move room doesn't exist");
-                testo!(self.room_matrix.get(*c).is_none(), "move-dest is occupied");
+            &RoomOp::Move(r,c) => {
+                let old = room_coord(sim, r);
+                testo!(old.is_some(), "to-move room doesn't exist");
+                testo!(!occupied(sim, c), "move-dest is occupied");
+                if let Some(old) = old {
+                    sim.coords.insert(old, None);
+                }
+                sim.coords.insert(c, Some(r));
+                sim.rooms.insert(r, Some(c));
             },
-            RoomOp::Del(r) => {
-                testo!(self.state.rooms.contains_key(*r), "to-delete room doesn't exist");
+            &RoomOp::Del(r) => {
+                let old = room_coord(sim, r);
+                testo!(old.is_some(), "to-delete room doesn't exist");
+                if let Some(old) = old {
+                    sim.coords.insert(old, None);
+                }
+                sim.rooms.insert(r, None);
             },
             RoomOp::Ins(r) => {
-                testo!(self.room_matrix.get(r.coord).is_none(), "undelete-dest is occupied");
+                testo!(!occupied(sim, r.coord), "undelete-dest is occupied");
+                sim.coords.insert(r.coord, Some(RoomId::null()));
+            },
+            &RoomOp::Conn(c, _, _, _) => {
+                testo!(occupied(sim, c), "to-connect room doesn't exist");
             },
+            // not simulated, only valid as first op
             RoomOp::SiftAway(a, b, c, d) => {
                 testo!(self.check_shift_away(*a, *b, *c, *d), "check_shift_away failure");
             },
@@ -153,7 +205,7 @@ impl Map {
                 // UNCHECKED
             },
             RoomOp::Multi(v) => {
-                ok &= v.iter().all(|v| self.validate_apply(v, messages) );
+                ok &= v.iter().all(|v| self.validate_apply_sim(v, sim, messages) );
             },
         }
 
@@ -163,6 +215,7 @@ impl Map {
     pub fn after_room_op_apply_invalidation(&mut self, redo: bool) {
         self.conn_change_inval();
         self.picomap_tex.dirty();
+        self.multisel.retain(|&id| self.state.rooms.contains_key(id) );
         if !redo {
             self.redo_buf.clear();
         }
@@ -237,7 +290,7 @@ impl Map {
         room.transient = false;
         let room_id = self.state.rooms.insert(room);
         uuidmap.insert(room_uuid, UUIDTarget::Room(self.id, room_id));
-        uuidmap.insert(room_resuuld, UUIDTarget::Resource(self.id, room_id));
+        update_res(uuidmap, room_resuuld, self.id, room_id);
         if dirty_file {
             self.dirty_rooms.insert(room_id);
         }
@@ -322,6 +375,93 @@ impl Map {
         Some(RoomOp::Del(id))
     }
 
+    /// Move all rooms by one. The rooms in front go first, so the group never collides with itself
+    pub fn create_group_move(&self, rooms: &[RoomId], axis: OpAxis, dir: bool) -> Option<RoomOp> {
+        let mut moves = rooms.iter()
+            .map(|&id| {
+                let coord = self.state.rooms.get(id)?.coord;
+                try_side(coord, axis, dir, |dest| (id,coord,dest) )
+            })
+            .collect::<Option<Vec<_>>>()?;
+        if moves.is_empty() {return None;}
+
+        moves.sort_by_key(|&(_,coord,_)| coord[axis.axis_idx()] );
+        if dir {
+            moves.reverse();
+        }
+
+        Some(RoomOp::Multi(
+            moves.into_iter().map(|(id,_,dest)| RoomOp::Move(id, dest) ).collect()
+        ))
+    }
+
+    /// Clone the rooms next to the group, keeping the connections inside the group
+    pub fn create_group_duplicate(&mut self, rooms: &[RoomId], axis: OpAxis, dir: bool, uuidmap: &mut UUIDMap) -> Option<RoomOp> {
+        let coords = rooms.iter()
+            .map(|&id| self.state.rooms.get(id).map(|r| r.coord ) )
+            .collect::<Option<Vec<_>>>()?;
+        let ai = axis.axis_idx();
+        let min = coords.iter().map(|c| c[ai] ).min()?;
+        let max = coords.iter().map(|c| c[ai] ).max()?;
+        let extent = (max - min).checked_add(1)?;
+
+        let dests = coords.iter()
+            .map(|&c| {
+                let mut dest = c;
+                dest[ai] = if dir {c[ai].checked_add(extent)?} else {c[ai].checked_sub(extent)?};
+                Some(dest)
+            })
+            .collect::<Option<Vec<_>>>()?;
+        if dests.iter().any(|&d| self.room_matrix.get(d).is_some() ) {return None;}
+
+        let mut ops = Vec::with_capacity(rooms.len());
+        for (&id,&dest) in rooms.iter().zip(&dests) {
+            let room = &mut self.state.rooms[id];
+            room.ensure_loaded(&self.path, self.state.rooms_size);
+            let mut clone = room.create_clone(dest, self.state.rooms_size, uuidmap, self.id, &self.path)?;
+            try_6_sides(room.coord, |c2,ax,d| {
+                if coords.contains(&c2) {
+                    clone.dirconn[ax.axis_idx()][d as usize] = room.dirconn[ax.axis_idx()][d as usize];
+                }
+            });
+            ops.push(RoomOp::Ins(Box::new(clone)));
+        }
+
+        Some(RoomOp::Multi(ops))
+    }
+
+    pub fn create_group_delete(&self, rooms: &[RoomId]) -> Option<RoomOp> {
+        let ops = rooms.iter()
+            .map(|&id| self.create_delete_room(id) )
+            .collect::<Option<Vec<_>>>()?;
+        if ops.is_empty() {return None;}
+
+        Some(RoomOp::Multi(ops))
+    }
+
+    /// Connect all neighbored rooms of the group with the type, or disconnect them with CONN_NONE
+    pub fn create_group_connect(&self, rooms: &[RoomId], v: ConnType) -> Option<RoomOp> {
+        let coords = rooms.iter()
+            .filter_map(|&id| self.state.rooms.get(id).map(|r| r.coord ) )
+            .collect::<Vec<_>>();
+
+        let mut ops = vec![];
+        for &coord in &coords {
+            try_6_sides(coord, |c2,ax,dir| {
+                if !dir || !coords.contains(&c2) {return;}
+                let conn = |c: [u8;3], dir: bool| self.room_matrix.get(c)
+                    .and_then(|&id| self.state.rooms.get(id) )
+                    .map(|r| r.dirconn[ax.axis_idx()][dir as usize] );
+                if conn(coord, true) != Some(v) || conn(c2, false) != Some(v) {
+                    ops.push(RoomOp::Conn(coord, ax, true, [v,v]));
+                }
+            });
+        }
+        if ops.is_empty() {return None;}
+
+        Some(RoomOp::Multi(ops))
+    }
+
     pub fn create_shift_away(&mut self, base_coord: [u8;3], n_sift: u8, axis: OpAxis, dir: bool) -> Option<RoomOp> {
         if !self.check_shift_away(base_coord, n_sift, axis, dir) {return None;}
 
@@ -393,7 +533,7 @@ impl Map {
                 debug_assert!(prev.is_none());
 
                 if unconnect_new {
-                    room.dirconn[axis.axis_idx()][(!dir) as usize] = false;
+                    room.dirconn[axis.axis_idx()][(!dir) as usize] = CONN_NONE;
                     self.adaptpush_preview = None;
                     self.smartmove_preview = None;
                     // if room.dirconn[axis.axis_idx()][(!dir) as usize] {
@@ -440,7 +580,7 @@ impl Map {
                 debug_assert!(!room.transient);
                 if away_lock && !in_sift_range(room.coord, base_coord, axis, dir) {continue}
                 if let Some((sidetest_a,sidetest_b)) = sidetest {
-                    if !room.dirconn[sidetest_a as usize][sidetest_b as usize] {continue}
+                    if room.dirconn[sidetest_a as usize][sidetest_b as usize] == CONN_NONE {continue}
                 }
                 if room.op_evo != op_evo {
                     area_min[0] = area_min[0].min(room.coord[0]); area_max[0] = area_max[0].max(room.coord[0]);
@@ -450,10 +590,9 @@ impl Map {
                     room.op_evo = op_evo;
 
                     try_6_sides(room.coord, |side_coord,sidetest_a,sidetest_b| {
-                        if room.dirconn[sidetest_a as usize][sidetest_b as usize] {
-                            if let Some(&side_room_id) = self.room_matrix.get(side_coord) {
-                                flood_spin.push_back((side_room_id,Some((sidetest_a.axis_idx() as u8,!sidetest_b))));
-                            }
+                        if room.dirconn[sidetest_a as usize][sidetest_b as usize] != CONN_NONE
+                            && let Some(&side_room_id) = self.room_matrix.get(side_coord) {
+                            flood_spin.push_back((side_room_id,Some((sidetest_a.axis_idx() as u8,!sidetest_b))));
                         }
                     });
 
@@ -549,7 +688,7 @@ impl Map {
         }
 
         let conn = |room: &Room,ax: OpAxis,dir: bool| {
-            room.dirconn[ax.axis_idx()][dir as usize]
+            room.dirconn[ax.axis_idx()][dir as usize] != CONN_NONE
         };
 
         while let Some(next_id) = flood_spin.pop_front() {
@@ -740,17 +879,15 @@ impl Map {
 
             if unconnect_new {
                 try_6_sides(room.coord, |side_coord,sidetest_a,sidetest_b| {
-                    if dconn[sidetest_a as usize][sidetest_b as usize] {
-                        if let Some(&sid) = self.room_matrix.get(side_coord) {
-                            // now we have a new neighbor at that side, if not ours, we shall unconnect
-                            if let Some(nroom) = self.state.rooms.get_mut(sid) {
-                                if nroom.op_evo != op_evo && nroom.dirconn[sidetest_a as usize][!sidetest_b as usize] {
-                                    nroom.dirconn[sidetest_a as usize][!sidetest_b as usize] = false;
-                                    let room = unsafe { self.state.rooms.get_unchecked_mut(id) };
-                                    room.dirconn[sidetest_a as usize][sidetest_b as usize] = false;
-                                    self.conn_change_inval();
-                                }
-                            }
+                    if dconn[sidetest_a as usize][sidetest_b as usize] != CONN_NONE
+                        && let Some(&sid) = self.room_matrix.get(side_coord) {
+                        // now we have a new neighbor at that side, if not ours, we shall unconnect
+                        if let Some(nroom) = self.state.rooms.get_mut(sid)
+                            && nroom.op_evo != op_evo && nroom.dirconn[sidetest_a as usize][!sidetest_b as usize] != CONN_NONE {
+                            nroom.dirconn[sidetest_a as usize][!sidetest_b as usize] = CONN_NONE;
+                            let room = unsafe { self.state.rooms.get_unchecked_mut(id) };
+                            room.dirconn[sidetest_a as usize][sidetest_b as usize] = CONN_NONE;
+                            self.conn_change_inval();
                         }
                     }
                 });
@@ -764,19 +901,18 @@ impl Map {
         }
     }
 
-    pub fn set_room_connect(&mut self, room_id: RoomId, ax: OpAxis, dir: bool, v: bool) {
-        let set_dir = |room: &mut Room,ax: OpAxis,dir: bool| {
-            room.dirconn[ax.axis_idx()][dir as usize] = v;
-        };
+    /// If oneway, the connection only leads from the room to the side
+    pub fn set_room_connect(&mut self, room_id: RoomId, ax: OpAxis, dir: bool, v: ConnType, oneway: bool) {
+        let back = if oneway && v != CONN_NONE {v | CONN_ONEWAY_IN} else {v};
 
         let Some(room) = self.state.rooms.get_mut(room_id) else {return};
 
-        set_dir(room,ax,dir);
+        room.dirconn[ax.axis_idx()][dir as usize] = v;
 
         try_side(room.coord, ax, dir, |c2| {
             if let Some(&id) = self.room_matrix.get(c2) {
                 if let Some(room) = self.state.rooms.get_mut(id) {
-                    set_dir(room,ax,!dir);
+                    room.dirconn[ax.axis_idx()][!dir as usize] = back;
                 }
             }
         });
@@ -784,28 +920,57 @@ impl Map {
         self.conn_change_inval();
     }
 
+    /// Set the connection of the room at coord and of its neighbor back, returns the previous connections
+    fn set_conn_at(&mut self, coord: [u8;3], ax: OpAxis, dir: bool, v: [ConnType;2]) -> [ConnType;2] {
+        let mut prev = v;
+
+        if let Some(room) = self.room_matrix.get(coord).and_then(|&id| self.state.rooms.get_mut(id) ) {
+            prev[0] = std::mem::replace(&mut room.dirconn[ax.axis_idx()][dir as usize], v[0]);
+        }
+
+        try_side(coord, ax, dir, |c2| {
+            if let Some(room) = self.room_matrix.get(c2).and_then(|&id| self.state.rooms.get_mut(id) ) {
+                prev[1] = std::mem::replace(&mut room.dirconn[ax.axis_idx()][!dir as usize], v[1]);
+            }
+        });
+
+        self.conn_change_inval();
+        prev
+    }
+
+    /// Connected in any direction
     pub fn get_room_connected(&self, room_id: RoomId, ax: OpAxis, dir: bool) -> bool {
-        let conn = |room: &Room,ax: OpAxis,dir: bool| {
-            room.dirconn[ax.axis_idx()][dir as usize]
-        };
+        self.get_room_conn_ways(room_id, ax, dir).is_some()
+    }
 
-        let Some(room) = self.state.rooms.get(room_id) else {return false};
+    /// Whether the side can be passed from the room to the neighbor
+    pub fn get_room_passable(&self, room_id: RoomId, ax: OpAxis, dir: bool) -> bool {
+        self.get_room_conn_ways(room_id, ax, dir).is_some_and(|[out,_]| out )
+    }
 
-        if !conn(room,ax,dir) {return false;}
+    /// None if not connected, else whether the side can be passed out of the room and into the room
+    pub fn get_room_conn_ways(&self, room_id: RoomId, ax: OpAxis, dir: bool) -> Option<[bool;2]> {
+        let room = self.state.rooms.get(room_id)?;
+        let conn = room.dirconn[ax.axis_idx()][dir as usize];
 
-        try_side(room.coord, ax, dir, |c2| {
-            if let Some(&id) = self.room_matrix.get(c2) {
-                if let Some(room) = self.state.rooms.get(id) {
-                    return conn(room,ax,!dir);
-                }
-            }
-            true
-        }).unwrap_or(false)
+        if conn == CONN_NONE {return None;}
+
+        let back = try_side(room.coord, ax, dir, |c2| {
+            self.room_matrix.get(c2)
+                .and_then(|&id| self.state.rooms.get(id) )
+                .map(|r| r.dirconn[ax.axis_idx()][!dir as usize] )
+        })?;
+
+        match back {
+            Some(CONN_NONE) => None,
+            Some(back) => Some([conn_exit(conn), conn_exit(back)]),
+            None => Some([conn_exit(conn), true]),
+        }
     }
 
     pub fn get_room_and_connected(&self, room_id: RoomId, ax: OpAxis, dir: bool) -> Option<([u8;3],RoomId,bool)> {
         let conn = |room: &Room,ax: OpAxis,dir: bool| {
-            room.dirconn[ax.axis_idx()][dir as usize]
+            room.dirconn[ax.axis_idx()][dir as usize] != CONN_NONE
         };
 
         let room = self.state.rooms.get(room_id)?;
@@ -822,6 +987,14 @@ impl Map {
     }
 }
 
+/// Occupancy changes by the previous ops of a Multi
+#[derive(Default)]
+struct ValidateSim {
+    coords: HashMap<[u8;3],Option<RoomId>>,
+    /// None if deleted
+    rooms: HashMap<RoomId,Option<[u8;3]>>,
+}
+
 pub fn render_picomap(current_level: u8, room_matrix: &CoordStore<RoomId>, bg: Color32, fg: Color32) -> ColorImage {
     let mut pixels = Vec::with_capacity(256*256);
     for y in 0 .. 256u32 {
@@ -0,0 +1,237 @@
+use egui::{Color32, CornerRadius, StrokeKind};
+use uuid::Uuid;
+
+use crate::gui::rector;
+use crate::gui::window_states::map::Maps;
+use crate::map::graph::{EdgeVia, RoomGraph};
+use crate::util::gui_error;
+
+use super::room_ops::describe_direction;
+use super::{Map, MapEditMode, RoomId};
+
+/// Rooms reachable from a room, and the shortest route to another
+pub struct RouteView {
+    from: Uuid,
+    to: Option<Uuid>,
+    graph: RoomGraph,
+    reachable: Vec<bool>,
+    /// The unreachable rooms, grouped by how they are connected with each other
+    islands: Vec<Vec<usize>>,
+    /// Edges of the shortest route, None if there is no route
+    route: Option<Vec<usize>>,
+}
+
+impl Map {
+    pub(super) fn selected_room(&self) -> Option<RoomId> {
+        let room = if self.state.edit_mode == MapEditMode::DrawSel {self.dsel_room} else {self.ssel_room};
+        room.filter(|&r| self.state.rooms.get(r).is_some_and(|r| !r.transient ) )
+    }
+
+    pub(super) fn open_route_view(&mut self, other_maps: &Maps) {
+        let Some(room) = self.selected_room() else {
+            gui_error("Reachability", "Select the room to start from");
+            return;
+        };
+        self.route_view = Some(RouteView {
+            from: self.state.rooms[room].uuid,
+            to: None,
+            graph: Default::default(),
+            reachable: vec![],
+            islands: vec![],
+            route: None,
+        });
+        self.refresh_route(other_maps);
+    }
+
+    /// Build the graph again from all open maps
+    fn refresh_route(&mut self, other_maps: &Maps) {
+        let others = other_maps.open_maps.iter()
+            .filter(|&(&id,_)| id != self.id )
+            .filter_map(|(_,m)| m.try_borrow().ok() )
+            .collect::<Vec<_>>();
+        let graph = RoomGraph::build(std::iter::once(&self.state).chain(others.iter().map(|m| &m.state )));
+        drop(others);
+
+        let Some(view) = &mut self.route_view else {return};
+
+        let Some(from) = graph.node(&view.from) else {
+            self.route_view = None;
+            return;
+        };
+
+        view.reachable = graph.reachable(from);
+        view.islands = graph.islands(|n| !view.reachable[n] );
+        view.route = view.to
+            .and_then(|to| graph.node(&to) )
+            .and_then(|to| graph.shortest_path(from, to) );
+        view.graph = graph;
+    }
+
+    pub(super) fn route_overlay_shapes(&self, mut dest: impl FnMut(egui::Shape)) {
+        let Some(view) = &self.route_view else {return};
+        let graph = &view.graph;
+        let level = self.state.current_level;
+        let rs = self.state.rooms_size;
+
+        let here = |n: usize| graph.nodes[n].map == self.state.uuid && graph.nodes[n].coord[2] == level;
+        let room_rect = |[x,y,_]: [u8;3], inset: u32| rector(
+            x as u32 * rs[0] + inset, y as u32 * rs[1] + inset,
+            (x as u32 + 1) * rs[0] - inset, (y as u32 + 1) * rs[1] - inset,
+        );
+        let center = |[x,y,_]: [u8;3]| egui::pos2(
+            (x as f32 + 0.5) * rs[0] as f32,
+            (y as f32 + 0.5) * rs[1] as f32,
+        );
+
+        let reach_color = Color32::from_rgb(0, 200, 0);
+        let unreach_color = Color32::from_rgb(220, 0, 0);
+        let route_color = Color32::from_rgb(0, 200, 255);
+
+        for (n,node) in graph.nodes.iter().enumerate() {
+            if !here(n) {continue;}
+            let rect = room_rect(node.coord, 0);
+            if view.reachable[n] {
+                dest(egui::Shape::rect_filled(rect, CornerRadius::ZERO, reach_color.gamma_multiply(0.15)));
+            } else {
+                dest(egui::Shape::rect_filled(rect, CornerRadius::ZERO, unreach_color.gamma_multiply(0.25)));
+                dest(egui::Shape::rect_stroke(room_rect(node.coord, 2), CornerRadius::ZERO, egui::Stroke::new(2., unreach_color), StrokeKind::Inside));
+            }
+        }
+
+        if let Some(route) = &view.route {
+            let stroke = egui::Stroke::new(3., route_color);
+            for &e in route {
+                let edge = &graph.edges[e];
+                let (a,b) = (&graph.nodes[edge.from],&graph.nodes[edge.to]);
+                match edge.via {
+                    EdgeVia::Conn(..) if here(edge.from) && here(edge.to) => {
+                        dest(egui::Shape::line_segment([center(a.coord), center(b.coord)], stroke));
+                    },
+                    EdgeVia::Warp(_) if here(edge.from) && here(edge.to) => {
+                        dest(egui::Shape::dashed_line(&[center(a.coord), center(b.coord)], stroke, 8., 6.).into());
+                    },
+                    _ => {
+                        // the route leaves or enters the level or map here
+                        for n in [edge.from,edge.to] {
+                            if here(n) {
+                                dest(egui::Shape::circle_stroke(center(graph.nodes[n].coord), 10., stroke));
+                            }
+                        }
+                    },
+                }
+            }
+        }
+
+        for (uuid,color) in [(Some(view.from),reach_color),(view.to,route_color)] {
+            let Some(n) = uuid.and_then(|u| graph.node(&u) ) else {continue};
+            if here(n) {
+                dest(egui::Shape::rect_stroke(room_rect(graph.nodes[n].coord, 4), CornerRadius::ZERO, egui::Stroke::new(3., color), StrokeKind::Inside));
+            }
+        }
+    }
+
+    pub(super) fn ui_route_window(&mut self, ctx: &egui::Context, other_maps: &Maps) {
+        let selected = self.selected_room().map(|r| self.state.rooms[r].uuid );
+        let Some(view) = &mut self.route_view else {return};
+
+        let mut open = true;
+        let mut refresh = false;
+        let mut jump_to = None;
+
+        egui::Window::new(format!("Reachability - {}", self.state.title))
+            .id(self.id.egui_id_map().with("route"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let graph = &view.graph;
+                let describe = |n: usize| {
+                    let node = &graph.nodes[n];
+                    let [x,y,z] = node.coord;
+                    let map = graph.map_titles.get(&node.map).map_or("", String::as_str);
+                    format!("{map} X{x}Y{y}Z{z} {}", node.title)
+                };
+
+                ui.horizontal(|ui| {
+                    ui.label(format!("From: {}", graph.node(&view.from).map_or(String::new(), describe)));
+                    if ui.add_enabled(selected.is_some(), egui::Button::new("Set to selected")).clicked() {
+                        view.from = selected.unwrap();
+                        refresh = true;
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label(format!("To: {}", view.to.and_then(|t| graph.node(&t) ).map_or(String::new(), describe)));
+                    if ui.add_enabled(selected.is_some(), egui::Button::new("Set to selected")).clicked() {
+                        view.to = selected;
+                        refresh = true;
+                    }
+                    if view.to.is_some() && ui.button("Clear").clicked() {
+                        view.to = None;
+                        refresh = true;
+                    }
+                });
+                refresh |= ui.button("Refresh").on_hover_text("Analyze again with the current state of the open maps").clicked();
+                ui.separator();
+
+                let n_reachable = view.reachable.iter().filter(|&&r| r ).count();
+                ui.label(format!("{n_reachable} of {} rooms reachable", graph.nodes.len()));
+
+                if let Some(to) = view.to {
+                    match &view.route {
+                        Some(route) => {
+                            ui.label(format!("Route: {} steps, {} screens", route.len(), route.len() + 1));
+                            egui::ScrollArea::vertical()
+                                .id_salt("route")
+                                .max_height(200.)
+                                .show(ui, |ui| {
+                                    for &e in route {
+                                        let edge = &graph.edges[e];
+                                        let via = match edge.via {
+                                            EdgeVia::Conn(axis, dir, _) => describe_direction(axis, dir).to_owned(),
+                                            EdgeVia::Warp(_) => "Warp".to_owned(),
+                                        };
+                                        if ui.selectable_label(false, format!("{via} to {}", describe(edge.to))).clicked() {
+                                            jump_to = Some(edge.to);
+                                        }
+                                    }
+                                });
+                        },
+                        None if graph.node(&to).is_none() => {ui.label("The destination room doesn't exist anymore");},
+                        None => {ui.colored_label(Color32::RED, "No route");},
+                    }
+                    ui.separator();
+                }
+
+                if !view.islands.is_empty() {
+                    let n_unreachable = graph.nodes.len() - n_reachable;
+                    ui.colored_label(Color32::RED, format!("{n_unreachable} unreachable rooms in {} islands", view.islands.len()));
+                    egui::ScrollArea::vertical()
+                        .id_salt("islands")
+                        .max_height(200.)
+                        .show(ui, |ui| {
+                            for (i,island) in view.islands.iter().enumerate() {
+                                let text = format!("Island {}: {} rooms, {}", i + 1, island.len(), describe(island[0]));
+                                if ui.selectable_label(false, text).clicked() {
+                                    jump_to = Some(island[0]);
+                                }
+                            }
+                        });
+                }
+            });
+
+        if refresh {
+            self.refresh_route(other_maps);
+        }
+
+        if let Some(n) = jump_to && let Some(view) = &self.route_view {
+            let node = &view.graph.nodes[n];
+            if node.map == self.state.uuid {
+                let [x,y,z] = node.coord;
+                self.move_viewpos_centred([x,y]);
+                self.update_level(z);
+            }
+        }
+
+        if !open {
+            self.route_view = None;
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::{bail, ensure};
+use image::{GenericImageView, RgbaImage};
+
+use crate::gui::room::CONN_OPEN;
+use crate::gui::util::RfdUtil;
+use crate::util::img::load_image;
+use crate::util::{gui_error, ResultExt};
+
+use super::import_profile::{ui_import_profile_select, write_room_layer};
+use super::room_ops::{OpAxis, RoomOp};
+use super::uuid::UUIDMap;
+use super::{next_ur_op_id, Map};
+
+/// Neighbors are connected if at least this many pixel rows or columns are passable on both sides of the shared edge
+const MIN_PASSAGE: u32 = 8;
+
+pub struct SliceImport {
+    pub path: PathBuf,
+    source: RgbaImage,
+    /// Profile applied to the source image
+    pub profile: Option<usize>,
+    pub image: RgbaImage,
+    /// Coord of the room receiving the top left tile
+    pub origin: [u8;3],
+    /// Don't create rooms for tiles of a single colour, like the background around a world map
+    pub skip_uniform: bool,
+    /// Connect neighbored tiles whose shared edge contains passable pixels
+    pub connect: bool,
+    pub passable_color: [u8;3],
+    /// Max difference per channel to the passable color
+    pub tolerance: u8,
+    /// Layer of the created rooms receiving the tile, with empty layers below
+    pub layer: usize,
+}
+
+impl SliceImport {
+    /// Number of rooms in x and y, the tiles at the right and bottom border may be partial
+    pub fn tiles(&self, rooms_size: [u32;2]) -> [u32;2] {
+        [
+            self.image.width().div_ceil(rooms_size[0]),
+            self.image.height().div_ceil(rooms_size[1]),
+        ]
+    }
+
+    fn tile(&self, [tx,ty]: [u32;2], rooms_size: [u32;2]) -> RgbaImage {
+        let [x,y] = [tx * rooms_size[0], ty * rooms_size[1]];
+        let w = rooms_size[0].min(self.image.width() - x);
+        let h = rooms_size[1].min(self.image.height() - y);
+        let mut tile = RgbaImage::new(rooms_size[0], rooms_size[1]);
+        image::imageops::replace(&mut tile, &*self.image.view(x, y, w, h), 0, 0);
+        tile
+    }
+
+    fn skip_tile(&self, tile: &RgbaImage) -> bool {
+        // the padding of partial tiles at the border is transparent
+        let mut opaque = tile.pixels().filter(|p| p.0[3] != 0 );
+        let Some(first) = opaque.next() else {return true};
+        self.skip_uniform && opaque.all(|p| p == first )
+    }
+
+    fn passable(&self, p: image::Rgba<u8>) -> bool {
+        p.0[3] != 0 && (0..3).all(|i| p.0[i].abs_diff(self.passable_color[i]) <= self.tolerance )
+    }
+
+    /// Whether the right (or bottom) edge of a and the left (or top) edge of b are passable on both sides
+    fn edge_passable(&self, a: &RgbaImage, b: &RgbaImage, axis: OpAxis) -> bool {
+        let (w,h) = a.dimensions();
+        let passages = match axis {
+            OpAxis::X => (0..h).filter(|&y| self.passable(*a.get_pixel(w-1, y)) && self.passable(*b.get_pixel(0, y)) ).count(),
+            OpAxis::Y => (0..w).filter(|&x| self.passable(*a.get_pixel(x, h-1)) && self.passable(*b.get_pixel(x, 0)) ).count(),
+            OpAxis::Z => 0,
+        };
+        passages as u32 >= MIN_PASSAGE
+    }
+}
+
+impl Map {
+    pub(super) fn open_slice_import_dialog(&mut self) {
+        let mut dialog = rfd::FileDialog::new()
+            .add_filter("Image", &["png","jpg","jpeg","bmp","gif","webp"])
+            .set_title("Import image sliced into rooms");
+        if let Some(dir) = self.path.parent() {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(path) = dialog.try_set_parent().pick_file() else {return};
+
+        let Some(image) = load_image(&path).unwrap_gui("Failed to load image") else {return};
+
+        let origin = match self.state.ssel_coord {
+            Some(c) if c[2] == self.state.current_level => c,
+            _ => [0, 0, self.state.current_level],
+        };
+
+        self.slice_import = Some(SliceImport {
+            path,
+            source: image.to_rgba8(),
+            profile: None,
+            image: image.to_rgba8(),
+            origin,
+            skip_uniform: true,
+            connect: false,
+            passable_color: [0,0,0],
+            tolerance: 16,
+            layer: 0,
+        });
+    }
+
+    pub(super) fn ui_slice_import_window(&mut self, ctx: &egui::Context, uuidmap: &mut UUIDMap) {
+        let rooms_size = self.state.rooms_size;
+        let profiles = &self.state.import_profiles;
+        let Some(si) = &mut self.slice_import else {return};
+
+        let mut open = true;
+        let mut import = false;
+
+        egui::Window::new(format!("Import Image - {}", self.state.title))
+            .id(self.id.egui_id_map().with("slice_import"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let [tw,th] = si.tiles(rooms_size);
+                ui.label(si.path.to_string_lossy());
+                ui.label(format!("{}x{} pixels, {tw}x{th} rooms of {}x{}", si.image.width(), si.image.height(), rooms_size[0], rooms_size[1]));
+
+                ui.horizontal(|ui| {
+                    ui.label("Profile");
+                    if ui_import_profile_select(ui, self.id.egui_id_map().with("slice_import_profile"), profiles, &mut si.profile) {
+                        match si.profile.and_then(|i| profiles.get(i) ) {
+                            Some(p) => {
+                                si.image = p.process(&si.source);
+                                si.layer = p.layer;
+                            },
+                            None => si.image = si.source.clone(),
+                        }
+                    }
+                }).response.on_hover_text("Crop, scale and palette snap the whole image before slicing it");
+
+                ui.horizontal(|ui| {
+                    ui.label("Origin X");
+                    ui.add(egui::DragValue::new(&mut si.origin[0]));
+                    ui.label("Y");
+                    ui.add(egui::DragValue::new(&mut si.origin[1]));
+                    ui.label("Z");
+                    ui.add(egui::DragValue::new(&mut si.origin[2]));
+                }).response.on_hover_text("Coord of the room receiving the top left of the image");
+                ui.horizontal(|ui| {
+                    ui.label("Layer");
+                    ui.add(egui::DragValue::new(&mut si.layer).range(0 ..= 15));
+                }).response.on_hover_text("Layer of the created rooms receiving the image, with empty layers below");
+
+                ui.checkbox(&mut si.skip_uniform, "Skip uniform tiles")
+                    .on_hover_text("Don't create rooms for tiles of a single colour, like the background around a world map. Fully transparent tiles are always skipped");
+                ui.checkbox(&mut si.connect, "Connect passable edges")
+                    .on_hover_text(format!("Connect neighbored rooms if at least {MIN_PASSAGE} pixels on both sides of the shared edge have the passable color"));
+                ui.add_enabled_ui(si.connect, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Passable color");
+                        ui.color_edit_button_srgb(&mut si.passable_color);
+                        ui.label("Tolerance");
+                        ui.add(egui::DragValue::new(&mut si.tolerance));
+                    });
+                });
+
+                import = ui.button("Import").clicked();
+            });
+
+        if import {
+            let si = self.slice_import.take().unwrap();
+            let op = self.create_slice_import(&si, uuidmap);
+            self.slice_import = Some(si);
+            if let Some(op) = op.unwrap_gui("Cannot import image") {
+                let mut msg = String::new();
+                if self.validate_apply(&op, &mut msg) {
+                    let ur = self.apply_room_op(op, uuidmap);
+                    self.undo_buf.push_back((ur,next_ur_op_id()));
+                    self.after_room_op_apply_invalidation(false);
+                    open = false;
+                } else {
+                    gui_error("Cannot import image", msg);
+                }
+            }
+        }
+
+        if !open {
+            self.slice_import = None;
+        }
+    }
+
+    /// Create the rooms of the non-empty tiles of the image as one op
+    pub fn create_slice_import(&mut self, si: &SliceImport, uuidmap: &mut UUIDMap) -> anyhow::Result<RoomOp> {
+        let rooms_size = self.state.rooms_size;
+        let [ox,oy,z] = si.origin;
+        let [tw,th] = si.tiles(rooms_size);
+
+        ensure!(
+            ox as u32 + tw <= 256 && oy as u32 + th <= 256,
+            "The image needs {tw}x{th} rooms, which don't fit right or below of X{ox} Y{oy}",
+        );
+
+        let mut tiles = vec![];
+        for ty in 0 .. th {
+            for tx in 0 .. tw {
+                let tile = si.tile([tx,ty], rooms_size);
+                if si.skip_tile(&tile) {continue;}
+                let coord = [ox + tx as u8, oy + ty as u8, z];
+                if self.room_matrix.get(coord).is_some() {
+                    bail!("X{} Y{} Z{z} is already occupied", coord[0], coord[1]);
+                }
+                tiles.push(([tx,ty],tile));
+            }
+        }
+
+        ensure!(!tiles.is_empty(), "The image has no non-empty tiles");
+
+        // connections between the new rooms, set before inserting so that they are undone with them
+        let mut conns = vec![[[false;2];2]; tiles.len()];
+        if si.connect {
+            let tile_idx = tiles.iter()
+                .enumerate()
+                .map(|(i,&(pos,_))| (pos,i) )
+                .collect::<HashMap<[u32;2],usize>>();
+
+            for (i,&([tx,ty],ref a)) in tiles.iter().enumerate() {
+                for (axis,next) in [(OpAxis::X,[tx+1,ty]), (OpAxis::Y,[tx,ty+1])] {
+                    let Some(&j) = tile_idx.get(&next) else {continue};
+                    if si.edge_passable(a, &tiles[j].1, axis) {
+                        conns[i][axis.axis_idx()][1] = true;
+                        conns[j][axis.axis_idx()][0] = true;
+                    }
+                }
+            }
+        }
+
+        let layer = si.layer;
+        let mut ops = vec![];
+
+        for (([tx,ty],tile),conns) in tiles.into_iter().zip(conns) {
+            let coord = [ox + tx as u8, oy + ty as u8, z];
+            let Some(RoomOp::Ins(mut room)) = self.create_create_room(coord, uuidmap) else {
+                bail!("X{} Y{} Z{z} is already occupied", coord[0], coord[1]);
+            };
+
+            write_room_layer(&mut room, layer, &tile, rooms_size);
+
+            for axis in [OpAxis::X, OpAxis::Y] {
+                for dir in [false,true] {
+                    if conns[axis.axis_idx()][dir as usize] {
+                        room.dirconn[axis.axis_idx()][dir as usize] = CONN_OPEN;
+                    }
+                }
+            }
+
+            ops.push(RoomOp::Ins(room));
+        }
+
+        Ok(RoomOp::Multi(ops))
+    }
+}
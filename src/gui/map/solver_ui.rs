@@ -0,0 +1,221 @@
+use std::collections::HashSet;
+
+use egui::{Color32, CornerRadius, StrokeKind};
+use uuid::Uuid;
+
+use crate::gui::rector;
+use crate::gui::window_states::map::Maps;
+use crate::map::graph::{EdgeVia, RoomGraph};
+use crate::map::solver::{solve, Solution, SolveStep};
+use crate::util::gui_error;
+
+use super::room_ops::describe_direction;
+use super::Map;
+
+/// Progression with the items of the tags, from a start room
+pub struct SolveView {
+    start: Uuid,
+    graph: RoomGraph,
+    solution: Option<Solution>,
+    /// Rooms to highlight: unreachable items, and the rooms of stuck or locked doors
+    problems: HashSet<usize>,
+}
+
+impl Map {
+    pub(super) fn open_solve_view(&mut self, other_maps: &Maps) {
+        let Some(room) = self.selected_room() else {
+            gui_error("Solve", "Select the room to start from");
+            return;
+        };
+        self.solve_view = Some(SolveView {
+            start: self.state.rooms[room].uuid,
+            graph: Default::default(),
+            solution: None,
+            problems: HashSet::new(),
+        });
+        self.refresh_solve(other_maps);
+    }
+
+    fn refresh_solve(&mut self, other_maps: &Maps) {
+        let others = other_maps.open_maps.iter()
+            .filter(|&(&id,_)| id != self.id )
+            .filter_map(|(_,m)| m.try_borrow().ok() )
+            .collect::<Vec<_>>();
+        let graph = RoomGraph::build(std::iter::once(&self.state).chain(others.iter().map(|m| &m.state )));
+        drop(others);
+
+        let Some(view) = &mut self.solve_view else {return};
+
+        let Some(start) = graph.node(&view.start) else {
+            self.solve_view = None;
+            return;
+        };
+
+        let solution = solve(&graph, start);
+
+        view.problems.clear();
+        view.problems.extend(solution.unreachable_items.iter().map(|&(n,_,_)| n ));
+        for &(e,_) in &solution.locked {
+            view.problems.insert(graph.edges[e].from);
+        }
+        for softlock in &solution.softlocks {
+            for &door in &softlock.stuck {
+                view.problems.extend(solution.doors[door]);
+            }
+        }
+
+        view.solution = Some(solution);
+        view.graph = graph;
+    }
+
+    pub(super) fn solve_overlay_shapes(&self, mut dest: impl FnMut(egui::Shape)) {
+        let Some(view) = &self.solve_view else {return};
+        let Some(solution) = &view.solution else {return};
+        let graph = &view.graph;
+        let level = self.state.current_level;
+        let rs = self.state.rooms_size;
+
+        let problem_color = Color32::from_rgb(255, 120, 0);
+        let unreach_color = Color32::from_rgb(220, 0, 0);
+
+        for (n,node) in graph.nodes.iter().enumerate() {
+            if node.map != self.state.uuid || node.coord[2] != level {continue;}
+            let [x,y,_] = node.coord;
+            let rect = |inset: u32| rector(
+                x as u32 * rs[0] + inset, y as u32 * rs[1] + inset,
+                (x as u32 + 1) * rs[0] - inset, (y as u32 + 1) * rs[1] - inset,
+            );
+            if !solution.reached[n] {
+                dest(egui::Shape::rect_filled(rect(0), CornerRadius::ZERO, unreach_color.gamma_multiply(0.25)));
+            }
+            if view.problems.contains(&n) {
+                dest(egui::Shape::rect_stroke(rect(2), CornerRadius::ZERO, egui::Stroke::new(3., problem_color), StrokeKind::Inside));
+            }
+            if graph.node(&view.start) == Some(n) {
+                dest(egui::Shape::rect_stroke(rect(6), CornerRadius::ZERO, egui::Stroke::new(3., Color32::from_rgb(0, 200, 0)), StrokeKind::Inside));
+            }
+        }
+    }
+
+    pub(super) fn ui_solve_window(&mut self, ctx: &egui::Context, other_maps: &Maps) {
+        let selected = self.selected_room().map(|r| self.state.rooms[r].uuid );
+        let Some(view) = &mut self.solve_view else {return};
+
+        let mut open = true;
+        let mut refresh = false;
+        let mut jump_to = None;
+
+        egui::Window::new(format!("Solve - {}", self.state.title))
+            .id(self.id.egui_id_map().with("solve"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let graph = &view.graph;
+                let describe = |n: usize| {
+                    let node = &graph.nodes[n];
+                    let [x,y,z] = node.coord;
+                    let map = graph.map_titles.get(&node.map).map_or("", String::as_str);
+                    format!("{map} X{x}Y{y}Z{z} {}", node.title)
+                };
+
+                ui.horizontal(|ui| {
+                    ui.label(format!("Start: {}", graph.node(&view.start).map_or(String::new(), describe)));
+                    if ui.add_enabled(selected.is_some(), egui::Button::new("Set to selected")).clicked() {
+                        view.start = selected.unwrap();
+                        refresh = true;
+                    }
+                });
+                refresh |= ui.button("Refresh").on_hover_text("Solve again with the current state of the open maps").clicked();
+                ui.separator();
+
+                let Some(solution) = &view.solution else {return};
+                let describe_door = |door: usize| {
+                    let [a,b] = solution.doors[door];
+                    format!("{} - {}", describe(a), describe(b))
+                };
+
+                let n_items = graph.nodes.iter().map(|n| n.items.len() ).sum::<usize>();
+                let n_collected = solution.steps.iter().filter(|s| matches!(s, SolveStep::Collect(..)) ).count();
+                let n_reached = solution.reached.iter().filter(|&&r| r ).count();
+                ui.label(format!("{n_collected} of {n_items} items collected, {n_reached} of {} rooms reached", graph.nodes.len()));
+                if !solution.complete {
+                    ui.colored_label(Color32::YELLOW, "Too many ways to open the doors, not all were tried");
+                }
+
+                ui.collapsing(format!("Playthrough ({} steps)", solution.steps.len()), |ui| {
+                    egui::ScrollArea::vertical()
+                        .id_salt("steps")
+                        .max_height(200.)
+                        .show(ui, |ui| {
+                            for (i,step) in solution.steps.iter().enumerate() {
+                                let (text,n) = match step {
+                                    SolveStep::Collect(n,_,item) => (format!("{}. Collect {item} in {}", i + 1, describe(*n)), *n),
+                                    SolveStep::Open(door) => (format!("{}. Open {}", i + 1, describe_door(*door)), solution.doors[*door][0]),
+                                };
+                                if ui.selectable_label(false, text).clicked() {
+                                    jump_to = Some(n);
+                                }
+                            }
+                        });
+                });
+
+                if !solution.softlocks.is_empty() {
+                    ui.colored_label(Color32::RED, format!("{} softlocks", solution.softlocks.len()))
+                        .on_hover_text("Orders of opening doors, after which less can be reached than with the playthrough");
+                    egui::ScrollArea::vertical()
+                        .id_salt("softlocks")
+                        .max_height(200.)
+                        .show(ui, |ui| {
+                            for softlock in &solution.softlocks {
+                                let opened = softlock.opened.iter().map(|&d| describe_door(d) ).collect::<Vec<_>>();
+                                let text = format!("Open {}: stuck with {} items and {} rooms", opened.join(", then "), softlock.items, softlock.rooms);
+                                let response = ui.selectable_label(false, text);
+                                let stuck = softlock.stuck.iter().map(|&d| describe_door(d) ).collect::<Vec<_>>();
+                                if response.on_hover_text(format!("Stuck at {}", stuck.join(", "))).clicked() {
+                                    jump_to = softlock.opened.last().map(|&d| solution.doors[d][0] );
+                                }
+                            }
+                        });
+                }
+
+                if !solution.unreachable_items.is_empty() {
+                    ui.colored_label(Color32::RED, format!("{} unreachable items", solution.unreachable_items.len()));
+                    for (n,_,item) in &solution.unreachable_items {
+                        if ui.selectable_label(false, format!("{item} in {}", describe(*n))).clicked() {
+                            jump_to = Some(*n);
+                        }
+                    }
+                }
+
+                if !solution.locked.is_empty() {
+                    ui.colored_label(Color32::RED, format!("{} connections stay locked", solution.locked.len()));
+                    for (e,item) in &solution.locked {
+                        let edge = &graph.edges[*e];
+                        let via = match edge.via {
+                            EdgeVia::Conn(axis, dir, _) => describe_direction(axis, dir),
+                            EdgeVia::Warp(_) => "Warp",
+                        };
+                        if ui.selectable_label(false, format!("{via} of {}, needs {item}", describe(edge.from))).clicked() {
+                            jump_to = Some(edge.from);
+                        }
+                    }
+                }
+            });
+
+        if refresh {
+            self.refresh_solve(other_maps);
+        }
+
+        if let Some(n) = jump_to && let Some(view) = &self.solve_view {
+            let node = &view.graph.nodes[n];
+            if node.map == self.state.uuid {
+                let [x,y,z] = node.coord;
+                self.move_viewpos_centred([x,y]);
+                self.update_level(z);
+            }
+        }
+
+        if !open {
+            self.solve_view = None;
+        }
+    }
+}
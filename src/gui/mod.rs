@@ -20,6 +20,7 @@ pub mod filedrop;
 pub mod conndraw_state;
 pub mod key_manager;
 pub mod doc;
+pub mod search;
 
 pub type MutQueue = Vec<Box<dyn FnOnce(&mut SharedApp)>>;
 
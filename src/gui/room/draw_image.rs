@@ -7,13 +7,14 @@ use image::{GenericImage, GenericImageView, ImageBuffer, Pixel as _, RgbaImage};
 use lab::Lab;
 use serde::Deserialize;
 
+use crate::gui::map::conn_types::{conn_style, ConnTypeDef};
 use crate::gui::map::{RoomId, RoomMap, DirtyRooms, MapEditMode, LruCache};
 use crate::gui::util::ArrUtl;
 use crate::gui::{rector, line2};
 use crate::gui::sel_matrix::{SelPt, DIGMatrixAccess, DIGMatrixAccessMut, SelMatrix};
 use crate::gui::texture::TextureCell;
 
-use super::Room;
+use super::{conn_kind, Room, CONN_NONE, CONN_ONEWAY_IN, CONN_OPEN};
 
 #[derive(Deserialize)]
 pub struct DrawImage {
@@ -96,6 +97,15 @@ impl DrawImage {
         }
     }
 
+    /// Copy of the pixels of one layer
+    pub fn layer_image(&self, layer: usize, rooms_size: [u32;2]) -> RgbaImage {
+        assert!(layer < self.layers);
+
+        let uv = self.layer_uv(layer, rooms_size);
+        let y0 = (uv.min.y as f64 * self.img.height() as f64).round() as u32;
+        self.img.view(0, y0, rooms_size[0], rooms_size[1]).to_image()
+    }
+
     pub fn rgb_avg(&self, layer: usize, rooms_size: [u32;2]) -> ([u64;3],u64) {
         assert!(layer < self.layers);
 
@@ -569,7 +579,7 @@ impl Room {
         }
     }
 
-    pub fn render_conns(&self, mode: MapEditMode, off: [u32;2], rooms_size: [u32;2], mut dest: impl FnMut(egui::Shape), ctx: &egui::Context) {
+    pub fn render_conns(&self, mode: MapEditMode, off: [u32;2], rooms_size: [u32;2], conn_types: &[ConnTypeDef], mut dest: impl FnMut(egui::Shape), ctx: &egui::Context) {
         let dest_rect = rector(off[0], off[1], off[0]+rooms_size[0], off[1]+rooms_size[1]);
 
         let unconn_color = Color32::RED;
@@ -577,47 +587,84 @@ impl Room {
 
         let unconn_stroke = Stroke::new(1.5, unconn_color);
 
-        if mode == MapEditMode::ConnDown {
-            if !self.dirconn[2][0] {
-                dest(egui::Shape::rect_filled(dest_rect, CornerRadius::ZERO, unconn_color_fill))
-            }
-        } else if mode == MapEditMode::ConnUp {
-            if !self.dirconn[2][1] {
-                dest(egui::Shape::rect_filled(dest_rect, CornerRadius::ZERO, unconn_color_fill))
+        let text = |pos: Pos2, align: Align2, text: String, color: Color32| {
+            ctx.fonts(|fonts| {
+                egui::Shape::text(
+                    fonts,
+                    pos, //TODO multiply dpi?
+                    align,
+                    text,
+                    FontId::monospace(16.),
+                    color,
+                )
+            })
+        };
+
+        if mode == MapEditMode::ConnDown || mode == MapEditMode::ConnUp {
+            let conn = self.dirconn[2][(mode == MapEditMode::ConnUp) as usize];
+            if conn == CONN_NONE {
+                dest(egui::Shape::rect_filled(dest_rect, CornerRadius::ZERO, unconn_color_fill));
+            } else {
+                let (color,_) = conn_style(conn_types, conn);
+                if conn_kind(conn) != CONN_OPEN {
+                    dest(egui::Shape::rect_filled(dest_rect, CornerRadius::ZERO, color.gamma_multiply(0.25)));
+                }
+                if conn & CONN_ONEWAY_IN != 0 {
+                    let arrow = if mode == MapEditMode::ConnUp {"↓"} else {"↑"};
+                    dest(text(dest_rect.center(), Align2::CENTER_CENTER, arrow.to_owned(), color));
+                }
             }
         }
         if mode == MapEditMode::ConnDown || mode == MapEditMode::ConnUp || mode == MapEditMode::ConnXY || mode == MapEditMode::RoomSel {
-            if !self.dirconn[0][0] {
-                dest(egui::Shape::line_segment(line2(off[0], off[1], off[0], off[1]+rooms_size[1]), unconn_stroke));
-            }
-            if !self.dirconn[0][1] {
-                dest(egui::Shape::line_segment(line2(off[0]+rooms_size[0], off[1], off[0]+rooms_size[0], off[1]+rooms_size[1]), unconn_stroke));
-            }
-            if !self.dirconn[1][0] {
-                dest(egui::Shape::line_segment(line2(off[0], off[1], off[0]+rooms_size[0], off[1]), unconn_stroke));
-            }
-            if !self.dirconn[1][1] {
-                dest(egui::Shape::line_segment(line2(off[0], off[1]+rooms_size[1], off[0]+rooms_size[0], off[1]+rooms_size[1]), unconn_stroke));
+            let sides = [
+                (self.dirconn[0][0], line2(off[0], off[1], off[0], off[1]+rooms_size[1])),
+                (self.dirconn[0][1], line2(off[0]+rooms_size[0], off[1], off[0]+rooms_size[0], off[1]+rooms_size[1])),
+                (self.dirconn[1][0], line2(off[0], off[1], off[0]+rooms_size[0], off[1])),
+                (self.dirconn[1][1], line2(off[0], off[1]+rooms_size[1], off[0]+rooms_size[0], off[1]+rooms_size[1])),
+            ];
+
+            for (conn,[a,b]) in sides {
+                if conn == CONN_NONE {
+                    dest(egui::Shape::line_segment([a,b], unconn_stroke));
+                    continue;
+                }
+
+                let (color,icon) = conn_style(conn_types, conn);
+                let mid = a.lerp(b, 0.5);
+                let inward = (dest_rect.center() - mid).normalized();
+                let oneway_in = conn & CONN_ONEWAY_IN != 0;
+
+                if conn_kind(conn) != CONN_OPEN {
+                    // the door in the middle of the side
+                    dest(egui::Shape::line_segment([a.lerp(b, 0.25),a.lerp(b, 0.75)], Stroke::new(3., color)));
+                    if !icon.is_empty() {
+                        let pos = mid + inward * if oneway_in {20.} else {10.};
+                        dest(text(pos, Align2::CENTER_CENTER, icon.to_owned(), color));
+                    }
+                }
+                if oneway_in {
+                    // the one-way connection leads into the room
+                    let base = mid + inward * 2.;
+                    let across = egui::vec2(-inward.y, inward.x) * 6.;
+                    dest(egui::Shape::convex_polygon(vec![mid + inward * 11., base + across, base - across], color, Stroke::NONE));
+                }
             }
-            
+
             if mode == MapEditMode::ConnXY || mode == MapEditMode::RoomSel {
-                let note = match self.dirconn[2] {
-                    [false, false] => "\n",
-                    [false, true] => "U",
-                    [true, false] => "\nD",
-                    [true, true] => "U\nD",
-                };
-
-                ctx.fonts(|fonts| {
-                    dest(egui::Shape::text(
-                        fonts,
-                        Pos2 { x: (off[0]+rooms_size[0]) as f32 - 8., y: off[1] as f32 + 8. }, //TODO multiply dpi?
+                for (conn,note,arrow,y) in [(self.dirconn[2][1],"U","↓",8.),(self.dirconn[2][0],"D","↑",26.)] {
+                    if conn == CONN_NONE {continue;}
+                    let (color,icon) = conn_style(conn_types, conn);
+                    let mut note = if conn_kind(conn) == CONN_OPEN {note.to_owned()} else {format!("{icon}{note}")};
+                    if conn & CONN_ONEWAY_IN != 0 {
+                        note.push_str(arrow);
+                    }
+                    dest(text(
+                        Pos2 { x: (off[0]+rooms_size[0]) as f32 - 8., y: off[1] as f32 + y },
                         Align2::RIGHT_TOP,
                         note,
-                        FontId::monospace(16.),
-                        Color32::GREEN,
+                        color,
                     ));
-                });
+                }
             }
         }
     }
@@ -1,6 +1,6 @@
 use std::collections::VecDeque;
 use std::io::Cursor;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use egui::{TextureHandle, TextureOptions};
 use image::RgbaImage;
@@ -10,12 +10,12 @@ use uuid::Uuid;
 
 use crate::gui::texture::TextureCell;
 use crate::util::img::{decode_cache_qoi, encode_cache_qoi, load_image, write_png};
-use crate::util::uuid::{generate_res_uuid, generate_uuid, UUIDMap, UUIDTarget};
+use crate::util::uuid::{content_res_uuid, generate_res_uuid, generate_uuid, release_res, retain_res, update_res, UUIDMap, UUIDTarget};
 use crate::util::{gui_error, seltrix_resource_path, tex_resource_path, MapId, ResultExt};
 
 use self::draw_image::DrawImage;
 
-use super::map::RoomId;
+use super::map::{ResStorage, RoomId};
 use super::sel_matrix::{sel_entry_dims, SelMatrixLayered};
 use super::tags::TagMap;
 
@@ -27,6 +27,8 @@ pub struct Room {
     pub uuid: Uuid,
     pub coord: [u8;3],
     pub resuuid: Uuid,
+    #[serde(default)]
+    pub title: String,
     pub desc_text: String,
     #[serde(default, with = "indexmap::map::serde_seq")]
     pub tags: TagMap,
@@ -39,7 +41,7 @@ pub struct Room {
     pub layers: Vec<Layer>,
     pub selected_layer: usize,
     #[serde(with = "dirconn_serde")]
-    pub dirconn: [[bool;2];3],
+    pub dirconn: [[ConnType;2];3],
     pub ctime: chrono::DateTime<chrono::Utc>,
     pub mtime: chrono::DateTime<chrono::Utc>,
     #[serde(skip)]
@@ -47,6 +49,25 @@ pub struct Room {
     pub editor_hide_layers_above: bool,
 }
 
+/// Connection of a room side, CONN_NONE or the index + 1 into MapState::conn_types, with the CONN_ONEWAY_IN flag
+pub type ConnType = u8;
+
+pub const CONN_NONE: ConnType = 0;
+/// The default type, which plain connections of older maps are
+pub const CONN_OPEN: ConnType = 1;
+/// Flag of a connected side which can only be entered, making the connection one-way from the neighbor
+pub const CONN_ONEWAY_IN: ConnType = 0x80;
+
+/// The type of the connection, without the one-way flag
+pub fn conn_kind(v: ConnType) -> ConnType {
+    v & !CONN_ONEWAY_IN
+}
+
+/// Whether the room can be left through the connected side
+pub fn conn_exit(v: ConnType) -> bool {
+    v != CONN_NONE && v & CONN_ONEWAY_IN == 0
+}
+
 #[derive(Clone, PartialEq, Deserialize,Serialize)]
 pub struct Layer {
     pub vis: u8,
@@ -73,7 +94,7 @@ pub struct RoomLoaded {
 
 impl Room {
     pub fn create_empty(coord: [u8;3], rooms_size: [u32;2], image: RgbaImage, initial_layers: usize, uuidmap: &mut UUIDMap, map_id: MapId, map_path: impl Into<PathBuf>) -> Self {
-        assert!(rooms_size[0] % 16 == 0 && rooms_size[1] % 16 == 0);
+        assert!(rooms_size[0] % 8 == 0 && rooms_size[1] % 8 == 0);
         assert!(image.width() == rooms_size[0] && image.height() as usize == rooms_size[1] as usize * initial_layers);
 
         let current_time = chrono::Utc::now();
@@ -102,6 +123,7 @@ impl Room {
             layers: vec![Layer::new_visible();initial_layers],
             selected_layer: 0,
             dirconn: Default::default(),
+            title: Default::default(),
             desc_text: Default::default(),
             ctime: current_time,
             mtime: current_time,
@@ -117,7 +139,7 @@ impl Room {
 
     /// Please call room.ensure_loaded before
     pub fn create_clone(&self, coord: [u8;3], rooms_size: [u32;2], uuidmap: &mut UUIDMap, map_id: MapId, map_path: impl Into<PathBuf>) -> Option<Self> {
-        assert!(rooms_size[0] % 16 == 0 && rooms_size[1] % 16 == 0);
+        assert!(rooms_size[0] % 8 == 0 && rooms_size[1] % 8 == 0);
 
         let current_time = chrono::Utc::now();
 
@@ -147,6 +169,7 @@ impl Room {
             layers: self.layers.clone(),
             selected_layer: self.selected_layer,
             dirconn: Default::default(),
+            title: self.title.clone(),
             desc_text: self.desc_text.clone(),
             ctime: current_time,
             mtime: current_time,
@@ -162,7 +185,7 @@ impl Room {
 
     pub fn update_uuidmap(&self, room_id: RoomId, uuidmap: &mut UUIDMap, map_id: MapId) {
         uuidmap.insert(self.uuid, UUIDTarget::Room(map_id, room_id));
-        uuidmap.insert(self.resuuid, UUIDTarget::Resource(map_id, room_id));
+        update_res(uuidmap, self.resuuid, map_id, room_id);
     }
 
     pub fn load_tex<'a>(&'a mut self, map_path: impl Into<PathBuf>, rooms_size: [u32;2], ctx: &egui::Context) -> Option<&'a mut TextureHandle> {
@@ -217,7 +240,7 @@ impl Room {
             .ensure_image(&loaded.image.img, ctx))
     }
 
-    fn load_room_res(&mut self, map_path: impl Into<PathBuf>, rooms_size: [u32;2]) -> anyhow::Result<RoomLoaded> {
+    pub(crate) fn load_room_res(&self, map_path: impl Into<PathBuf>, rooms_size: [u32;2]) -> anyhow::Result<RoomLoaded> {
         let map_path = map_path.into();
         let sel_file = seltrix_resource_path(&map_path, &self.resuuid);
         let tex_file = tex_resource_path(map_path, &self.resuuid);
@@ -256,36 +279,53 @@ impl Room {
         Ok(loaded)
     }
 
-    pub fn save_room_res(&mut self, map_path: impl Into<PathBuf>, cleanup_old: &mut Vec<PathBuf>, uuidmap: &mut UUIDMap, map_id: MapId, room_id: RoomId) -> anyhow::Result<()> {
-        if !self.can_edit() || self.transient {return Ok(());}
+    /// Returns the replaced resource, to be deleted after the map is saved if nothing else uses it
+    pub fn save_room_res(&mut self, map_path: impl Into<PathBuf>, storage: ResStorage, map_uuid: &Uuid, uuidmap: &mut UUIDMap, map_id: MapId, room_id: RoomId) -> anyhow::Result<Option<Uuid>> {
+        if !self.can_edit() || self.transient {return Ok(None);}
         
         let map_path = map_path.into();
 
         if let Some(loaded) = &mut self.loaded {
-            let old_resuuid = self.resuuid;
-            self.resuuid = generate_res_uuid(uuidmap, &map_path);
-            uuidmap.remove(&old_resuuid);
-            uuidmap.insert(self.resuuid, UUIDTarget::Resource(map_id,room_id));
-
-            let old_tex_path = tex_resource_path(&map_path, &old_resuuid);
-            let old_sel_path = seltrix_resource_path(&map_path, &old_resuuid);
-            let new_tex_path = tex_resource_path(&map_path, &self.resuuid);
-            let new_sel_path = seltrix_resource_path(map_path, &self.resuuid);
-            if !loaded.image.img.is_empty() {
-                let mut buf = Vec::with_capacity(1024*1024);
-                write_png(&mut Cursor::new(&mut buf), &loaded.image.img)?;
-                std::fs::write(new_tex_path, buf)?;
-                cleanup_old.push(old_tex_path);
+            let mut tex_buf = Vec::with_capacity(1024*1024);
+            write_png(&mut Cursor::new(&mut tex_buf), &loaded.image.img)?;
+            let mut sel_buf = Vec::with_capacity(1024*1024);
+            loaded.sel_matrix.ser(&mut Cursor::new(&mut sel_buf))?;
+
+            let img = &loaded.image.img;
+
+            let (resuuid,write) = match storage {
+                ResStorage::Uuid => (generate_res_uuid(uuidmap, &map_path), true),
+                ResStorage::Content => {
+                    let uuid = content_res_uuid(map_uuid, &[&img.width().to_le_bytes()[..], img.as_raw(), &sel_buf[..]]);
+                    let taken = uuidmap.get(&uuid)
+                        .is_some_and(|t| !matches!(t, UUIDTarget::Resource(map,_) | UUIDTarget::SharedResource(map,_) if *map == map_id) );
+                    match stored_res_matches(&map_path, &uuid, img, &sel_buf) {
+                        _ if taken => (generate_res_uuid(uuidmap, &map_path), true),
+                        None => (uuid, true),
+                        Some(true) => (uuid, false),
+                        // hash collision or broken file, fall back to a fresh UUID
+                        Some(false) => (generate_res_uuid(uuidmap, &map_path), true),
+                    }
+                },
+            };
+
+            if write {
+                std::fs::write(tex_resource_path(&map_path, &resuuid), tex_buf)?;
+                std::fs::write(seltrix_resource_path(&map_path, &resuuid), sel_buf)?;
             }
-            if !loaded.sel_matrix.is_empty() {
-                let mut buf = Vec::with_capacity(1024*1024);
-                loaded.sel_matrix.ser(&mut Cursor::new(&mut buf))?;
-                std::fs::write(new_sel_path, buf)?;
-                cleanup_old.push(old_sel_path);
+
+            let old_resuuid = self.resuuid;
+            self.resuuid = resuuid;
+
+            if old_resuuid != resuuid {
+                release_res(uuidmap, old_resuuid);
+                retain_res(uuidmap, resuuid, map_id, room_id);
+                return Ok(Some(old_resuuid));
             }
+            update_res(uuidmap, resuuid, map_id, room_id);
         }
 
-        Ok(())
+        Ok(None)
     }
 
     // pub fn insert_layer(&mut self, off: usize) {
@@ -305,6 +345,7 @@ impl Room {
         if src.locked.is_some() {return;}
         self.ensure_loaded(map_path, rooms_size);
         self.selected_layer = src.selected_layer;
+        self.title = src.title.clone();
         self.desc_text = src.desc_text.clone();
         self.tags = src.tags.clone();
         self.layers = src.layers.clone();
@@ -319,6 +360,16 @@ impl Room {
     }
 }
 
+/// None if nothing is stored under the resuuid, else whether the stored content is the same
+fn stored_res_matches(map_path: &Path, resuuid: &Uuid, image: &RgbaImage, sel_buf: &[u8]) -> Option<bool> {
+    let tex_path = tex_resource_path(map_path, resuuid);
+    let Ok(stored_sel) = std::fs::read(seltrix_resource_path(map_path, resuuid)) else {return None};
+    if !tex_path.is_file() {return None;}
+    if stored_sel != sel_buf {return Some(false);}
+    let Ok(stored_img) = load_image(&tex_path) else {return Some(false)};
+    Some(stored_img.to_rgba8() == *image)
+}
+
 const ROOM_TEX_OPTS: TextureOptions = TextureOptions {
     magnification: egui::TextureFilter::Nearest,
     minification: egui::TextureFilter::Linear,
@@ -328,55 +379,38 @@ const ROOM_TEX_OPTS: TextureOptions = TextureOptions {
 
 mod dirconn_serde {
     use super::*;
-    use serde::de::Error;
 
-    pub(super) fn serialize<S>(v: &[[bool;2];3], serializer: S) -> Result<S::Ok, S::Error>
+    pub(super) fn serialize<S>(v: &[[ConnType;2];3], serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer
     {
-        stupid_dirconn_ser(*v).serialize(serializer)
+        v.serialize(serializer)
     }
 
-    pub(super) fn deserialize<'de,D>(deserializer: D) -> Result<[[bool;2];3], D::Error>
+    pub(super) fn deserialize<'de,D>(deserializer: D) -> Result<[[ConnType;2];3], D::Error>
     where
         D: serde::Deserializer<'de>
     {
-        let v = <[[u8;2];3]>::deserialize(deserializer)?;
-        let v = stupid_dirconn_deser(v).map_err(D::Error::custom)?;
-        Ok(v)
+        let v = <[[StoredConn;2];3]>::deserialize(deserializer)?;
+        Ok(v.map(|a| a.map(StoredConn::conn_type) ))
     }
 
-    fn stupid_dirconn_ser(v: [[bool;2];3]) -> [[u8;2];3] {
-        let mut dest = [[0u8;2];3];
-
-        for i in 0..3 {
-            for j in 0..2 {
-                dest[i][j] = v[i][j] as u8;
-            }
-        }
-
-        dest
+    /// Before typed connections, 1 was connected. Plain bools are also accepted
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StoredConn {
+        Bool(bool),
+        Type(ConnType),
     }
 
-    fn stupid_dirconn_deser(v: [[u8;2];3]) -> anyhow::Result<[[bool;2];3]> {
-        // https://github.com/rust-lang/rust/pull/43220
-
-        fn conv(v: u8) -> anyhow::Result<bool> {
-            match v {
-                0 => Ok(false),
-                1 => Ok(true),
-                _ => Err(anyhow::anyhow!("dirconn type must be 0 or 1"))
-            }
-        }
-        let mut dest = [[false;2];3];
-
-        for i in 0..3 {
-            for j in 0..2 {
-                dest[i][j] = conv(v[i][j])?;
+    impl StoredConn {
+        fn conn_type(self) -> ConnType {
+            match self {
+                Self::Bool(true) => CONN_OPEN,
+                Self::Bool(false) => CONN_NONE,
+                Self::Type(v) => v,
             }
         }
-
-        Ok(dest)
     }
 }
 
@@ -0,0 +1,181 @@
+use regex::{Regex, RegexBuilder};
+
+use crate::util::MapId;
+
+use super::dock::DockTab;
+use super::init::SharedApp;
+use super::map::{Map, RoomId};
+use super::room::draw_image::DrawImageGroup;
+use super::tags::WarpUR;
+
+/// Search of room titles, descriptions, layer labels and tag texts in all open maps
+pub struct Search {
+    query: String,
+    regex: bool,
+    case_sensitive: bool,
+    results: Vec<SearchHit>,
+    error: Option<String>,
+    searched: Option<(String,bool,bool)>,
+}
+
+struct SearchHit {
+    map: MapId,
+    map_title: String,
+    room: RoomId,
+    coord: [u8;3],
+    field: String,
+    text: String,
+}
+
+impl Search {
+    pub fn new() -> Self {
+        Self {
+            query: String::new(),
+            regex: false,
+            case_sensitive: false,
+            results: vec![],
+            error: None,
+            searched: None,
+        }
+    }
+
+    fn build_regex(&self) -> Result<Regex,regex::Error> {
+        let pattern = if self.regex {
+            self.query.clone()
+        } else {
+            regex::escape(&self.query)
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .build()
+    }
+
+    fn run(&mut self, state: &SharedApp) {
+        self.searched = Some((self.query.clone(),self.regex,self.case_sensitive));
+        self.results.clear();
+        self.error = None;
+
+        if self.query.is_empty() {return;}
+
+        let regex = match self.build_regex() {
+            Ok(v) => v,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                return;
+            },
+        };
+
+        for (&map_id,map) in &state.maps.open_maps {
+            search_map(map_id, &map.borrow(), &regex, &mut self.results);
+        }
+
+        self.results.sort_by(|a,b|
+            a.map_title.cmp(&b.map_title)
+                .then([a.coord[2],a.coord[1],a.coord[0]].cmp(&[b.coord[2],b.coord[1],b.coord[0]]))
+        );
+    }
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn search_map(map_id: MapId, map: &Map, regex: &Regex, dest: &mut Vec<SearchHit>) {
+    for (room_id,room) in &map.state.rooms {
+        if room.transient {continue;}
+
+        let mut hit = |field: String, text: &str| {
+            let Some(m) = regex.find(text) else {return};
+            // the line of the match
+            let start = text[..m.start()].rfind('\n').map_or(0, |i| i + 1 );
+            let end = text[m.end()..].find('\n').map_or(text.len(), |i| m.end() + i );
+            let line = &text[start..end];
+            let text = match line.char_indices().nth(80) {
+                Some((i,_)) => format!("{}…", &line[..i]),
+                None => line.to_owned(),
+            };
+            dest.push(SearchHit {
+                map: map_id,
+                map_title: map.state.title.clone(),
+                room: room_id,
+                coord: room.coord,
+                field,
+                text,
+            });
+        };
+
+        hit("Title".into(), &room.title);
+        hit("Desc".into(), &room.desc_text);
+        for (i,layer) in room.layers.iter().enumerate() {
+            hit(format!("Layer {i}"), &layer.label);
+        }
+        for tag in room.tags.values() {
+            hit("Tag".into(), &tag.text);
+        }
+    }
+}
+
+pub fn search_ui(state: &mut SharedApp, ui: &mut egui::Ui) {
+    let mut search = std::mem::take(&mut state.search);
+
+    let mut refresh = false;
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut search.query)
+                .hint_text("Search rooms and tags")
+                .desired_width(200. * state.sam.dpi_scale)
+        );
+        ui.checkbox(&mut search.regex, "Regex");
+        ui.checkbox(&mut search.case_sensitive, "Aa").on_hover_text("Case sensitive");
+        refresh = ui.button("Refresh").on_hover_text("Search again, to include changes of the maps").clicked();
+    });
+
+    if refresh || search.searched.as_ref() != Some(&(search.query.clone(),search.regex,search.case_sensitive)) {
+        search.run(state);
+    }
+
+    if let Some(e) = &search.error {
+        ui.colored_label(egui::Color32::RED, e);
+    } else if !search.query.is_empty() {
+        ui.label(format!("{} results", search.results.len()));
+    }
+
+    let mut jump = None;
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        for hit in &search.results {
+            let [x,y,z] = hit.coord;
+            let text = format!("{} X{x}Y{y}Z{z} {}: {}", hit.map_title, hit.field, hit.text);
+            if ui.selectable_label(false, text).clicked() {
+                jump = Some((hit.map,hit.room));
+            }
+        }
+    });
+
+    state.search = search;
+
+    if let Some((map_id,room_id)) = jump {
+        jump_to_room(state, map_id, room_id);
+    }
+}
+
+/// Show and select the room, like warping to it
+fn jump_to_room(state: &mut SharedApp, map_id: MapId, room_id: RoomId) {
+    let Some(map) = state.maps.open_maps.get(&map_id) else {return};
+    let mut map = map.borrow_mut();
+    let Some(room) = map.state.rooms.get(room_id) else {return};
+    let coord = room.coord;
+
+    state.sam.push_to_undo(WarpUR::current(&map, false), false);
+    map.move_viewpos_centred([coord[0],coord[1]]);
+    map.state.current_level = coord[2];
+    map.picomap_tex.dirty();
+    map.state.dsel_coord = Some(coord);
+    map.dsel_room = Some(room_id);
+    map.editsel = DrawImageGroup::single(room_id, coord, map.state.rooms_size);
+    map.post_drawroom_switch(&mut state.sam.uuidmap);
+    map.dsel_updated();
+    state.sam.set_focus_to = Some(DockTab::Map(map_id));
+    state.sam.push_to_undo(WarpUR::current(&map, true), false);
+}
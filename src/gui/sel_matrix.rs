@@ -249,7 +249,8 @@ impl SelMatrixLayered {
         Ok(())
     }
 
-    pub fn deser(mut src: impl std::io::Read, expected_size: [u32;2]) -> anyhow::Result<Self> {
+    /// Read only the header, returns the dims and the layer count
+    pub fn deser_header(mut src: impl std::io::Read) -> anyhow::Result<([u32;2],usize)> {
         let mut match_header = [0u8;SEL_MATRIX_FILE_HEADER.len()];
         let mut w = [0u8;4];
         let mut h = [0u8;4];
@@ -263,6 +264,16 @@ impl SelMatrixLayered {
         src.read_exact(&mut len)?;
         let size = [u32::from_le_bytes(w), u32::from_le_bytes(h)];
         let len = u64::from_le_bytes(len) as usize;
+        Ok((size,len))
+    }
+
+    /// The file size of a seltrix with the given dims and layer count
+    pub fn ser_len([w,h]: [u32;2], layers: usize) -> u64 {
+        SEL_MATRIX_FILE_HEADER.len() as u64 + 16 + w as u64 * h as u64 * layers as u64 * 8
+    }
+
+    pub fn deser(mut src: impl std::io::Read, expected_size: [u32;2]) -> anyhow::Result<Self> {
+        let (size,len) = Self::deser_header(&mut src)?;
         if size != expected_size {
             anyhow::bail!("sel matrix size mismatch");
         }
@@ -26,21 +26,24 @@ pub type TagMap = IndexMap<Uuid,TagState,BuildHasherDefault<AHasher>>;
 
 #[derive(Clone, Deserialize, Serialize)]
 pub struct TagState {
-    pos: [u32;2],
+    pub pos: [u32;2],
     show_text: bool,
     show_always: bool,
     pub text: String,
     #[serde(with = "parse_color")]
-    color: [u8;3],
-    warp_enabled: bool,
-    warp: Option<WarpDest>,
+    pub color: [u8;3],
+    pub warp_enabled: bool,
+    pub warp: Option<WarpDest>,
+    /// The item collected here, like a small key. Empty if none
+    #[serde(default)]
+    pub item: String,
 }
 
 #[derive(Clone, Deserialize, Serialize)]
 pub struct WarpDest {
-    dest_map: Uuid,
-    dest_room: Uuid,
-    dest_pos: [u32;2],
+    pub dest_map: Uuid,
+    pub dest_room: Uuid,
+    pub dest_pos: [u32;2],
 }
 
 impl TagState {
@@ -87,7 +90,7 @@ pub fn can_place_tag_here(v: &TagMap, pos: [u32;2]) -> bool {
         .all(|(_,v)| !v.may_overlap(pos) )
 }
 
-const RADIUS: u32 = 6;
+pub(crate) const RADIUS: u32 = 6;
 const RADIUSF: f32 = RADIUS as _;
 const RADIUS2: u32 = 9;
 
@@ -187,6 +190,7 @@ impl Map {
                     color: calc_text_color(room, sub_click_coord, self.state.rooms_size),
                     warp_enabled: true,
                     warp: None,
+                    item: String::new(),
                 };
                 room.tags.insert(uuid,tag);
                 sam.uuidmap.insert(uuid, UUIDTarget::Tag(self.id, id, uuid));
@@ -289,6 +293,10 @@ impl Map {
                     tag.warp = None;
                 }
             }
+            ui.separator();
+            ui.label("Item: ");
+            ui.add(egui::TextEdit::singleline(&mut tag.item).hint_text("None").desired_width(100. * sam.dpi_scale))
+                .on_hover_text("Item collected at the tag, like \"Small Key\". Connection types can require it");
         }
         ui.separator();
         if ui.button("Remove Tag").clicked() { // TODO integrate move/add/remove tag with map undoredo
@@ -368,7 +376,7 @@ pub struct WarpUR {
 }
 
 impl WarpUR {
-    fn current(map: &Map, pre: bool) -> Self {
+    pub(crate) fn current(map: &Map, pre: bool) -> Self {
         Self {
             pre,
             map: map.state.uuid,
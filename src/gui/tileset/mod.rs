@@ -6,6 +6,7 @@ use egui::{Color32, CursorIcon, Key, PointerButton, TextureOptions, Vec2};
 use image::RgbaImage;
 use serde::{Deserialize, Serialize};
 
+use crate::export::tiled::export_tileset_tsx;
 use crate::gui::util::dragslider_up;
 use crate::util::img::{load_image, write_png};
 use crate::util::{attached_to_path, gui_error, json_ser_with_ident, ResultExt, TilesetId};
@@ -24,7 +25,7 @@ use super::rector;
 use super::init::{SharedApp, SAM};
 use super::sel_matrix::{sel_entry_dims, SelMatrix, SelMatrixLayered};
 use super::texture::{RECT_0_0_1_1, TextureCell};
-use super::util::{alloc_painter_rel_ds, button_with_green_success, draw_grid, ArrUtl, DragOp, ResponseUtil};
+use super::util::{alloc_painter_rel_ds, button_with_green_success, draw_grid, ArrUtl, DragOp, ResponseUtil, RfdUtil};
 
 mod convert_0_1;
 
@@ -90,6 +91,9 @@ impl Tileset {
                 sam.mut_queue.push(Box::new(move |state: &mut SharedApp| {state.tilesets.open_tilesets.remove(&id);} ))
             }
             ui.text_edit_singleline(&mut self.state.title);
+            if ui.button("Export TSX").on_hover_text("Export as Tiled tileset, with a tile for every selection group").clicked() {
+                self.export_tsx_dialog();
+            }
         });
         ui.horizontal(|ui| {
             dragslider_up(&mut self.state.zoom, 0.03125, 1..=2, 1, ui);
@@ -331,6 +335,20 @@ impl Tileset {
         // let hover_pos = reg.hover_pos_rel();
     }
 
+    fn export_tsx_dialog(&self) {
+        let file_name = attached_to_path(self.path.file_name().unwrap_or_default(), ".tsx");
+        let mut dialog = rfd::FileDialog::new()
+            .add_filter("Tiled tileset", &["tsx"])
+            .set_file_name(file_name.to_string_lossy())
+            .set_title("Export Tiled tileset");
+        if let Some(dir) = self.path.parent() {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(path) = dialog.try_set_parent().save_file() else {return};
+
+        export_tileset_tsx(self, &path).unwrap_gui("Failed to export tileset");
+    }
+
     pub fn ui_save(&mut self, save_draw: bool) {
         if self.save_editstate() && save_draw && self.dirty_img {
             if let Err(e) = self.save_image() {
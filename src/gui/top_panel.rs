@@ -38,8 +38,9 @@ pub fn top_panel_ui(state: &mut SharedApp, ui: &mut egui::Ui) {
         if ui.button("Create Map:").clicked() {
             new_map(state);
         }
-        dragvalion_up(&mut state.top_panel.create_map_size[0], 16, 160..=320, 16, ui);
-        dragvalion_up(&mut state.top_panel.create_map_size[1], 16, 128..=240, 16, ui);
+        // any multiple of 8, like 160x144 (Game Boy) or 256x224 (SNES)
+        dragvalion_up(&mut state.top_panel.create_map_size[0], 8, 64..=512, 8, ui);
+        dragvalion_up(&mut state.top_panel.create_map_size[1], 8, 64..=512, 8, ui);
         ui.separator();
         if ui.button("Create Tileset:").clicked() {
             new_tileset(state);
@@ -86,7 +87,7 @@ pub fn top_panel_ui(state: &mut SharedApp, ui: &mut egui::Ui) {
 }
 
 fn new_map(state: &mut SharedApp) {
-    state.top_panel.create_map_size = state.top_panel.create_map_size.div([16,16]).mul([16,16]);
+    state.top_panel.create_map_size = state.top_panel.create_map_size.div8().mul8();
 
     let mut dialog = rfd::FileDialog::new();
     if let Some(v) = state.top_panel.last_map_path.as_ref().and_then(|f| f.parent() ) {
@@ -4,5 +4,6 @@ pub mod gui;
 pub mod map;
 pub mod cli;
 pub mod convert_0_1;
+pub mod export;
 
 type SRc<T> = std::rc::Rc<T>;
@@ -130,6 +130,32 @@ impl<T> CoordStore<T> {
         }
     }
 
+    /// Find the nearest free coord on the same Z level, then on the levels nearest to it
+    pub fn find_free_near(&self, [x,y,z]: [u8;3]) -> Option<[u8;3]> {
+        let levels = std::iter::once(z as i32)
+            .chain((1 ..= 255i32).flat_map(|d| [z as i32 + d, z as i32 - d] ))
+            .filter(|&v| (0..=255).contains(&v) );
+
+        for nz in levels {
+            if self.vacant_axis(nz as u8, OpAxis::Z) >= 256 * 256 {continue;}
+            for r in 0 ..= 255i32 {
+                for dy in -r ..= r {
+                    // only walk the ring of radius r
+                    let step = if dy.abs() == r {1} else {(2 * r) as usize};
+                    for dx in (-r ..= r).step_by(step.max(1)) {
+                        let (nx,ny) = (x as i32 + dx, y as i32 + dy);
+                        if !(0..=255).contains(&nx) || !(0..=255).contains(&ny) {continue;}
+                        let coord = [nx as u8, ny as u8, nz as u8];
+                        if self.get(coord).is_none() {
+                            return Some(coord);
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
     pub(crate) fn debug_walk(&self, mut f: impl FnMut([u8;3],&T)) {
         for z1 in 0 .. 16u8 {
         for y1 in 0 .. 16u8 {
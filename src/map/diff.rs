@@ -0,0 +1,242 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::export::load_room_image;
+use crate::gui::map::MapState;
+use crate::gui::room::Room;
+use crate::gui::room::draw_image::DrawImage;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffKind {
+    Added,
+    Removed,
+    /// Only the coord changed
+    Moved,
+    /// Changed, and possibly moved too
+    Modified,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RoomDiff {
+    pub uuid: Uuid,
+    pub kind: DiffKind,
+    pub old_coord: Option<[u8;3]>,
+    pub new_coord: Option<[u8;3]>,
+    /// Changed fields, besides the coord
+    pub changed: Vec<&'static str>,
+    /// Layers with changed pixels, None if the images couldn't be compared
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changed_layers: Option<Vec<usize>>,
+}
+
+impl RoomDiff {
+    pub fn is_moved(&self) -> bool {
+        self.old_coord.is_some() && self.new_coord.is_some() && self.old_coord != self.new_coord
+    }
+
+    /// The coord the room is shown at, the new one if it still exists
+    pub fn coord(&self) -> [u8;3] {
+        self.new_coord.or(self.old_coord).unwrap_or_default()
+    }
+}
+
+#[derive(Default, Serialize)]
+pub struct MapDiff {
+    pub rooms: Vec<RoomDiff>,
+}
+
+impl MapDiff {
+    pub fn count(&self, kind: DiffKind) -> usize {
+        self.rooms.iter().filter(|r| r.kind == kind ).count()
+    }
+}
+
+/// Compare two versions of a map, matching rooms by their UUID.
+///
+/// The room images of old are looked up at old_path, and at new_path if they don't exist there anymore.
+pub fn diff_maps(old: &MapState, old_path: &Path, new: &MapState, new_path: &Path) -> MapDiff {
+    let old_rooms = old.rooms.values().filter(|r| !r.transient ).map(|r| (r.uuid,r) ).collect::<std::collections::HashMap<_,_>>();
+    let new_rooms = new.rooms.values().filter(|r| !r.transient ).map(|r| (r.uuid,r) ).collect::<std::collections::HashMap<_,_>>();
+
+    let mut rooms = vec![];
+
+    for (&uuid,&o) in &old_rooms {
+        if !new_rooms.contains_key(&uuid) {
+            rooms.push(RoomDiff {
+                uuid,
+                kind: DiffKind::Removed,
+                old_coord: Some(o.coord),
+                new_coord: None,
+                changed: vec![],
+                changed_layers: None,
+            });
+        }
+    }
+
+    for (&uuid,&n) in &new_rooms {
+        let Some(&o) = old_rooms.get(&uuid) else {
+            rooms.push(RoomDiff {
+                uuid,
+                kind: DiffKind::Added,
+                old_coord: None,
+                new_coord: Some(n.coord),
+                changed: vec![],
+                changed_layers: None,
+            });
+            continue;
+        };
+
+        let mut changed = vec![];
+        if o.title != n.title {
+            changed.push("title");
+        }
+        if o.desc_text != n.desc_text {
+            changed.push("desc_text");
+        }
+        if serde_json::to_value(&o.tags).ok() != serde_json::to_value(&n.tags).ok() {
+            changed.push("tags");
+        }
+        if o.layers != n.layers {
+            changed.push("layers");
+        }
+        if o.dirconn != n.dirconn {
+            changed.push("dirconn");
+        }
+
+        let mut changed_layers = None;
+        if image_maybe_changed(o, n) {
+            match compare_images(o, old_path, old.rooms_size, n, new_path, new.rooms_size) {
+                Ok(layers) if layers.is_empty() => {},
+                Ok(layers) => {
+                    changed.push("image");
+                    changed_layers = Some(layers);
+                },
+                Err(_) => changed.push("image"),
+            }
+        }
+
+        let kind = if !changed.is_empty() {
+            DiffKind::Modified
+        } else if o.coord != n.coord {
+            DiffKind::Moved
+        } else {
+            continue
+        };
+
+        rooms.push(RoomDiff {
+            uuid,
+            kind,
+            old_coord: Some(o.coord),
+            new_coord: Some(n.coord),
+            changed,
+            changed_layers,
+        });
+    }
+
+    rooms.sort_by_key(|r| r.uuid );
+
+    MapDiff { rooms }
+}
+
+fn image_maybe_changed(old: &Room, new: &Room) -> bool {
+    // unsaved edits keep the resuuid
+    old.resuuid != new.resuuid || new.loaded.as_ref().is_some_and(|l| l.dirty_file )
+}
+
+/// Indices of the layers with changed pixels
+fn compare_images(old: &Room, old_path: &Path, old_size: [u32;2], new: &Room, new_path: &Path, new_size: [u32;2]) -> anyhow::Result<Vec<usize>> {
+    anyhow::ensure!(old_size == new_size, "Room sizes differ");
+
+    let old_image = load_old_room_image(old, old_path, new_path, old_size)?;
+    let new_image = load_room_image(new, new_path, new_size)?;
+
+    let layer_len = old_size[0] as usize * old_size[1] as usize * 4;
+    let layers = old_image.layers.max(new_image.layers);
+
+    Ok(
+        (0 .. layers)
+            .filter(|&i| layer_bytes(&old_image, i, layer_len) != layer_bytes(&new_image, i, layer_len) )
+            .collect()
+    )
+}
+
+/// Resources removed since the old version may still be in the new _data folder
+pub fn load_old_room_image(room: &Room, old_path: &Path, new_path: &Path, rooms_size: [u32;2]) -> anyhow::Result<DrawImage> {
+    load_room_image(room, old_path, rooms_size)
+        .or_else(|e| load_room_image(room, new_path, rooms_size).map_err(|_| e ) )
+}
+
+fn layer_bytes(image: &DrawImage, layer: usize, layer_len: usize) -> Option<&[u8]> {
+    if layer >= image.layers {return None;}
+    image.img.as_raw().get(layer * layer_len .. (layer + 1) * layer_len)
+}
+
+impl fmt::Display for RoomDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = match self.kind {
+            DiffKind::Added => '+',
+            DiffKind::Removed => '-',
+            DiffKind::Moved => '>',
+            DiffKind::Modified => '*',
+        };
+        let [x,y,z] = self.coord();
+        write!(f, "{sign} {} X{x}Y{y}Z{z}", self.uuid)?;
+        if self.is_moved() && let Some([x,y,z]) = self.old_coord {
+            write!(f, " (from X{x}Y{y}Z{z})")?;
+        }
+        if !self.changed.is_empty() {
+            write!(f, ": {}", self.changed.join(", "))?;
+        }
+        if let Some(layers) = &self.changed_layers {
+            let layers = layers.iter().map(|l| l.to_string() ).collect::<Vec<_>>();
+            write!(f, " (layers {})", layers.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for MapDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for room in &self.rooms {
+            writeln!(f, "{room}")?;
+        }
+        write!(
+            f, "{} added, {} removed, {} moved, {} modified",
+            self.count(DiffKind::Added), self.count(DiffKind::Removed),
+            self.count(DiffKind::Moved), self.count(DiffKind::Modified),
+        )
+    }
+}
+
+#[derive(clap::Args, Debug)]
+pub struct DiffArgs {
+    /// The older version of the map
+    pub old: PathBuf,
+    /// The newer version of the map
+    pub new: PathBuf,
+    /// Print the diff as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+pub fn diff_cmd(args: DiffArgs) -> anyhow::Result<()> {
+    let old = MapState::read_file(&args.old)
+        .with_context(|| format!("Reading {}", args.old.to_string_lossy()) )?;
+    let new = MapState::read_file(&args.new)
+        .with_context(|| format!("Reading {}", args.new.to_string_lossy()) )?;
+
+    let diff = diff_maps(&old, &args.old, &new, &args.new);
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else {
+        println!("{diff}");
+    }
+
+    Ok(())
+}
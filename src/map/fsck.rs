@@ -0,0 +1,424 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use image::RgbaImage;
+use uuid::Uuid;
+
+use crate::gui::map::room_ops::{describe_direction, try_side, OpAxis};
+use crate::gui::map::{MapState, RoomId};
+use crate::gui::room::{conn_exit, conn_kind, Room, CONN_NONE, CONN_ONEWAY_IN, CONN_OPEN};
+use crate::gui::sel_matrix::{sel_entry_dims, SelMatrixLayered};
+use crate::gui::tags::WarpDest;
+use crate::util::img::{load_image, write_png};
+use crate::util::{attached_to_path, seltrix_resource_path, tex_resource_path};
+
+use super::coord_store::CoordStore;
+
+#[derive(Default)]
+pub struct CheckReport {
+    pub issues: Vec<CheckIssue>,
+}
+
+pub struct CheckIssue {
+    pub at: IssueAt,
+    pub kind: IssueKind,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum IssueAt {
+    Room(RoomId,[u8;3]),
+    Template(usize),
+}
+
+pub enum IssueKind {
+    MissingTex,
+    MissingSel,
+    BrokenTex(String),
+    BrokenSel(String),
+    TexSize {
+        expected: [u32;2],
+        actual: [u32;2],
+    },
+    /// Another room was already at this coord
+    Collision,
+    /// The room is connected to the side, but the room on the side isn't connected back
+    AsymmetricConn(OpAxis,bool),
+    /// The connection type isn't defined in the map
+    UnknownConnType(OpAxis,bool,u8),
+    /// Both rooms are one-way connected into each other, so it can't be passed at all
+    BlockedConn(OpAxis,bool),
+    /// The warp target room doesn't exist in the destination map
+    DanglingWarp(Uuid),
+}
+
+impl IssueKind {
+    pub fn is_resource(&self) -> bool {
+        matches!(self, Self::MissingTex | Self::MissingSel | Self::BrokenTex(_) | Self::BrokenSel(_) | Self::TexSize{..})
+    }
+}
+
+impl fmt::Display for IssueAt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Room(_, [x,y,z]) => write!(f, "Room X{x}Y{y}Z{z}"),
+            Self::Template(i) => write!(f, "Template {}", i + 1),
+        }
+    }
+}
+
+impl fmt::Display for IssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingTex => write!(f, "Image file missing"),
+            Self::MissingSel => write!(f, "Sel matrix file missing"),
+            Self::BrokenTex(e) => write!(f, "Image file unreadable: {e}"),
+            Self::BrokenSel(e) => write!(f, "Sel matrix file invalid: {e}"),
+            Self::TexSize { expected, actual } => write!(f, "Image is {}x{}, expected {}x{}", actual[0], actual[1], expected[0], expected[1]),
+            Self::Collision => write!(f, "Coordinate collides with another room"),
+            Self::AsymmetricConn(axis, dir) => write!(f, "Connection {} isn't connected back", describe_direction(*axis, *dir)),
+            Self::UnknownConnType(axis, dir, v) => write!(f, "Connection {} has unknown type {v}", describe_direction(*axis, *dir)),
+            Self::BlockedConn(axis, dir) => write!(f, "One-way connection {} can't be passed in any direction", describe_direction(*axis, *dir)),
+            Self::DanglingWarp(tag) => write!(f, "Warp of tag {tag} points to a non-existing room"),
+        }
+    }
+}
+
+impl fmt::Display for CheckIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.at, self.kind)
+    }
+}
+
+/// Check a map and its resource files.
+///
+/// warp_ok returns whether the warp destination exists, or None if the destination map isn't known.
+///
+/// Resources of loaded rooms aren't checked, as the loaded room is written on save anyway.
+pub fn check_map(state: &MapState, map_path: &Path, warp_ok: impl Fn(&WarpDest) -> Option<bool>) -> CheckReport {
+    let mut report = CheckReport::default();
+    let rooms_size = state.rooms_size;
+
+    let mut matrix = CoordStore::<RoomId>::new();
+
+    for (id,room) in &state.rooms {
+        if room.transient {continue;}
+        if matrix.get(room.coord).is_some() {
+            report.push(IssueAt::Room(id, room.coord), IssueKind::Collision);
+            continue;
+        }
+        matrix.insert(room.coord, id);
+    }
+
+    for (id,room) in &state.rooms {
+        if room.transient {continue;}
+        let at = IssueAt::Room(id, room.coord);
+
+        if room.loaded.is_none() {
+            for kind in check_room_res(room, map_path, rooms_size) {
+                report.push(at, kind);
+            }
+        }
+
+        if matrix.get(room.coord) == Some(&id) {
+            for axis in [OpAxis::X, OpAxis::Y, OpAxis::Z] {
+                for dir in [false,true] {
+                    let conn = room.dirconn[axis.axis_idx()][dir as usize];
+                    if conn == CONN_NONE {continue;}
+                    if conn_kind(conn) == CONN_NONE || conn_kind(conn) as usize > state.conn_types.len() {
+                        report.push(at, IssueKind::UnknownConnType(axis, dir, conn_kind(conn)));
+                    }
+                    let side = try_side(room.coord, axis, dir, |c| matrix.get(c).map(|&id| &state.rooms[id] ) ).flatten();
+                    if let Some(side) = side {
+                        let back = side.dirconn[axis.axis_idx()][!dir as usize];
+                        if back == CONN_NONE {
+                            report.push(at, IssueKind::AsymmetricConn(axis, dir));
+                        } else if dir && !conn_exit(conn) && !conn_exit(back) {
+                            report.push(at, IssueKind::BlockedConn(axis, dir));
+                        }
+                    }
+                }
+            }
+        }
+
+        for (tag_id,tag) in &room.tags {
+            if let Some(warp) = &tag.warp && warp_ok(warp) == Some(false) {
+                report.push(at, IssueKind::DanglingWarp(*tag_id));
+            }
+        }
+    }
+
+    for (i,room) in state.quickroom_template.iter().enumerate() {
+        let Some(room) = room else {continue};
+        if room.loaded.is_some() {continue;}
+        for kind in check_room_res(room, map_path, rooms_size) {
+            report.push(IssueAt::Template(i), kind);
+        }
+    }
+
+    report
+}
+
+/// Warp check for maps given by the room UUIDs of each map
+pub fn warp_ok_in(known_maps: &HashMap<Uuid,Vec<Uuid>>) -> impl Fn(&WarpDest) -> Option<bool> + '_ {
+    |warp| {
+        known_maps.get(&warp.dest_map)
+            .map(|rooms| rooms.contains(&warp.dest_room) )
+    }
+}
+
+pub fn check_room_res(room: &Room, map_path: &Path, rooms_size: [u32;2]) -> Vec<IssueKind> {
+    let mut issues = vec![];
+
+    let sel_path = seltrix_resource_path(map_path, &room.resuuid);
+    let tex_path = tex_resource_path(map_path, &room.resuuid);
+
+    let mut sel_layers = None;
+    if !sel_path.is_file() {
+        issues.push(IssueKind::MissingSel);
+    } else {
+        match read_sel_layers(&sel_path, rooms_size) {
+            Ok(v) => sel_layers = Some(v),
+            Err(e) => issues.push(IssueKind::BrokenSel(format!("{e:#}"))),
+        }
+    }
+
+    if !tex_path.is_file() {
+        issues.push(IssueKind::MissingTex);
+    } else {
+        match load_image(&tex_path) {
+            Ok(image) => {
+                let actual = [image.width(), image.height()];
+                let expected = expected_tex_size(sel_layers, actual, rooms_size);
+                if actual != expected {
+                    issues.push(IssueKind::TexSize { expected, actual });
+                }
+            },
+            Err(e) => issues.push(IssueKind::BrokenTex(format!("{e}"))),
+        }
+    }
+
+    issues
+}
+
+/// Read and validate the seltrix header, returns the layer count
+fn read_sel_layers(path: &Path, rooms_size: [u32;2]) -> anyhow::Result<usize> {
+    let file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let (dims,layers) = SelMatrixLayered::deser_header(BufReader::new(file))?;
+    let expected_dims = sel_entry_dims(rooms_size);
+    anyhow::ensure!(dims == expected_dims, "Dimensions {}x{} don't match room size {}x{}", dims[0], dims[1], expected_dims[0], expected_dims[1]);
+    anyhow::ensure!(layers != 0, "No layers");
+    anyhow::ensure!(file_len == SelMatrixLayered::ser_len(dims, layers), "File size doesn't match {layers} layers");
+    Ok(layers)
+}
+
+fn expected_tex_size(sel_layers: Option<usize>, actual: [u32;2], rooms_size: [u32;2]) -> [u32;2] {
+    let layers = sel_layers.unwrap_or_else(|| (actual[1] / rooms_size[1]).max(1) as usize );
+    [rooms_size[0], rooms_size[1] * layers as u32]
+}
+
+impl CheckReport {
+    fn push(&mut self, at: IssueAt, kind: IssueKind) {
+        self.issues.push(CheckIssue { at, kind });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Repair the issues of the report.
+///
+/// - colliding rooms are moved to the nearest free coord
+/// - one-sided connections are disconnected
+/// - dangling warps are removed
+/// - missing or broken resource files are recreated, broken files are kept with .broken attached
+///
+/// Returns a line for every repair done.
+pub fn repair_map(state: &mut MapState, map_path: &Path, report: &CheckReport) -> Vec<String> {
+    let mut log = vec![];
+    let rooms_size = state.rooms_size;
+
+    let mut matrix = CoordStore::<RoomId>::new();
+    let colliding = report.issues.iter()
+        .filter_map(|i| match (i.at, &i.kind) {
+            (IssueAt::Room(id, _), IssueKind::Collision) => Some(id),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    for (id,room) in &state.rooms {
+        if room.transient || colliding.contains(&id) {continue;}
+        matrix.insert(room.coord, id);
+    }
+
+    let mut res_repaired = vec![];
+
+    for issue in &report.issues {
+        let room = match issue.at {
+            IssueAt::Room(id, _) => state.rooms.get_mut(id),
+            IssueAt::Template(i) => state.quickroom_template.get_mut(i).and_then(Option::as_mut),
+        };
+        let Some(room) = room else {continue};
+
+        match &issue.kind {
+            IssueKind::Collision => {
+                let IssueAt::Room(id, _) = issue.at else {continue};
+                match matrix.find_free_near(room.coord) {
+                    Some(coord) => {
+                        let [x,y,z] = coord;
+                        log.push(format!("{}: Moved to X{x}Y{y}Z{z}", issue.at));
+                        room.coord = coord;
+                        matrix.insert(coord, id);
+                    },
+                    None => log.push(format!("{}: No free coord to move to", issue.at)),
+                }
+            },
+            &IssueKind::AsymmetricConn(axis, dir) => {
+                room.dirconn[axis.axis_idx()][dir as usize] = CONN_NONE;
+                log.push(format!("{}: Disconnected {}", issue.at, describe_direction(axis, dir)));
+            },
+            &IssueKind::BlockedConn(axis, dir) => {
+                room.dirconn[axis.axis_idx()][dir as usize] &= !CONN_ONEWAY_IN;
+                log.push(format!("{}: Made connection {} passable", issue.at, describe_direction(axis, dir)));
+            },
+            &IssueKind::UnknownConnType(axis, dir, _) => {
+                let conn = &mut room.dirconn[axis.axis_idx()][dir as usize];
+                *conn = CONN_OPEN | (*conn & CONN_ONEWAY_IN);
+                log.push(format!("{}: Set connection {} to the open type", issue.at, describe_direction(axis, dir)));
+            },
+            IssueKind::DanglingWarp(tag_id) => {
+                if let Some(tag) = room.tags.get_mut(tag_id) {
+                    tag.warp = None;
+                    log.push(format!("{}: Removed warp of tag {tag_id}", issue.at));
+                }
+            },
+            kind if kind.is_resource() => {
+                if res_repaired.contains(&issue.at) {continue;}
+                res_repaired.push(issue.at);
+                match repair_room_res(room, map_path, rooms_size) {
+                    Ok(()) => log.push(format!("{}: Recreated resources", issue.at)),
+                    Err(e) => log.push(format!("{}: Failed to repair resources: {e:#}", issue.at)),
+                }
+            },
+            _ => {},
+        }
+    }
+
+    log
+}
+
+/// Recreate missing or broken resource files of the room, keeping what's still usable
+pub fn repair_room_res(room: &Room, map_path: &Path, rooms_size: [u32;2]) -> anyhow::Result<()> {
+    let sel_path = seltrix_resource_path(map_path, &room.resuuid);
+    let tex_path = tex_resource_path(map_path, &room.resuuid);
+
+    let sel_layers = read_sel_layers(&sel_path, rooms_size).ok();
+    let old_image = load_image(&tex_path).ok().map(|v| v.to_rgba8() );
+    let tex_dims = old_image.as_ref().map(|v| [v.width(), v.height()] );
+
+    let layers = sel_layers
+        .or(tex_dims.map(|d| expected_tex_size(None, d, rooms_size)[1] as usize / rooms_size[1] as usize ))
+        .unwrap_or(1);
+    let expected = [rooms_size[0], rooms_size[1] * layers as u32];
+
+    if sel_layers.is_none() {
+        keep_broken(&sel_path)?;
+        let sel_matrix = SelMatrixLayered::new(sel_entry_dims(rooms_size), layers);
+        let file = File::create(&sel_path).context("Creating sel matrix file")?;
+        sel_matrix.ser(BufWriter::new(file))?;
+    }
+
+    if tex_dims != Some(expected) {
+        let mut image = RgbaImage::new(expected[0], expected[1]);
+        if let Some(old) = &old_image {
+            image::imageops::replace(&mut image, old, 0, 0);
+        }
+        keep_broken(&tex_path)?;
+        let file = File::create(&tex_path).context("Creating image file")?;
+        write_png(BufWriter::new(file), &image)?;
+    }
+
+    Ok(())
+}
+
+fn keep_broken(path: &Path) -> anyhow::Result<()> {
+    if path.symlink_metadata().is_ok() {
+        let dest: PathBuf = attached_to_path(path, ".broken");
+        std::fs::rename(path, dest).context("Moving broken file")?;
+    }
+    Ok(())
+}
+
+#[derive(clap::Args, Debug)]
+pub struct CheckArgs {
+    /// Maps to check. Warps between the given maps are checked too
+    #[arg(required = true)]
+    pub maps: Vec<PathBuf>,
+    /// Repair what can be repaired, failing if issues remain. The map file is backed up with .bak attached before
+    #[arg(long)]
+    pub repair: bool,
+}
+
+pub fn check_cmd(args: CheckArgs) -> anyhow::Result<()> {
+    let mut states = vec![];
+    for path in args.maps {
+        let state = MapState::read_file(&path)
+            .with_context(|| format!("Reading {}", path.to_string_lossy()) )?;
+        states.push((path,state));
+    }
+
+    let known_maps_of = |states: &[(PathBuf,MapState)]| states.iter()
+        .map(|(_,state)| (state.uuid, state.rooms.values().map(|r| r.uuid ).collect()) )
+        .collect::<HashMap<_,_>>();
+    let known_maps = known_maps_of(&states);
+
+    let mut n_issues = 0;
+
+    for (path,state) in &mut states {
+        let report = check_map(state, path, warp_ok_in(&known_maps));
+
+        println!("{}: {} issues", path.to_string_lossy(), report.issues.len());
+        for issue in &report.issues {
+            println!("  {issue}");
+        }
+        n_issues += report.issues.len();
+
+        if args.repair && !report.is_empty() {
+            std::fs::copy(&*path, attached_to_path(&*path, ".bak")).context("Backing up map")?;
+            for line in repair_map(state, path, &report) {
+                println!("  Repair {line}");
+            }
+            state.write_file(path).context("Writing repaired map")?;
+        }
+    }
+
+    if n_issues != 0 && args.repair {
+        // the repaired maps may have dropped rooms which other maps warp to
+        let known_maps = known_maps_of(&states);
+        n_issues = 0;
+        for (path,state) in &states {
+            let report = check_map(state, path, warp_ok_in(&known_maps));
+            if !report.is_empty() {
+                println!("{}: {} issues remain after repair", path.to_string_lossy(), report.issues.len());
+                for issue in &report.issues {
+                    println!("  {issue}");
+                }
+            }
+            n_issues += report.issues.len();
+        }
+        if n_issues != 0 {
+            anyhow::bail!("{n_issues} issues remain after repair");
+        }
+    }
+
+    if n_issues != 0 {
+        anyhow::bail!("{n_issues} issues found");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use uuid::Uuid;
+
+use crate::gui::map::MapState;
+use crate::util::{quarantine_resource_dir, seltrix_resource_dir, tex_resource_dir};
+
+/// Resource files in the _data folder which no room refers to
+#[derive(Default)]
+pub struct OrphanReport {
+    pub files: Vec<(PathBuf,u64)>,
+}
+
+impl OrphanReport {
+    pub fn total_size(&self) -> u64 {
+        self.files.iter().map(|(_,size)| size ).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+/// The resuuids of all rooms and quickroom templates
+pub fn referenced_resources(state: &MapState) -> HashSet<Uuid> {
+    let rooms = state.rooms.values()
+        .filter(|r| !r.transient );
+    let templates = state.quickroom_template.iter()
+        .filter_map(Option::as_ref);
+
+    rooms.chain(templates)
+        .map(|r| r.resuuid )
+        .collect()
+}
+
+/// Files named like resources, but not in referenced. Other files in the folders are left alone.
+pub fn find_orphans(map_path: &Path, referenced: &HashSet<Uuid>) -> anyhow::Result<OrphanReport> {
+    let mut report = OrphanReport::default();
+
+    for (dir,ext) in [(tex_resource_dir(map_path),".png"),(seltrix_resource_dir(map_path),".sel")] {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("Reading {}", dir.to_string_lossy()) ),
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(uuid) = name.to_str()
+                .and_then(|v| v.strip_suffix(ext) )
+                .and_then(|v| Uuid::parse_str(v).ok() )
+            else {continue};
+
+            if referenced.contains(&uuid) {continue;}
+
+            let meta = entry.metadata()?;
+            if !meta.is_file() {continue;}
+
+            report.files.push((entry.path(), meta.len()));
+        }
+    }
+
+    report.files.sort_by(|a,b| a.0.cmp(&b.0) );
+
+    Ok(report)
+}
+
+pub fn delete_orphans(report: &OrphanReport) -> anyhow::Result<()> {
+    for (path,_) in &report.files {
+        std::fs::remove_file(path)
+            .with_context(|| format!("Deleting {}", path.to_string_lossy()) )?;
+    }
+    Ok(())
+}
+
+/// Move the orphans into a new timestamped folder in the quarantine dir, returns that folder
+pub fn quarantine_orphans(report: &OrphanReport, map_path: &Path) -> anyhow::Result<PathBuf> {
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    let dest_dir = quarantine_resource_dir(map_path).join(stamp.to_string());
+
+    for (path,_) in &report.files {
+        // keep tex and sel apart
+        let kind = path.parent().and_then(Path::file_name).unwrap_or_default();
+        let dir = dest_dir.join(kind);
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Creating {}", dir.to_string_lossy()) )?;
+        let dest = dir.join(path.file_name().unwrap_or_default());
+        std::fs::rename(path, &dest)
+            .with_context(|| format!("Moving {}", path.to_string_lossy()) )?;
+    }
+
+    Ok(dest_dir)
+}
+
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str;4] = ["B","KiB","MiB","GiB"];
+    let mut v = size as f64;
+    let mut unit = 0;
+    while v >= 1024. && unit < UNITS.len() - 1 {
+        v /= 1024.;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{size} B")
+    } else {
+        format!("{v:.1} {}", UNITS[unit])
+    }
+}
+
+#[derive(clap::Args, Debug)]
+pub struct GcArgs {
+    /// Maps to collect garbage of
+    #[arg(required = true)]
+    pub maps: Vec<PathBuf>,
+    /// Delete the unreferenced files. Without --delete or --quarantine they are only listed
+    #[arg(long, conflicts_with = "quarantine")]
+    pub delete: bool,
+    /// Move the unreferenced files into the quarantine folder in the _data folder of the map
+    #[arg(long)]
+    pub quarantine: bool,
+}
+
+pub fn gc_cmd(args: GcArgs) -> anyhow::Result<()> {
+    for path in args.maps {
+        let state = MapState::read_file(&path)
+            .with_context(|| format!("Reading {}", path.to_string_lossy()) )?;
+
+        let report = find_orphans(&path, &referenced_resources(&state))?;
+
+        for (file,size) in &report.files {
+            println!("  {} ({})", file.to_string_lossy(), format_size(*size));
+        }
+        println!("{}: {} unreferenced files, {}", path.to_string_lossy(), report.files.len(), format_size(report.total_size()));
+
+        if report.is_empty() {continue;}
+
+        if args.delete {
+            delete_orphans(&report)?;
+            println!("Deleted");
+        } else if args.quarantine {
+            let dest = quarantine_orphans(&report, &path)?;
+            println!("Moved to {}", dest.to_string_lossy());
+        }
+    }
+
+    Ok(())
+}
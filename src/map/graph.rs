@@ -0,0 +1,215 @@
+use std::collections::{HashMap, VecDeque};
+
+use uuid::Uuid;
+
+use crate::gui::map::room_ops::{try_side, OpAxis};
+use crate::gui::map::conn_types::ConnTypeDef;
+use crate::gui::map::MapState;
+use crate::gui::room::{conn_exit, conn_kind, ConnType, CONN_NONE};
+
+pub struct GraphNode {
+    pub map: Uuid,
+    pub room: Uuid,
+    pub coord: [u8;3],
+    pub title: String,
+    /// The items of the tags, with the tag
+    pub items: Vec<(Uuid,String)>,
+}
+
+#[derive(Clone, Copy)]
+pub enum EdgeVia {
+    /// Through the side of the room, with the type of the connection
+    Conn(OpAxis,bool,ConnType),
+    /// The warp of the tag
+    Warp(Uuid),
+}
+
+pub struct GraphEdge {
+    pub from: usize,
+    pub to: usize,
+    pub via: EdgeVia,
+}
+
+/// Directed graph of the rooms of maps, from the connections of the rooms and the warps of their tags.
+///
+/// Warps into maps that aren't part of the graph are left out.
+#[derive(Default)]
+pub struct RoomGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+    /// Outgoing edges of every node
+    pub out: Vec<Vec<usize>>,
+    pub map_titles: HashMap<Uuid,String>,
+    pub conn_types: HashMap<Uuid,Vec<ConnTypeDef>>,
+    index: HashMap<Uuid,usize>,
+}
+
+impl RoomGraph {
+    pub fn build<'a>(maps: impl IntoIterator<Item = &'a MapState>) -> Self {
+        let maps = maps.into_iter().collect::<Vec<_>>();
+        let mut graph = Self::default();
+
+        for state in &maps {
+            graph.map_titles.insert(state.uuid, state.title.clone());
+            graph.conn_types.insert(state.uuid, state.conn_types.clone());
+            for room in state.rooms.values() {
+                if room.transient {continue;}
+                graph.index.insert(room.uuid, graph.nodes.len());
+                graph.nodes.push(GraphNode {
+                    map: state.uuid,
+                    room: room.uuid,
+                    coord: room.coord,
+                    title: room.title.clone(),
+                    items: room.tags.iter()
+                        .filter(|(_,t)| !t.item.trim().is_empty() )
+                        .map(|(&id,t)| (id,t.item.trim().to_owned()) )
+                        .collect(),
+                });
+            }
+        }
+        graph.out = vec![vec![]; graph.nodes.len()];
+
+        for state in &maps {
+            let matrix = state.rooms.values()
+                .filter(|r| !r.transient )
+                .map(|r| (r.coord,r) )
+                .collect::<HashMap<_,_>>();
+
+            for room in state.rooms.values() {
+                if room.transient {continue;}
+                let from = graph.index[&room.uuid];
+
+                for axis in [OpAxis::X, OpAxis::Y, OpAxis::Z] {
+                    for dir in [false,true] {
+                        let conn = room.dirconn[axis.axis_idx()][dir as usize];
+                        if !conn_exit(conn) {continue;}
+                        let Some(side) = try_side(room.coord, axis, dir, |c| matrix.get(&c) ).flatten() else {continue};
+                        if side.dirconn[axis.axis_idx()][!dir as usize] == CONN_NONE {continue;}
+                        graph.push_edge(from, graph.index[&side.uuid], EdgeVia::Conn(axis, dir, conn_kind(conn)));
+                    }
+                }
+
+                for (&tag_id,tag) in &room.tags {
+                    let Some(warp) = &tag.warp else {continue};
+                    let Some(&to) = graph.index.get(&warp.dest_room) else {continue};
+                    if graph.nodes[to].map != warp.dest_map {continue;}
+                    graph.push_edge(from, to, EdgeVia::Warp(tag_id));
+                }
+            }
+        }
+
+        graph
+    }
+
+    fn push_edge(&mut self, from: usize, to: usize, via: EdgeVia) {
+        self.out[from].push(self.edges.len());
+        self.edges.push(GraphEdge { from, to, via });
+    }
+
+    pub fn node(&self, room: &Uuid) -> Option<usize> {
+        self.index.get(room).cloned()
+    }
+
+    /// The item required to pass the edge, and whether passing uses it up
+    pub fn lock(&self, edge: usize) -> Option<(&str,bool)> {
+        let edge = &self.edges[edge];
+        let EdgeVia::Conn(_, _, kind) = edge.via else {return None};
+        let t = self.conn_types.get(&self.nodes[edge.from].map)?.get((kind as usize).checked_sub(1)?)?;
+        let requires = t.requires.trim();
+        (!requires.is_empty()).then_some((requires,t.consumes))
+    }
+
+    /// Whether every node is reachable from start
+    pub fn reachable(&self, start: usize) -> Vec<bool> {
+        let mut reached = vec![false; self.nodes.len()];
+        for n in self.bfs_order(start) {
+            reached[n] = true;
+        }
+        reached
+    }
+
+    /// The nodes reachable from start, in the order of a breadth first search
+    pub fn bfs_order(&self, start: usize) -> Vec<usize> {
+        let mut reached = vec![false; self.nodes.len()];
+        let mut order = vec![];
+        let mut queue = VecDeque::from([start]);
+        reached[start] = true;
+
+        while let Some(n) = queue.pop_front() {
+            order.push(n);
+            for &e in &self.out[n] {
+                let to = self.edges[e].to;
+                if !reached[to] {
+                    reached[to] = true;
+                    queue.push_back(to);
+                }
+            }
+        }
+
+        order
+    }
+
+    /// The edges of a route with the fewest steps
+    pub fn shortest_path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        let mut prev_edge = vec![None; self.nodes.len()];
+        let mut reached = vec![false; self.nodes.len()];
+        let mut queue = VecDeque::from([from]);
+        reached[from] = true;
+
+        while let Some(n) = queue.pop_front() {
+            if n == to {break;}
+            for &e in &self.out[n] {
+                let next = self.edges[e].to;
+                if !reached[next] {
+                    reached[next] = true;
+                    prev_edge[next] = Some(e);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        if !reached[to] {return None;}
+
+        let mut path = vec![];
+        let mut n = to;
+        while let Some(e) = prev_edge[n] {
+            path.push(e);
+            n = self.edges[e].from;
+        }
+        path.reverse();
+
+        Some(path)
+    }
+
+    /// Groups of the selected nodes, which are connected with each other in any direction
+    pub fn islands(&self, selected: impl Fn(usize) -> bool) -> Vec<Vec<usize>> {
+        let mut adjacent = vec![vec![]; self.nodes.len()];
+        for edge in &self.edges {
+            adjacent[edge.from].push(edge.to);
+            adjacent[edge.to].push(edge.from);
+        }
+
+        let mut done = vec![false; self.nodes.len()];
+        let mut islands = vec![];
+
+        for start in 0 .. self.nodes.len() {
+            if done[start] || !selected(start) {continue;}
+            done[start] = true;
+
+            let mut island = vec![];
+            let mut queue = VecDeque::from([start]);
+            while let Some(n) = queue.pop_front() {
+                island.push(n);
+                for &next in &adjacent[n] {
+                    if !done[next] && selected(next) {
+                        done[next] = true;
+                        queue.push_back(next);
+                    }
+                }
+            }
+            islands.push(island);
+        }
+
+        islands
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde_json::{Map as JsonMap, Value};
+
+use crate::gui::map::{MapDeserProbe, MapState};
+use crate::util::json_ser_with_ident;
+
+use super::coord_store::CoordStore;
+
+#[derive(clap::Args, Debug)]
+pub struct MergeArgs {
+    /// Common ancestor version (%O of the git merge driver)
+    pub base: PathBuf,
+    /// Our version (%A). The result is written here unless --output is given
+    pub ours: PathBuf,
+    /// Their version (%B)
+    pub theirs: PathBuf,
+    /// Write the result here instead of to ours
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// Also write the conflict report to this file
+    #[arg(long)]
+    pub report: Option<PathBuf>,
+}
+
+/// Header fields which only hold the view state of the editor. On conflict ours is taken without report
+const VIEW_FIELDS: &[&str] = &[
+    "map_zoom", "draw_zoom", "dsel_coord", "ssel_coord", "view_pos", "current_level",
+    "edit_mode", "draw_draw_mode", "draw_sel", "smart_move_size", "smart_awaylock_mode",
+    "ds_replace", "dsel_whole", "dsel_room", "ssel_room", "template_room",
+    "set_dssel_merged", "quick_shift_keep_gap",
+];
+
+/// Room fields which are merged per element, as every element is an own connection
+const ELEMENTWISE_FIELDS: &[&str] = &["dirconn"];
+
+/// Room fields describing the layers of the room image, which are only valid together with it
+const IMAGE_FIELDS: &[&str] = &["resuuid", "layers", "selected_layer"];
+
+pub fn merge_cmd(args: MergeArgs) -> anyhow::Result<()> {
+    let base = read_json(&args.base)?;
+    let ours = read_json(&args.ours)?;
+    let theirs = read_json(&args.theirs)?;
+
+    let (merged,conflicts) = merge_maps(&base, &ours, &theirs)?;
+
+    let state = serde_json::from_value::<MapState>(merged).context("Merged map is invalid")?;
+    let dest = json_ser_with_ident(&state, state.ser_layout.json_ident(state.json_ident))?;
+
+    let output = args.output.as_ref().unwrap_or(&args.ours);
+    std::fs::write(output, dest).context("Writing merged map")?;
+
+    if !conflicts.is_empty() {
+        let report = conflicts.iter()
+            .map(|c| format!("{c}\n") )
+            .collect::<String>();
+        eprint!("{report}");
+        if let Some(path) = &args.report {
+            std::fs::write(path, &report).context("Writing conflict report")?;
+        }
+        anyhow::bail!("{} conflicts, ours was taken for them", conflicts.len());
+    }
+
+    Ok(())
+}
+
+fn read_json(path: &Path) -> anyhow::Result<Value> {
+    let data = std::fs::read(path)
+        .with_context(|| format!("Reading {}", path.to_string_lossy()) )?;
+
+    let header = serde_json::from_slice::<MapDeserProbe>(&data)?;
+    anyhow::ensure!(header.mzd_format == 2, "Unsupported mzd_format {}", header.mzd_format);
+
+    Ok(serde_json::from_slice(&data)?)
+}
+
+/// Three way merge of map files, matching rooms by their UUID.
+///
+/// Returns the merged map and the conflicts. For conflicting fields ours is taken,
+/// rooms claiming the same coord are moved to the nearest free coord.
+pub fn merge_maps(base: &Value, ours: &Value, theirs: &Value) -> anyhow::Result<(Value,Vec<String>)> {
+    let (Some(base),Some(ours),Some(theirs)) = (base.as_object(),ours.as_object(),theirs.as_object()) else {
+        anyhow::bail!("Map file isn't a JSON object");
+    };
+
+    anyhow::ensure!(ours.get("uuid") == theirs.get("uuid"), "Not the same map, UUIDs differ");
+    anyhow::ensure!(ours.get("rooms_size") == theirs.get("rooms_size"), "Room sizes differ, resize the rooms before merging");
+
+    let mut conflicts = vec![];
+    let mut dest = JsonMap::new();
+
+    for key in union_keys(ours, theirs) {
+        let (b,o,t) = (base.get(key),ours.get(key),theirs.get(key));
+        let merged = match key.as_str() {
+            "rooms" => Some(merge_rooms(b, o, t, &mut conflicts)?),
+            "mtime" => newer(o, t),
+            _ => match merge_value(b, o, t) {
+                Ok(v) => v,
+                Err(()) => {
+                    if !VIEW_FIELDS.contains(&key.as_str()) {
+                        conflicts.push(format!("Map field {key} changed on both sides"));
+                    }
+                    o.cloned()
+                },
+            },
+        };
+        if let Some(v) = merged {
+            dest.insert(key.clone(), v);
+        }
+    }
+
+    Ok((Value::Object(dest),conflicts))
+}
+
+fn merge_rooms(base: Option<&Value>, ours: Option<&Value>, theirs: Option<&Value>, conflicts: &mut Vec<String>) -> anyhow::Result<Value> {
+    let empty = JsonMap::new();
+    let base = base.and_then(Value::as_object).unwrap_or(&empty);
+    let ours = ours.and_then(Value::as_object).unwrap_or(&empty);
+    let theirs = theirs.and_then(Value::as_object).unwrap_or(&empty);
+
+    let mut rooms = vec![];
+
+    for uuid in union_keys(ours, theirs) {
+        let (b,o,t) = (base.get(uuid),ours.get(uuid),theirs.get(uuid));
+        let merged = match (o,t) {
+            (Some(o),Some(t)) => merge_room(uuid, b, o, t, conflicts),
+            (Some(v),None) | (None,Some(v)) => {
+                match b {
+                    // deleted on the other side
+                    Some(b) if b == v => continue,
+                    Some(_) => {
+                        conflicts.push(format!("Room {uuid}: deleted on one side, changed on the other, kept the changed room"));
+                        v.clone()
+                    },
+                    // added
+                    None => v.clone(),
+                }
+            },
+            (None,None) => continue,
+        };
+        rooms.push((uuid.clone(),merged));
+    }
+
+    resolve_coord_collisions(&mut rooms, ours, conflicts)?;
+
+    rooms.sort_by(|a,b| a.0.cmp(&b.0) );
+
+    Ok(Value::Object(rooms.into_iter().collect()))
+}
+
+fn merge_room(uuid: &str, base: Option<&Value>, ours: &Value, theirs: &Value, conflicts: &mut Vec<String>) -> Value {
+    let (Some(o),Some(t)) = (ours.as_object(),theirs.as_object()) else {
+        return ours.clone();
+    };
+    let b = base.and_then(Value::as_object);
+
+    let mut dest = JsonMap::new();
+
+    let resuuid = (b.and_then(|b| b.get("resuuid") ),o.get("resuuid"),t.get("resuuid"));
+    let image_conflict = merge_value(resuuid.0, resuuid.1, resuuid.2).is_err();
+    if image_conflict {
+        conflicts.push(format!(
+            "Room {uuid}: image changed on both sides, kept ours with its layers, theirs is resource {}",
+            resuuid.2.and_then(Value::as_str).unwrap_or_default()
+        ));
+    }
+    // only theirs changed the image, so on conflict the layers are taken from theirs
+    let image_theirs = !image_conflict && resuuid.1 == resuuid.0 && resuuid.2 != resuuid.0;
+
+    for key in union_keys(o, t) {
+        let (bv,ov,tv) = (b.and_then(|b| b.get(key) ),o.get(key),t.get(key));
+        let merged = match key.as_str() {
+            "tags" => merge_tags(uuid, bv, ov, tv, conflicts),
+            "mtime" => newer(ov, tv),
+            key if IMAGE_FIELDS.contains(&key) && image_conflict => ov.cloned(),
+            key if IMAGE_FIELDS.contains(&key) => merge_value(bv, ov, tv).unwrap_or_else(|()| {
+                // the selected layer is view state, not worth a conflict
+                if key == "layers" {
+                    conflicts.push(format!(
+                        "Room {uuid}: layers changed on both sides, kept {} with the image",
+                        if image_theirs {"theirs"} else {"ours"}
+                    ));
+                }
+                if image_theirs {tv.cloned()} else {ov.cloned()}
+            }),
+            key if ELEMENTWISE_FIELDS.contains(&key) => merge_elementwise(bv, ov, tv).unwrap_or_else(|()| {
+                conflicts.push(format!("Room {uuid}: {key} changed on both sides"));
+                ov.cloned()
+            }),
+            key => merge_value(bv, ov, tv).unwrap_or_else(|()| {
+                match key {
+                    // the view state of the room is not worth a conflict
+                    "editor_hide_layers_above" => {},
+                    _ => conflicts.push(format!("Room {uuid}: {key} changed on both sides")),
+                }
+                ov.cloned()
+            }),
+        };
+        if let Some(v) = merged {
+            dest.insert(key.clone(), v);
+        }
+    }
+
+    Value::Object(dest)
+}
+
+/// Tags are a list of [uuid, tag] pairs, merged by tag UUID
+fn merge_tags(room: &str, base: Option<&Value>, ours: Option<&Value>, theirs: Option<&Value>, conflicts: &mut Vec<String>) -> Option<Value> {
+    fn tag_list(v: Option<&Value>) -> Vec<(String,Value)> {
+        v.and_then(Value::as_array).into_iter().flatten()
+            .filter_map(|pair| {
+                let [uuid,tag] = pair.as_array()?.as_slice() else {return None};
+                Some((uuid.as_str()?.to_owned(),tag.clone()))
+            })
+            .collect()
+    }
+
+    let base = tag_list(base);
+    let ours = tag_list(ours);
+    let theirs = tag_list(theirs);
+    let find = |list: &[(String,Value)], uuid: &str| list.iter().find(|(u,_)| u == uuid ).map(|(_,v)| v.clone() );
+
+    let mut order = ours.iter().map(|(u,_)| u.clone() ).collect::<Vec<_>>();
+    for (u,_) in &theirs {
+        if !order.contains(u) {
+            order.push(u.clone());
+        }
+    }
+
+    let mut dest = vec![];
+    for uuid in order {
+        let (b,o,t) = (find(&base, &uuid),find(&ours, &uuid),find(&theirs, &uuid));
+        let merged = merge_value(b.as_ref(), o.as_ref(), t.as_ref()).unwrap_or_else(|()| {
+            conflicts.push(format!("Room {room}: tag {uuid} changed on both sides"));
+            o.clone().or(t.clone())
+        });
+        if let Some(tag) = merged {
+            dest.push(Value::Array(vec![Value::String(uuid),tag]));
+        }
+    }
+
+    Some(Value::Array(dest))
+}
+
+/// Put the rooms which kept the coord they have in ours first, and move the others on collision
+fn resolve_coord_collisions(rooms: &mut [(String,Value)], ours: &JsonMap<String,Value>, conflicts: &mut Vec<String>) -> anyhow::Result<()> {
+    let coord_of = |v: &Value| serde_json::from_value::<[u8;3]>(v.get("coord").cloned().unwrap_or_default()).ok();
+
+    let mut order = (0 .. rooms.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| {
+        let (uuid,room) = &rooms[i];
+        let keeps_ours = ours.get(uuid).and_then(coord_of).is_some_and(|c| Some(c) == coord_of(room) );
+        !keeps_ours
+    });
+
+    let mut matrix = CoordStore::<()>::new();
+    let mut moved = HashMap::new();
+
+    for i in order {
+        let (uuid,room) = &rooms[i];
+        let coord = coord_of(room).with_context(|| format!("Room {uuid} has no valid coord") )?;
+        if matrix.get(coord).is_none() {
+            matrix.insert(coord, ());
+            continue;
+        }
+        let free = matrix.find_free_near(coord).context("Map is full")?;
+        matrix.insert(free, ());
+        conflicts.push(format!(
+            "Room {uuid}: X{}Y{}Z{} is taken by another room, moved to X{}Y{}Z{}",
+            coord[0], coord[1], coord[2], free[0], free[1], free[2],
+        ));
+        moved.insert(i, free);
+    }
+
+    for (i,coord) in moved {
+        if let Some(room) = rooms[i].1.as_object_mut() {
+            room.insert("coord".into(), serde_json::to_value(coord)?);
+        }
+    }
+
+    Ok(())
+}
+
+/// Err if changed differently on both sides. None if deleted
+fn merge_value(base: Option<&Value>, ours: Option<&Value>, theirs: Option<&Value>) -> Result<Option<Value>,()> {
+    if ours == theirs || theirs == base {
+        Ok(ours.cloned())
+    } else if ours == base {
+        Ok(theirs.cloned())
+    } else {
+        Err(())
+    }
+}
+
+fn merge_elementwise(base: Option<&Value>, ours: Option<&Value>, theirs: Option<&Value>) -> Result<Option<Value>,()> {
+    if let Ok(v) = merge_value(base, ours, theirs) {
+        return Ok(v);
+    }
+    let (Some(Value::Array(b)),Some(Value::Array(o)),Some(Value::Array(t))) = (base,ours,theirs) else {return Err(())};
+    if b.len() != o.len() || b.len() != t.len() {return Err(());}
+
+    let mut dest = Vec::with_capacity(o.len());
+    for ((b,o),t) in b.iter().zip(o).zip(t) {
+        match merge_elementwise(Some(b), Some(o), Some(t))? {
+            Some(v) => dest.push(v),
+            None => return Err(()),
+        }
+    }
+    Ok(Some(Value::Array(dest)))
+}
+
+fn newer(ours: Option<&Value>, theirs: Option<&Value>) -> Option<Value> {
+    let time = |v: Option<&Value>| v.and_then(|v| serde_json::from_value::<chrono::DateTime<chrono::Utc>>(v.clone()).ok() );
+    if time(theirs) > time(ours) {
+        theirs.cloned()
+    } else {
+        ours.or(theirs).cloned()
+    }
+}
+
+/// Keys of ours in order, then the keys only in theirs
+fn union_keys<'a>(ours: &'a JsonMap<String,Value>, theirs: &'a JsonMap<String,Value>) -> Vec<&'a String> {
+    let mut keys = ours.keys().collect::<Vec<_>>();
+    keys.extend(theirs.keys().filter(|k| !ours.contains_key(*k) ));
+    keys
+}
@@ -1 +1,9 @@
 pub mod coord_store;
+pub mod diff;
+pub mod fsck;
+pub mod gc;
+pub mod graph;
+pub mod merge;
+pub mod resize;
+pub mod solver;
+pub mod storage;
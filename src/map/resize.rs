@@ -0,0 +1,244 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context};
+use image::{GenericImageView, RgbaImage};
+use slotmap::Key;
+
+use crate::gui::map::{MapState, RoomId};
+use crate::gui::room::Room;
+use crate::gui::sel_matrix::{sel_entry_dims, SelEntry, SelEntryRead, SelEntryWrite, SelMatrix, SelMatrixLayered};
+use crate::util::uuid::UUIDMap;
+use crate::util::{attached_to_path, seltrix_resource_path, tex_resource_path, MapId};
+
+use super::gc::referenced_resources;
+
+/// Where the old room content stays when padding or cropping
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug, clap::ValueEnum)]
+pub enum ResizeAnchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    #[default]
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl ResizeAnchor {
+    pub const ALL: [Self;9] = [
+        Self::TopLeft, Self::Top, Self::TopRight,
+        Self::Left, Self::Center, Self::Right,
+        Self::BottomLeft, Self::Bottom, Self::BottomRight,
+    ];
+
+    /// Position of the old room content in the resized room, in multiples of 8 so that the selection cells stay aligned
+    pub fn offset(self, old_size: [u32;2], new_size: [u32;2]) -> [i64;2] {
+        let i = Self::ALL.iter().position(|&v| v == self ).unwrap();
+        let align = [i % 3, i / 3];
+        [0,1].map(|a| {
+            let d = new_size[a] as i64 - old_size[a] as i64;
+            match align[a] {
+                0 => 0,
+                1 => d / 2 / 8 * 8,
+                _ => d,
+            }
+        })
+    }
+}
+
+/// Pad or crop the image, selection matrix and tag positions of the loaded room
+pub fn resize_room(room: &mut Room, old_size: [u32;2], new_size: [u32;2], anchor: ResizeAnchor) {
+    let off = anchor.offset(old_size, new_size);
+    let loaded = room.loaded.as_mut().unwrap();
+    let layers = loaded.image.layers;
+
+    let mut img = RgbaImage::new(new_size[0], new_size[1] * layers as u32);
+    for l in 0 .. layers as u32 {
+        let mut layer = RgbaImage::new(new_size[0], new_size[1]);
+        image::imageops::replace(&mut layer, &*loaded.image.img.view(0, l * old_size[1], old_size[0], old_size[1]), off[0], off[1]);
+        image::imageops::replace(&mut img, &layer, 0, (l * new_size[1]) as i64);
+    }
+    loaded.image.img = img;
+    loaded.image.tex = None;
+
+    let dims = sel_entry_dims(new_size);
+    let cell_off = off.map(|v| v / 8 );
+    let layers = loaded.sel_matrix.layers.iter()
+        .map(|old| {
+            let mut new = SelMatrix::new_empty(dims);
+            for y in 0 .. old.dims[1] {
+                for x in 0 .. old.dims[0] {
+                    let pos = [x as i64 + cell_off[0], y as i64 + cell_off[1]];
+                    if pos[0] < 0 || pos[1] < 0 || pos[0] >= dims[0] as i64 || pos[1] >= dims[1] as i64 {continue;}
+                    let mut entry = old.get([x,y]).unwrap().clone();
+                    // the cropped off part of a selection group
+                    let origin = [0,1].map(|a| pos[a] - entry.start[a] as i64 );
+                    let cut = (0..2).any(|a| origin[a] < 0 || origin[a] + entry.size[a] as i64 > dims[a] as i64 );
+                    if entry.size != [0,0] && cut {
+                        entry = SelEntry { start: [0,0], size: [1,1] };
+                    }
+                    *new.get_mut([pos[0] as u32, pos[1] as u32]).unwrap() = entry;
+                }
+            }
+            new
+        })
+        .collect();
+    loaded.sel_matrix = SelMatrixLayered { dims, layers };
+
+    loaded.dirty_file = true;
+    loaded.ur_snapshot_required = true;
+    loaded.undo_buf.clear();
+    loaded.redo_buf.clear();
+
+    // tags cropped off are moved to the border
+    for tag in room.tags.values_mut() {
+        tag.pos = shift_pos(tag.pos, off, new_size);
+    }
+}
+
+/// Move the destinations of the warps into the map along with the room content. Warps from other maps can't be reached here.
+///
+/// Returns the number of moved warps
+pub fn resize_warp_dests(state: &mut MapState, old_size: [u32;2], new_size: [u32;2], anchor: ResizeAnchor) -> usize {
+    let off = anchor.offset(old_size, new_size);
+    let map_uuid = state.uuid;
+    let mut moved = 0;
+
+    let rooms = state.rooms.values_mut()
+        .chain(state.quickroom_template.iter_mut().filter_map(Option::as_mut));
+
+    for tag in rooms.flat_map(|r| r.tags.values_mut() ) {
+        if let Some(warp) = &mut tag.warp && warp.dest_map == map_uuid {
+            warp.dest_pos = shift_pos(warp.dest_pos, off, new_size);
+            moved += 1;
+        }
+    }
+
+    moved
+}
+
+fn shift_pos(pos: [u32;2], off: [i64;2], new_size: [u32;2]) -> [u32;2] {
+    [0,1].map(|a| (pos[a] as i64 + off[a]).clamp(0, new_size[a] as i64 - 1) as u32 )
+}
+
+pub fn check_rooms_size(size: [u32;2]) -> anyhow::Result<()> {
+    ensure!(
+        size.iter().all(|&v| v != 0 && v % 8 == 0 && v <= 4096),
+        "The room size must be a multiple of 8, up to 4096",
+    );
+    Ok(())
+}
+
+/// Copy the map file to <map>.bak and its room files to <map>.bak_data, so the backup can be opened as map
+pub fn backup_map(path: &Path) -> anyhow::Result<PathBuf> {
+    let backup = attached_to_path(path, ".bak");
+    let data = attached_to_path(path, "_data");
+    let backup_data = attached_to_path(&backup, "_data");
+
+    if backup_data.exists() {
+        std::fs::remove_dir_all(&backup_data).context("Removing previous backup")?;
+    }
+    if data.is_dir() {
+        copy_dir(&data, &backup_data).context("Backing up room files")?;
+    }
+    std::fs::copy(path, &backup).context("Backing up map")?;
+
+    Ok(backup)
+}
+
+fn copy_dir(src: &Path, dest: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dest)?;
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let dest = dest.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &dest)?;
+        } else {
+            std::fs::copy(entry.path(), dest)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ResizeArgs {
+    /// Map to resize
+    pub map: PathBuf,
+    /// New room width, a multiple of 8
+    pub width: u32,
+    /// New room height, a multiple of 8
+    pub height: u32,
+    /// Where the old room content stays when padding or cropping
+    #[arg(short, long, default_value = "center")]
+    pub anchor: ResizeAnchor,
+}
+
+/// Pad or crop every room of a map to the new size. The map is backed up with .bak attached before, its room files into .bak_data
+pub fn resize_cmd(args: ResizeArgs) -> anyhow::Result<()> {
+    let path = args.map;
+    let new_size = [args.width, args.height];
+    check_rooms_size(new_size)?;
+
+    let mut state = MapState::read_file(&path)
+        .with_context(|| format!("Reading {}", path.to_string_lossy()) )?;
+    let old_size = state.rooms_size;
+    ensure!(old_size != new_size, "The rooms are already {}x{}", new_size[0], new_size[1]);
+
+    let rooms = state.rooms.values_mut()
+        .chain(state.quickroom_template.iter_mut().filter_map(Option::as_mut));
+
+    // load everything first, a partially resized map is worse than none
+    for room in rooms {
+        if room.transient {continue;}
+        let loaded = room.load_room_res(&path, old_size)
+            .with_context(|| format!("Loading room @ X{}Y{}Z{}", room.coord[0], room.coord[1], room.coord[2]) )?;
+        room.loaded = Some(loaded);
+    }
+
+    let backup = backup_map(&path)?;
+
+    let map_id = MapId::new();
+    let mut uuidmap = UUIDMap::default();
+    let mut cleanup_res = vec![];
+    let mut resized = 0;
+
+    let map_uuid = state.uuid;
+    let rooms = state.rooms.values_mut()
+        .chain(state.quickroom_template.iter_mut().filter_map(Option::as_mut));
+
+    for room in rooms {
+        if room.loaded.is_none() {continue;}
+        resize_room(room, old_size, new_size, args.anchor);
+        let old = room.save_room_res(&path, state.res_storage, &map_uuid, &mut uuidmap, map_id, RoomId::null())
+            .with_context(|| format!("Writing room @ X{}Y{}Z{}", room.coord[0], room.coord[1], room.coord[2]) )?;
+        cleanup_res.extend(old);
+        room.loaded = None;
+        resized += 1;
+    }
+
+    let warps = resize_warp_dests(&mut state, old_size, new_size, args.anchor);
+
+    state.view_pos = [0,1].map(|a| state.view_pos[a] * new_size[a] as f32 / old_size[a] as f32 );
+    state.rooms_size = new_size;
+    state.write_file(&path).context("Writing map")?;
+
+    let in_use = referenced_resources(&state);
+    cleanup_res.retain(|v| !in_use.contains(v) );
+    cleanup_res.sort();
+    cleanup_res.dedup();
+    for resuuid in &cleanup_res {
+        let _ = std::fs::remove_file(tex_resource_path(&path, resuuid));
+        let _ = std::fs::remove_file(seltrix_resource_path(&path, resuuid));
+    }
+
+    eprintln!(
+        "Resized {resized} rooms from {}x{} to {}x{} and moved {warps} warps into them, backup at {}",
+        old_size[0], old_size[1], new_size[0], new_size[1], backup.to_string_lossy(),
+    );
+    eprintln!("Warps from other maps into this map keep their old destination positions");
+
+    Ok(())
+}
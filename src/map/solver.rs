@@ -0,0 +1,273 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use uuid::Uuid;
+
+use super::graph::RoomGraph;
+
+/// Explored sets of opened doors, before giving up
+const MAX_STATES: usize = 10000;
+
+#[derive(Clone)]
+pub enum SolveStep {
+    /// The item of the tag in the room
+    Collect(usize,Uuid,String),
+    /// Use up the item to open the door
+    Open(usize),
+}
+
+/// A way to spend the consumed items, after which less can be reached than otherwise
+pub struct Softlock {
+    /// The doors in the order they were opened
+    pub opened: Vec<usize>,
+    /// Unopened doors which can be passed from the reached rooms
+    pub stuck: Vec<usize>,
+    pub items: usize,
+    pub rooms: usize,
+}
+
+/// Result of simulating the progression from a start room
+pub struct Solution {
+    /// Connections with an item used up at the first pass, the two rooms of the connection
+    pub doors: Vec<[usize;2]>,
+    /// The playthrough, reaching the most
+    pub steps: Vec<SolveStep>,
+    /// The rooms reached by the playthrough
+    pub reached: Vec<bool>,
+    pub softlocks: Vec<Softlock>,
+    /// Items not collected by the playthrough, with the room and tag
+    pub unreachable_items: Vec<(usize,Uuid,String)>,
+    /// Edges out of the reached rooms, still locked after the playthrough, with the missing item
+    pub locked: Vec<(usize,String)>,
+    /// False if there were too many ways to open the doors to try them all
+    pub complete: bool,
+}
+
+struct Closure {
+    reached: Vec<bool>,
+    /// In the order of collection
+    collected: Vec<(usize,Uuid,String)>,
+}
+
+struct Solver<'a> {
+    graph: &'a RoomGraph,
+    doors: Vec<[usize;2]>,
+    door_items: Vec<&'a str>,
+    /// The door of every edge
+    edge_door: Vec<Option<usize>>,
+}
+
+impl<'a> Solver<'a> {
+    fn new(graph: &'a RoomGraph) -> Self {
+        let mut doors = vec![];
+        let mut door_items = vec![];
+        let mut door_index = HashMap::new();
+        let mut edge_door = vec![None; graph.edges.len()];
+
+        for (e,edge) in graph.edges.iter().enumerate() {
+            let Some((item,true)) = graph.lock(e) else {continue};
+            let pair = [edge.from.min(edge.to),edge.from.max(edge.to)];
+            let door = *door_index.entry(pair).or_insert_with(|| {
+                doors.push(pair);
+                door_items.push(item);
+                doors.len() - 1
+            });
+            edge_door[e] = Some(door);
+        }
+
+        Self { graph, doors, door_items, edge_door }
+    }
+
+    /// Everything reachable with the doors opened
+    fn closure(&self, start: usize, opened: &[bool]) -> Closure {
+        let graph = self.graph;
+        let mut reached = vec![false; graph.nodes.len()];
+        let mut collected = vec![];
+        let mut have: HashMap<&str,usize> = HashMap::new();
+        reached[start] = true;
+
+        // items found can open further locks, so repeat until nothing changes
+        let mut queue = VecDeque::from([start]);
+        let mut waiting = vec![];
+        loop {
+            while let Some(n) = queue.pop_front() {
+                for (tag,item) in &graph.nodes[n].items {
+                    collected.push((n,*tag,item.clone()));
+                    *have.entry(item).or_default() += 1;
+                }
+                for &e in &graph.out[n] {
+                    let to = graph.edges[e].to;
+                    if reached[to] {continue;}
+                    let passable = match (graph.lock(e),self.edge_door[e]) {
+                        (_,Some(door)) => opened[door],
+                        (Some((item,_)),None) => have.contains_key(item),
+                        (None,None) => true,
+                    };
+                    if passable {
+                        reached[to] = true;
+                        queue.push_back(to);
+                    } else {
+                        waiting.push(e);
+                    }
+                }
+            }
+
+            let before = waiting.len();
+            waiting.retain(|&e| {
+                let to = graph.edges[e].to;
+                if reached[to] {return false;}
+                match (graph.lock(e),self.edge_door[e]) {
+                    (Some((item,_)),None) if have.contains_key(item) => {
+                        reached[to] = true;
+                        queue.push_back(to);
+                        false
+                    },
+                    _ => true,
+                }
+            });
+            if queue.is_empty() && waiting.len() == before {break;}
+        }
+
+        Closure { reached, collected }
+    }
+
+    /// Items left to open doors with
+    fn keys<'c>(&self, closure: &'c Closure, opened: &[bool]) -> HashMap<&'c str,isize> {
+        let mut keys = HashMap::new();
+        for (_,_,item) in &closure.collected {
+            *keys.entry(item.as_str()).or_default() += 1;
+        }
+        for (door,_) in opened.iter().enumerate().filter(|&(_,&o)| o ) {
+            if let Some(k) = keys.get_mut(self.door_items[door]) {
+                *k -= 1;
+            }
+        }
+        keys
+    }
+
+    /// Unopened doors which can be passed from the reached rooms, one-way doors only in their direction
+    fn frontier(&self, closure: &Closure, opened: &[bool]) -> Vec<usize> {
+        let mut frontier = vec![false; self.doors.len()];
+        for (e,&door) in self.edge_door.iter().enumerate() {
+            if let Some(d) = door && !opened[d] && closure.reached[self.graph.edges[e].from] {
+                frontier[d] = true;
+            }
+        }
+        (0 .. self.doors.len())
+            .filter(|&d| frontier[d] )
+            .collect()
+    }
+}
+
+/// Opened doors, with the state and door it was opened from
+type OpenState = (Vec<bool>,Option<(usize,usize)>);
+
+fn score(closure: &Closure) -> (usize,usize) {
+    (closure.collected.len(), closure.reached.iter().filter(|&&r| r ).count())
+}
+
+/// Simulate the progression from the start room, trying every order of opening the doors with used up items
+pub fn solve(graph: &RoomGraph, start: usize) -> Solution {
+    let solver = Solver::new(graph);
+    let n_doors = solver.doors.len();
+
+    // the explored sets of opened doors
+    let mut states: Vec<OpenState> = vec![(vec![false; n_doors],None)];
+    let mut known = HashSet::from([vec![false; n_doors]]);
+    let mut terminals = vec![];
+    let mut complete = true;
+
+    let mut i = 0;
+    while i < states.len() {
+        let opened = states[i].0.clone();
+        let closure = solver.closure(start, &opened);
+        let keys = solver.keys(&closure, &opened);
+
+        let mut openable = false;
+        for door in solver.frontier(&closure, &opened) {
+            if keys.get(solver.door_items[door]).is_none_or(|&k| k <= 0) {continue;}
+            openable = true;
+
+            let mut next = opened.clone();
+            next[door] = true;
+            if known.contains(&next) {continue;}
+            if states.len() >= MAX_STATES {
+                complete = false;
+                continue;
+            }
+            known.insert(next.clone());
+            states.push((next,Some((i,door))));
+        }
+
+        if !openable {
+            terminals.push((i,score(&closure)));
+        }
+        i += 1;
+    }
+
+    let path = |mut state: usize| {
+        let mut doors = vec![];
+        while let Some((prev,door)) = states[state].1 {
+            doors.push(door);
+            state = prev;
+        }
+        doors.reverse();
+        doors
+    };
+
+    // without terminals if the cap was hit, then the start is the best known
+    let &(best_state,best_score) = terminals.iter()
+        .max_by_key(|&&(s,score)| (score,std::cmp::Reverse(s)) )
+        .unwrap_or(&(0,(0,0)));
+    let best_path = path(best_state);
+
+    let mut steps = vec![];
+    let mut opened = vec![false; n_doors];
+    let mut closure = solver.closure(start, &opened);
+    for i in 0 ..= best_path.len() {
+        // collection order differs between closures, so only the new items
+        for c in &closure.collected {
+            if !steps.iter().any(|s| matches!(s, SolveStep::Collect(_,tag,_) if *tag == c.1) ) {
+                steps.push(SolveStep::Collect(c.0, c.1, c.2.clone()));
+            }
+        }
+        let Some(&door) = best_path.get(i) else {break};
+        steps.push(SolveStep::Open(door));
+        opened[door] = true;
+        closure = solver.closure(start, &opened);
+    }
+
+    let unreachable_items = graph.nodes.iter().enumerate()
+        .filter(|&(n,_)| !closure.reached[n] )
+        .flat_map(|(n,node)| node.items.iter().map(move |(tag,item)| (n,*tag,item.clone())) )
+        .collect();
+
+    let locked = graph.edges.iter().enumerate()
+        .filter(|(_,edge)| closure.reached[edge.from] && !closure.reached[edge.to] )
+        .filter_map(|(e,_)| graph.lock(e).map(|(item,_)| (e,item.to_owned())) )
+        .collect();
+
+    let mut softlocks = terminals.iter()
+        .filter(|&&(_,score)| score < best_score )
+        .map(|&(s,(items,rooms))| {
+            let opened = &states[s].0;
+            let closure = solver.closure(start, opened);
+            Softlock {
+                opened: path(s),
+                stuck: solver.frontier(&closure, opened),
+                items,
+                rooms,
+            }
+        })
+        .collect::<Vec<_>>();
+    softlocks.sort_by_key(|s| s.opened.len() );
+
+    Solution {
+        doors: solver.doors,
+        steps,
+        reached: closure.reached,
+        softlocks,
+        unreachable_items,
+        locked,
+        complete,
+    }
+}
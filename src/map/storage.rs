@@ -0,0 +1,79 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use slotmap::Key;
+
+use crate::gui::map::{MapState, ResStorage, RoomId};
+use crate::gui::room::Room;
+use crate::util::uuid::UUIDMap;
+use crate::util::{attached_to_path, seltrix_resource_path, tex_resource_path, MapId};
+
+use super::gc::referenced_resources;
+
+#[derive(clap::Args, Debug)]
+pub struct StorageArgs {
+    /// Map to convert
+    pub map: PathBuf,
+    /// How room files should be named. With content, identical rooms share their files
+    #[arg(value_enum)]
+    pub mode: ResStorage,
+}
+
+/// Rename the room files of a map to the given storage mode. The map file is backed up with .bak attached before
+pub fn storage_cmd(args: StorageArgs) -> anyhow::Result<()> {
+    let path = args.map;
+
+    let mut state = MapState::read_file(&path)
+        .with_context(|| format!("Reading {}", path.to_string_lossy()) )?;
+
+    let map_id = MapId::new();
+    let mut uuidmap = UUIDMap::default();
+    let mut cleanup_res = vec![];
+    let mut converted = 0;
+    let mut errors = 0;
+
+    let rooms_size = state.rooms_size;
+    let map_uuid = state.uuid;
+    let rooms = state.rooms.values_mut()
+        .chain(state.quickroom_template.iter_mut().filter_map(Option::as_mut));
+
+    for room in rooms {
+        match convert_room(room, &path, rooms_size, args.mode, &map_uuid, &mut uuidmap, map_id) {
+            Ok(old) => {
+                cleanup_res.extend(old);
+                converted += 1;
+            },
+            Err(e) => {
+                eprintln!("Failed to convert room @ X{}Y{}Z{}: {e:#}", room.coord[0], room.coord[1], room.coord[2]);
+                errors += 1;
+            },
+        }
+    }
+
+    state.res_storage = args.mode;
+
+    std::fs::copy(&path, attached_to_path(&path, ".bak")).context("Backing up map")?;
+    state.write_file(&path).context("Writing map")?;
+
+    let in_use = referenced_resources(&state);
+    cleanup_res.retain(|v| !in_use.contains(v) );
+    cleanup_res.sort();
+    cleanup_res.dedup();
+    for resuuid in &cleanup_res {
+        let _ = std::fs::remove_file(tex_resource_path(&path, resuuid));
+        let _ = std::fs::remove_file(seltrix_resource_path(&path, resuuid));
+    }
+
+    eprintln!("Converted {converted} rooms, removed {} unused resources", cleanup_res.len());
+
+    anyhow::ensure!(errors == 0, "{errors} rooms failed to convert");
+
+    Ok(())
+}
+
+fn convert_room(room: &mut Room, map_path: &Path, rooms_size: [u32;2], mode: ResStorage, map_uuid: &uuid::Uuid, uuidmap: &mut UUIDMap, map_id: MapId) -> anyhow::Result<Option<uuid::Uuid>> {
+    room.loaded = Some(room.load_room_res(map_path, rooms_size)?);
+    let result = room.save_room_res(map_path, mode, map_uuid, uuidmap, map_id, RoomId::null());
+    room.loaded = None;
+    result
+}
@@ -93,3 +93,29 @@ pub fn load_image_off_thread(path: impl AsRef<Path>) -> anyhow::Result<DynamicIm
         }
     }
 }
+
+/// Fill the rect, clipped to the image
+pub fn fill_rect(image: &mut RgbaImage, off: [i64;2], size: [u32;2], color: image::Rgba<u8>) {
+    let x0 = off[0].clamp(0, image.width() as i64) as u32;
+    let y0 = off[1].clamp(0, image.height() as i64) as u32;
+    let x1 = (off[0] + size[0] as i64).clamp(0, image.width() as i64) as u32;
+    let y1 = (off[1] + size[1] as i64).clamp(0, image.height() as i64) as u32;
+    for y in y0 .. y1 {
+        for x in x0 .. x1 {
+            image.put_pixel(x, y, color);
+        }
+    }
+}
+
+/// Fill the circle, clipped to the image
+pub fn fill_circle(image: &mut RgbaImage, center: [i64;2], radius: u32, color: image::Rgba<u8>) {
+    let r = radius as i64;
+    for y in center[1] - r .. center[1] + r {
+        for x in center[0] - r .. center[0] + r {
+            let (dx,dy) = (x - center[0], y - center[1]);
+            if dx*dx + dy*dy <= r*r && x >= 0 && y >= 0 && x < image.width() as i64 && y < image.height() as i64 {
+                image.put_pixel(x as u32, y as u32, color);
+            }
+        }
+    }
+}
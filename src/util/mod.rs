@@ -191,6 +191,25 @@ pub fn seltrix_resource_path(map_path: impl Into<PathBuf>, resource_uuid: &Uuid)
     dir
 }
 
+pub fn room_resource_dir(map_path: impl Into<PathBuf>) -> PathBuf {
+    let mut dir = attached_to_path(map_path, "_data");
+    dir.push("rooms");
+    dir
+}
+
+pub fn room_resource_path(map_path: impl Into<PathBuf>, room_uuid: &Uuid) -> PathBuf {
+    let mut dir = room_resource_dir(map_path);
+    dir.push(format!("{room_uuid}.json"));
+    dir
+}
+
+/// Unreferenced resources moved away by the GC
+pub fn quarantine_resource_dir(map_path: impl Into<PathBuf>) -> PathBuf {
+    let mut dir = attached_to_path(map_path, "_data");
+    dir.push("quarantine");
+    dir
+}
+
 const SER_IDENT: &[u8;256] = &[b' ';256];
 
 pub fn json_ser_with_ident<T>(v: &T, ident: Option<u8>) -> anyhow::Result<Vec<u8>> where T: serde::Serialize {
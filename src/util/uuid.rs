@@ -12,6 +12,8 @@ pub enum UUIDTarget {
     Map(MapId),
     Room(MapId,RoomId),
     Resource(MapId,RoomId),
+    /// Resource of content storage used by several rooms of the map, with the number of rooms
+    SharedResource(MapId,usize),
     Tag(MapId,RoomId,Uuid),
 }
 
@@ -27,6 +29,39 @@ pub fn generate_uuid(check: &UUIDMap) -> Uuid {
     }
 }
 
+/// Register a room using the resource, counting the rooms of the map sharing it. False if the UUID is taken by something else
+pub fn retain_res(uuidmap: &mut UUIDMap, resuuid: Uuid, map_id: MapId, room_id: RoomId) -> bool {
+    match uuidmap.get_mut(&resuuid) {
+        None => {
+            uuidmap.insert(resuuid, UUIDTarget::Resource(map_id, room_id));
+        },
+        Some(UUIDTarget::Resource(map,_)) if *map == map_id => {
+            uuidmap.insert(resuuid, UUIDTarget::SharedResource(map_id, 2));
+        },
+        Some(UUIDTarget::SharedResource(map,n)) if *map == map_id => *n += 1,
+        Some(_) => return false,
+    }
+    true
+}
+
+/// Unregister a room using the resource, removing it when no room of the map uses it anymore
+pub fn release_res(uuidmap: &mut UUIDMap, resuuid: Uuid) {
+    match uuidmap.get_mut(&resuuid) {
+        Some(UUIDTarget::SharedResource(_,n)) if *n > 1 => *n -= 1,
+        Some(UUIDTarget::Resource(..) | UUIDTarget::SharedResource(..)) => {
+            uuidmap.remove(&resuuid);
+        },
+        _ => {},
+    }
+}
+
+/// Update the room using the resource, e.g. after it was reinserted
+pub fn update_res(uuidmap: &mut UUIDMap, resuuid: Uuid, map_id: MapId, room_id: RoomId) {
+    if !matches!(uuidmap.get(&resuuid), Some(UUIDTarget::SharedResource(..))) {
+        uuidmap.insert(resuuid, UUIDTarget::Resource(map_id, room_id));
+    }
+}
+
 pub fn generate_res_uuid(check: &UUIDMap, map_path: impl Into<PathBuf>) -> Uuid {
     let map_path = map_path.into();
     loop {
@@ -42,3 +77,52 @@ pub fn generate_res_uuid(check: &UUIDMap, map_path: impl Into<PathBuf>) -> Uuid
         return uuid;
     }
 }
+
+/// Resource UUID derived from the content, salted with the map UUID so that identical rooms of different maps don't share it.
+///
+/// The hash isn't cryptographic, so before sharing a file with the same name the content has to be compared.
+pub fn content_res_uuid(map_uuid: &Uuid, parts: &[&[u8]]) -> Uuid {
+    const M0: u64 = 0x9e3779b97f4a7c15;
+    const M1: u64 = 0xc2b2ae3d27d4eb4f;
+
+    fn mix(h: &mut [u64;2], w: u64) {
+        h[0] = (h[0] ^ w).wrapping_mul(M0).rotate_left(31);
+        h[1] = (h[1] ^ w.rotate_left(23)).wrapping_mul(M1).rotate_left(29);
+        h[0] = h[0].wrapping_add(h[1]);
+    }
+
+    fn fmix(mut v: u64) -> u64 {
+        v ^= v >> 33;
+        v = v.wrapping_mul(0xff51afd7ed558ccd);
+        v ^= v >> 33;
+        v = v.wrapping_mul(0xc4ceb9fe1a85ec53);
+        v ^= v >> 33;
+        v
+    }
+
+    let mut h = [0x243f6a8885a308d3, 0x13198a2e03707344];
+
+    for part in std::iter::once(&&map_uuid.as_bytes()[..]).chain(parts) {
+        let mut chunks = part.chunks_exact(8);
+        for chunk in &mut chunks {
+            mix(&mut h, u64::from_le_bytes(chunk.try_into().unwrap()));
+        }
+        let rem = chunks.remainder();
+        if !rem.is_empty() {
+            let mut last = [0u8;8];
+            last[..rem.len()].copy_from_slice(rem);
+            mix(&mut h, u64::from_le_bytes(last));
+        }
+        // so that moving bytes between parts changes the hash
+        mix(&mut h, part.len() as u64);
+    }
+
+    let lo = fmix(h[0]);
+    let hi = fmix(h[1] ^ lo);
+
+    let mut bytes = [0u8;16];
+    bytes[..8].copy_from_slice(&lo.to_le_bytes());
+    bytes[8..].copy_from_slice(&hi.to_le_bytes());
+
+    uuid::Builder::from_custom_bytes(bytes).into_uuid()
+}